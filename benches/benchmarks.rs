@@ -1,439 +1,228 @@
-use chrono::prelude::*;
 use criterion::{criterion_group, criterion_main, Criterion};
 use ethers::{
-    providers::{Http, Middleware, Provider, Ws},
-    types::{
-        transaction::eip2930::AccessList, BlockNumber, Bytes, Eip1559TransactionRequest,
-        NameOrAddress, H160, U256,
-    },
+    abi::{encode, Token},
+    types::{Address, Log, H160, H256, U256, U64},
+    utils::keccak256,
 };
-use std::{path::Path, str::FromStr, sync::Arc, time::Instant};
+use ethers_providers::Provider;
+use serde_json::json;
+use std::{collections::HashMap, sync::Arc};
 use tokio::runtime::Runtime;
-use tokio::sync::broadcast::{self, Sender};
-use tokio::task::JoinSet;
 
 use rust::bundler::{Bundler, Flashloan};
-use rust::constants::{Env, ZERO_ADDRESS};
-use rust::multi::{batch_get_uniswap_v2_reserves, get_uniswap_v2_reserves};
-use rust::paths::generate_triangular_paths;
-use rust::pools::load_all_pools_from_v2;
-use rust::streams::{stream_new_blocks, stream_pending_transactions, Event};
-use rust::utils::{calculate_next_block_base_fee, get_touched_pool_reserves};
-
-pub async fn logging_event_handler(_: Arc<Provider<Ws>>, event_sender: Sender<Event>) {
-    let benchmark_file = Path::new("benches/.benchmark.csv");
-    let mut writer = csv::Writer::from_path(benchmark_file).unwrap();
-
-    let mut event_receiver = event_sender.subscribe();
+use rust::chains::ChainProfile;
+use rust::engine::Strategy;
+use rust::mock::{mock_env, serve_fixtures, FixtureTransport, Fixtures};
+use rust::paths::{generate_triangular_paths, ArbPath};
+use rust::pools::{DexVariant, Pool};
+use rust::strategy::TriangularStrategy;
+use rust::streams::{Event, NewBlock};
+use rust::utils::{get_touched_pool_reserves, Reserve};
+
+/*
+Benchmarking tasks that really matter in MEV bots is the critical part here.
+
+全部离线: 池子和储备量是生成的, RPC 由 FixtureTransport / serve_fixtures 回放, 不需要节点和私钥
+结果只反映本地计算 (和本机 HTTP 往返), 节点的延迟要在实盘里看
+*/
+
+const TOKENS: u64 = 200;
+
+fn token(i: u64) -> H160 {
+    H160::from_low_u64_be(0x1000 + i)
+}
 
-    loop {
-        match event_receiver.recv().await {
-            Ok(event) => match event {
-                Event::Block(_) => {}
-                Event::PendingTx(tx) => {
-                    let now = Local::now().timestamp_micros();
-                    writer.serialize((tx.hash, now)).unwrap();
-                }
-                Event::Log(_) => {}
-            },
-            Err(_) => {}
-        }
+// 按地址排好 token0 / token1
+fn pool(address: u64, a: (H160, u8), b: (H160, u8)) -> Pool {
+    let (t0, t1) = if a.0 < b.0 { (a, b) } else { (b, a) };
+    Pool {
+        address: H160::from_low_u64_be(address),
+        version: DexVariant::UniswapV2,
+        token0: t0.0,
+        token1: t1.0,
+        decimals0: t0.1,
+        decimals1: t1.1,
+        fee: 300,
+        router: ChainProfile::ethereum().dexes[0].router,
     }
 }
 
-pub async fn touched_pools_event_handler(provider: Arc<Provider<Ws>>, event_sender: Sender<Event>) {
-    let mut event_receiver = event_sender.subscribe();
-
-    loop {
-        match event_receiver.recv().await {
-            Ok(event) => match event {
-                Event::Block(block) => {
-                    let s = Instant::now();
-                    match get_touched_pool_reserves(provider.clone(), block.block_number).await {
-                        Ok(reserves) => {
-                            let took = s.elapsed().as_millis();
-                            let now = Instant::now();
-                            println!(
-                                "[{:?}] Block #{:?} {:?} pools touched | Took: {:?} ms",
-                                now,
-                                block.block_number,
-                                reserves.len(),
-                                took
-                            );
-                        }
-                        Err(_) => {}
-                    }
-                }
-                Event::PendingTx(_) => {}
-                Event::Log(_) => {}
-            },
-            Err(_) => {}
+// 储备量按代币给, 放到 reserve0 / reserve1
+fn reserve(pool: &Pool, token: H160, amount: U256, other: U256) -> Reserve {
+    if pool.token0 == token {
+        Reserve {
+            reserve0: amount,
+            reserve1: other,
+        }
+    } else {
+        Reserve {
+            reserve0: other,
+            reserve1: amount,
         }
     }
 }
 
-pub async fn full_course_event_handler(provider: Arc<Provider<Ws>>, event_sender: Sender<Event>) {
-    // pass
+// USDC/WETH 一个池子, 每个代币各有一个 USDC 池和 WETH 池
+// 价格: 1 WETH = 2000 USDC, 1 TKN = 2 USDC; mispriced 时每 10 个代币里有一个在 USDC 池里是 2.2
+struct Market {
+    usdc: H160,
+    pools: Vec<Pool>,
+    reserves: HashMap<H160, Reserve>,
+    sync_logs: Vec<Log>,
 }
 
-pub fn benchmark_function(_: &mut Criterion) {
-    /*
-    Benchmarking tasks that really matter in MEV bots is the critical part here.
-    The first two tasks are to illustrate how benchmarks will be done.
-
-    All benchmarks are done using a local full node running on another machine.
-    - Running on the same machine will make the async tasks here go faster.
-    - Using node services like Infura/Alchemy will make this go considerably slower.
-    */
-    dotenv::dotenv().ok();
-    let env = Env::new();
-
-    println!("Starting benchmark");
-
-    // 1. Create HTTP provider
-    let s = Instant::now();
-    let client = Provider::<Http>::try_from(env.https_url.clone()).unwrap();
-    let client = Arc::new(client);
-    let took = s.elapsed().as_micros();
-    println!("1. HTTP provider created | Took: {:?} microsec", took);
-
-    // runtime for async tasks
-    let rt = Runtime::new().unwrap();
-
-    // 2: Get block info
-    let mut runs = 10;
-
-    // Testing the call synchronously: one by one
-    loop {
-        let task = async {
-            let s = Instant::now();
-            let block = client.clone().get_block(BlockNumber::Latest).await.unwrap();
-            let took = s.elapsed().as_millis();
-            println!(
-                "2. New block: #{:?} | Took: {:?} ms",
-                block.unwrap().number.unwrap(),
-                took
-            );
-        };
-        rt.block_on(task);
-
-        runs -= 1;
-        if runs == 0 {
-            break;
-        }
-    }
-
-    // 3. Retrieving cached pools data
-    let task = async {
-        let factory_addresses = vec!["0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac"];
-        let factory_blocks = vec![10794229u64];
-
-        let s = Instant::now();
-        let pools = load_all_pools_from_v2(env.wss_url.clone(), factory_addresses, factory_blocks)
-            .await
-            .unwrap();
-        let took = s.elapsed().as_millis();
-        println!(
-            "3. Cached {:?} pools data | Took: {:?} ms",
-            pools.len(),
-            took
+fn market() -> Market {
+    let ethereum = ChainProfile::ethereum();
+    let usdc = ethereum.stables[0].address;
+    let weth = ethereum.wrapped_native.address;
+    let e6 = U256::exp10(6);
+    let e18 = U256::exp10(18);
+
+    let mut pools = vec![pool(0xa000, (usdc, 6), (weth, 18))];
+    let mut reserves = HashMap::from([(
+        pools[0].address,
+        reserve(&pools[0], usdc, e6 * 2_000_000, e18 * 1_000),
+    )]);
+    let mut sync_logs = Vec::new();
+    for i in 0..TOKENS {
+        let tkn = token(i);
+        let usdc_pool = pool(0xb000 + i, (usdc, 6), (tkn, 18));
+        let weth_pool = pool(0xc000 + i, (weth, 18), (tkn, 18));
+        reserves.insert(
+            usdc_pool.address,
+            reserve(&usdc_pool, usdc, e6 * 2_000_000, e18 * 1_000_000),
         );
-    };
-    rt.block_on(task);
-
-    // 4. Generate triangular arbitrage paths
-    let task = async {
-        let factory_addresses = vec!["0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac"];
-        let factory_blocks = vec![10794229u64];
-        let pools = load_all_pools_from_v2(env.wss_url.clone(), factory_addresses, factory_blocks)
-            .await
-            .unwrap();
-        let usdc_address = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap();
-
-        let s = Instant::now();
-        let paths = generate_triangular_paths(&pools, usdc_address);
-        let took = s.elapsed().as_millis();
-        println!(
-            "4. Generated {:?} 3-hop paths | Took: {:?} ms",
-            paths.len(),
-            took
+        reserves.insert(
+            weth_pool.address,
+            reserve(&weth_pool, weth, e18 * 1_000, e18 * 1_000_000),
         );
-    };
-    rt.block_on(task);
-
-    // 5. Multicall test: calling 250 requests using multicall
-    // This is used quite often in real bots.
-
-    // Single multicall
-    let task = async {
-        let factory_addresses = vec!["0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac"];
-        let factory_blocks = vec![10794229u64];
-        let pools = load_all_pools_from_v2(env.wss_url.clone(), factory_addresses, factory_blocks)
-            .await
-            .unwrap();
-
-        let s = Instant::now();
-        let reserves = get_uniswap_v2_reserves(env.https_url.clone(), pools[0..250].to_vec())
-            .await
-            .unwrap();
-        let took = s.elapsed().as_millis();
-        println!(
-            "5. Multicall result for {:?} | Took: {:?} ms",
-            reserves.len(),
-            took
-        );
-    };
-    rt.block_on(task);
-
-    // Batch multicall (thousands of requests asynchronously)
-    let task = async {
-        let factory_addresses = vec!["0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac"];
-        let factory_blocks = vec![10794229u64];
-        let pools = load_all_pools_from_v2(env.wss_url.clone(), factory_addresses, factory_blocks)
-            .await
-            .unwrap();
-
-        let s = Instant::now();
-        let reserves = batch_get_uniswap_v2_reserves(env.https_url.clone(), pools).await;
-        let took = s.elapsed().as_millis();
-        println!(
-            "5. Bulk multicall result for {:?} | Took: {:?} ms",
-            reserves.len(),
-            took
-        );
-    };
-    rt.block_on(task);
-
-    /*
-    6. Going to start running async streams and record the data on csv files
-       to see how long it takes to receive the data on average.
-
-       There may be better ways to pull this off, but the best use case benchmarks are those
-       that benchmark the exact same setup that people use.
-
-       Most people running MEV bots using Rust will use ethers-rs Provider<Ws> to stream
-       real-time data. And that is what I'm testing, without having to look under the hood.
-    */
-    // let task = async {
-    //     let ws = Ws::connect(env.wss_url.clone()).await.unwrap();
-    //     let provider = Arc::new(Provider::new(ws));
-
-    //     let (event_sender, _): (Sender<Event>, _) = broadcast::channel(512);
-
-    //     let mut set = JoinSet::new();
-
-    //     // try running the stream for n seconds
-    //     set.spawn(tokio::time::timeout(
-    //         std::time::Duration::from_secs(180),
-    //         stream_pending_transactions(provider.clone(), event_sender.clone()),
-    //     ));
-
-    //     set.spawn(tokio::time::timeout(
-    //         std::time::Duration::from_secs(180),
-    //         logging_event_handler(provider.clone(), event_sender.clone()),
-    //     ));
-
-    //     println!("6. Logging receive time for pending transaction streams. Wait 180 seconds...");
-    //     while let Some(res) = set.join_next().await {
-    //         println!("Closed: {:?}", res);
-    //     }
-    // };
-    // rt.block_on(task);
-
-    // 7. Retrieving logs from a newly created block
-    // let task = async {
-    //     let ws = Ws::connect(env.wss_url.clone()).await.unwrap();
-    //     let provider = Arc::new(Provider::new(ws));
-
-    //     let (event_sender, _): (Sender<Event>, _) = broadcast::channel(512);
-
-    //     let mut set = JoinSet::new();
-
-    //     // try running the stream for n seconds
-    //     set.spawn(tokio::time::timeout(
-    //         std::time::Duration::from_secs(60 * 5),
-    //         stream_new_blocks(provider.clone(), event_sender.clone()),
-    //     ));
-
-    //     set.spawn(tokio::time::timeout(
-    //         std::time::Duration::from_secs(60 * 5),
-    //         touched_pools_event_handler(provider.clone(), event_sender.clone()),
-    //     ));
-
-    //     // test for at least 5 minutes
-    //     println!("7. Starting touched pools with new blocks streams. Wait 300 seconds...");
-    //     while let Some(res) = set.join_next().await {
-    //         println!("Closed: {:?}", res);
-    //     }
-    // };
-    // rt.block_on(task);
-
-    // 8. 3-hop path simulation
-    let task = async {
-        let factory_addresses = vec!["0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac"];
-        let factory_blocks = vec![10794229u64];
-        let pools = load_all_pools_from_v2(env.wss_url.clone(), factory_addresses, factory_blocks)
-            .await
-            .unwrap();
-        let usdc_address = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap();
-
-        let paths = generate_triangular_paths(&pools, usdc_address);
-        let reserves = batch_get_uniswap_v2_reserves(env.https_url.clone(), pools).await;
-
-        let took = paths.iter().map(|path| {
-            let s = Instant::now();
-            let amount_in = U256::from(1);
-            match path.simulate_v2_path(amount_in, &reserves) {
-                Some(_) => {}
-                None => {}
-            };
-            return s.elapsed().as_micros() as i32;
-        });
-        let total_took = took.clone().into_iter().sum::<i32>();
-        println!(
-            "8. 3-hop path simulation took: {:?} microsecs in total ({:?} simulations)",
-            total_took,
-            took.len()
-        );
-    };
-    rt.block_on(task);
-
-    // 9. Creating flashbots bundles
-    let task = async {
-        let factory_addresses = vec!["0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac"];
-        let factory_blocks = vec![10794229u64];
-        let pools = load_all_pools_from_v2(env.wss_url.clone(), factory_addresses, factory_blocks)
-            .await
-            .unwrap();
-        let usdc_address = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap();
-        let usdc_decimals = 6;
-
-        let paths = generate_triangular_paths(&pools, usdc_address);
-
-        let unit = U256::from(10).pow(U256::from(usdc_decimals));
-        let gwei = U256::from(10).pow(U256::from(9));
+        if i % 10 == 0 {
+            let mispriced = reserve(&usdc_pool, usdc, e6 * 2_200_000, e18 * 1_000_000);
+            sync_logs.push(sync_log(&usdc_pool, &mispriced));
+        }
+        pools.push(usdc_pool);
+        pools.push(weth_pool);
+    }
+    Market {
+        usdc,
+        pools,
+        reserves,
+        sync_logs,
+    }
+}
 
-        let bundler = Bundler::new();
-        let block_number = bundler.provider.get_block_number().await.unwrap();
+fn sync_log(pool: &Pool, reserve: &Reserve) -> Log {
+    Log {
+        address: pool.address,
+        topics: vec![H256::from(keccak256("Sync(uint112,uint112)"))],
+        data: encode(&[Token::Uint(reserve.reserve0), Token::Uint(reserve.reserve1)]).into(),
+        transaction_index: Some(U64::from(1)),
+        ..Default::default()
+    }
+}
 
-        let s = Instant::now();
-        let router_address = "0xd9e1cE17f2641f24aE83637ab66a2cca9C378B9F";
-        let routers = vec![
-            H160::from_str(router_address).unwrap(),
-            H160::from_str(router_address).unwrap(),
-            H160::from_str(router_address).unwrap(),
-        ];
-        let path = &paths[0];
-        let path_params = path.to_path_params(&routers);
-        let amount_in = U256::from(1) * unit;
-        let flashloan = Flashloan::NotUsed;
-        let loan_from = *ZERO_ADDRESS;
-        let max_priority_fee_per_gas = U256::from(1) * gwei;
-        let max_fee_per_gas = U256::from(50) * gwei;
-        let order_tx = bundler
-            .order_tx(
-                path_params,
-                amount_in,
-                flashloan,
-                loan_from,
-                max_priority_fee_per_gas,
-                max_fee_per_gas,
-            )
-            .await
-            .unwrap();
-        let signed_tx = bundler.sign_tx(order_tx).await.unwrap();
-        let bundle = bundler.to_bundle(vec![signed_tx], block_number);
-        let took = s.elapsed().as_millis();
-        println!("9. Creating Flashbots bundle | Took: {:?} ms", took);
-        println!("{:?}", bundle);
-    };
-    rt.block_on(task);
+fn new_block(block_number: u64) -> NewBlock {
+    NewBlock {
+        block_number: U64::from(block_number),
+        base_fee: U256::exp10(9),
+        next_base_fee: U256::exp10(9),
+    }
+}
 
-    // 10. Sending Flashbots bundles
-    // Going to test out a real transaction with a wallet that has some ETH: Sending ETH to myself
-    let task = async {
-        let mut time_took = Vec::new();
+// eth_chainId / nonce / 每个区块的 Sync 日志都从 fixture 返回
+async fn mock_bundler(fixtures: &Fixtures) -> Bundler {
+    fixtures.push("eth_chainId", json!("0x1"));
+    fixtures.push("eth_getTransactionCount", json!("0x0"));
+    let url = serve_fixtures(fixtures.clone()).await.unwrap();
+    Bundler::from_env(mock_env(&url).unwrap()).unwrap()
+}
 
-        for n in 0..10 {
-            let bundler = Bundler::new();
-            let block = bundler
-                .provider
-                .get_block(BlockNumber::Latest)
-                .await
+pub fn benchmark_function(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let market = market();
+
+    // 1. Generate triangular arbitrage paths
+    c.bench_function("generate_triangular_paths", |b| {
+        b.iter(|| generate_triangular_paths(&market.pools, market.usdc))
+    });
+    let paths: Vec<ArbPath> = generate_triangular_paths(&market.pools, market.usdc);
+
+    // 2. 3-hop path simulation
+    c.bench_function("simulate_v2_paths", |b| {
+        b.iter(|| {
+            paths
+                .iter()
+                .filter_map(|path| path.simulate_v2_path(U256::from(1000), &market.reserves))
+                .count()
+        })
+    });
+
+    // 3. Decoding touched pools from Sync logs (eth_getLogs)
+    let fixtures = Fixtures::default();
+    fixtures.push("eth_getLogs", json!(market.sync_logs));
+    let provider = Arc::new(Provider::new(FixtureTransport::new(fixtures)));
+    c.bench_function("get_touched_pool_reserves", |b| {
+        b.iter(|| {
+            rt.block_on(get_touched_pool_reserves(provider.clone(), U64::from(100)))
                 .unwrap()
-                .unwrap();
-            let next_base_fee = U256::from(calculate_next_block_base_fee(
-                block.gas_used,
-                block.gas_limit,
-                block.base_fee_per_gas.unwrap_or_default(),
-            ));
-            let max_priority_fee_per_gas = U256::from(1);
-            let max_fee_per_gas = next_base_fee + max_priority_fee_per_gas;
-
-            let _s = Instant::now();
-            let s = Instant::now();
-            let common = bundler._common_fields().await.unwrap();
-            let to = NameOrAddress::Address(common.0);
-            let amount_in = U256::from(1) * U256::from(10).pow(U256::from(15)); // 0.001
-            let tx = Eip1559TransactionRequest {
-                to: Some(to),
-                from: Some(common.0),
-                data: Some(Bytes(bytes::Bytes::new())),
-                value: Some(amount_in),
-                chain_id: Some(common.2),
-                max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
-                max_fee_per_gas: Some(max_fee_per_gas),
-                gas: Some(U256::from(30000)),
-                nonce: Some(common.1),
-                access_list: AccessList::default(),
-            };
-            let signed_tx = bundler.sign_tx(tx).await.unwrap();
-            let bundle = bundler.to_bundle(vec![signed_tx], block.number.unwrap());
-            let took = s.elapsed().as_millis();
-            println!("- Creating bundle took: {:?} ms", took);
-
-            let s = Instant::now();
-            let simulated = bundler
-                .flashbots
-                .inner()
-                .simulate_bundle(&bundle)
-                .await
-                .unwrap();
-
-            for tx in &simulated.transactions {
-                if let Some(e) = &tx.error {
-                    println!("Simulation error: {e:?}");
+        })
+    });
+
+    // 4. Creating and signing a bundle
+    let fixtures = Fixtures::default();
+    let bundler = rt.block_on(mock_bundler(&fixtures));
+    let path = &paths[0];
+    c.bench_function("create_bundle", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let calldata = bundler
+                    .order_calldata(
+                        path.to_path_params(),
+                        U256::exp10(6),
+                        Flashloan::NotUsed,
+                        Address::zero(),
+                    )
+                    .unwrap();
+                let txs = bundler.order_txs(
+                    vec![(calldata, U256::from(600000))],
+                    U256::zero(),
+                    U256::exp10(9),
+                    U256::exp10(9) * 50,
+                );
+                let mut signed_txs = Vec::new();
+                for tx in txs {
+                    signed_txs.push(bundler.sign_tx(tx).await.unwrap());
                 }
-                if let Some(r) = &tx.revert {
-                    println!("Simulation revert: {r:?}");
-                }
-            }
-            let took = s.elapsed().as_millis();
-            println!("- Running simulation took: {:?} ms", took);
-
-            let s = Instant::now();
-            let pending_bundle = bundler
-                .flashbots
-                .inner()
-                .send_bundle(&bundle)
-                .await
-                .unwrap();
-
-            let took = s.elapsed().as_millis();
-            let total_took = _s.elapsed().as_millis();
-            println!(
-                "10. Sending Flashbots bundle ({:?}) | Took: {:?} ms",
-                pending_bundle.bundle_hash, took
-            );
-
-            time_took.push(total_took as u128);
-        }
-        println!("{:?}", time_took.iter().copied().sum::<u128>());
-    };
-    rt.block_on(task);
-
-    // 11. Full course testing
-    // ==> Receive new block / get touched pools / simulate paths / create flashbots bundle / send bundle
+                bundler.to_bundles(signed_txs, U64::from(100), 2)
+            })
+        })
+    });
+
+    // 5. Full course
+    // ==> Receive new block / get touched pools / simulate paths / bid / sign / build bundles
+    let fixtures = Fixtures::default();
+    fixtures.push("eth_getLogs", json!(market.sync_logs));
+    let bundler = Arc::new(rt.block_on(mock_bundler(&fixtures)));
+    let config = bundler.env.config.clone();
+    let provider = Arc::new(Provider::new(FixtureTransport::new(fixtures)));
+    let mut strategy = TriangularStrategy::new(&config, provider, bundler).unwrap();
+    strategy.use_flashloan = false;
+    strategy.use_access_list = false;
+    strategy.paths = paths.clone();
+    strategy.pools = market
+        .pools
+        .iter()
+        .map(|pool| (pool.address, pool.clone()))
+        .collect();
+    let mut block_number = 100;
+    c.bench_function("triangular_strategy_block", |b| {
+        b.iter(|| {
+            // 每次都从同样的储备量开始, 日志里的池子才算价格变了
+            strategy.reserves = market.reserves.clone();
+            block_number += 1;
+            rt.block_on(strategy.process_event(Event::Block(new_block(block_number))))
+        })
+    });
 }
 
 criterion_group!(benches, benchmark_function);
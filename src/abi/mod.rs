@@ -1,6 +1,34 @@
-use ethers_core::abi::Abi;
-use std::fs;
+use ethers::abi::Abi;
 
+// 编译期从 src/abi/*.json 生成合约绑定, 运行时不再读文件
+// 每个合约放在自己的模块里, 避免 Transfer / Approval 之类的事件重名
+pub mod erc20 {
+    ethers::prelude::abigen!(ERC20, "src/abi/ERC20.json");
+}
+
+pub mod weth {
+    ethers::prelude::abigen!(WETH, "src/abi/WETH.json");
+}
+
+pub mod uniswap_v2_factory {
+    ethers::prelude::abigen!(UniswapV2Factory, "src/abi/UniswapV2Factory.json");
+}
+
+pub mod uniswap_v2_pair {
+    ethers::prelude::abigen!(UniswapV2Pair, "src/abi/UniswapV2Pair.json");
+}
+
+pub mod v2_arb_bot {
+    ethers::prelude::abigen!(V2ArbBot, "src/abi/V2ArbBot.json");
+}
+
+pub use erc20::ERC20;
+pub use uniswap_v2_factory::UniswapV2Factory;
+pub use uniswap_v2_pair::UniswapV2Pair;
+pub use v2_arb_bot::V2ArbBot;
+pub use weth::WETH;
+
+// 保留原来的接口, 内容来自编译期的绑定
 pub struct ABI {
     pub erc20: Abi,
     pub weth: Abi,
@@ -11,17 +39,12 @@ pub struct ABI {
 
 impl ABI {
     pub fn new() -> Self {
-        let erc20_json = fs::read_to_string("src/abi/ERC20.json").unwrap();
-        let weth_json = fs::read_to_string("src/abi/WETH.json").unwrap();
-        let uniswap_v2_factory_json = fs::read_to_string("src/abi/UniswapV2Factory.json").unwrap();
-        let uniswap_v2_pair_json = fs::read_to_string("src/abi/UniswapV2Pair.json").unwrap();
-        let v2_arb_bot_json = fs::read_to_string("src/abi/V2ArbBot.json").unwrap();
         Self {
-            erc20: serde_json::from_str(&erc20_json).unwrap(),
-            weth: serde_json::from_str(&weth_json).unwrap(),
-            uniswap_v2_factory: serde_json::from_str(&uniswap_v2_factory_json).unwrap(),
-            uniswap_v2_pair: serde_json::from_str(&uniswap_v2_pair_json).unwrap(),
-            v2_arb_bot: serde_json::from_str(&v2_arb_bot_json).unwrap(),
+            erc20: erc20::ERC20_ABI.clone(),
+            weth: weth::WETH_ABI.clone(),
+            uniswap_v2_factory: uniswap_v2_factory::UNISWAPV2FACTORY_ABI.clone(),
+            uniswap_v2_pair: uniswap_v2_pair::UNISWAPV2PAIR_ABI.clone(),
+            v2_arb_bot: v2_arb_bot::V2ARBBOT_ABI.clone(),
         }
     }
 }
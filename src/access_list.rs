@@ -0,0 +1,280 @@
+use ethers::{
+    abi::{self, Token},
+    types::{
+        transaction::eip2718::TypedTransaction,
+        transaction::eip2930::{AccessList, AccessListItem},
+        Address, Eip1559TransactionRequest, H160, H256, U256,
+    },
+    utils::keccak256,
+};
+use ethers_providers::Middleware;
+use log::info;
+use std::{collections::HashMap, str::FromStr};
+
+use crate::pools::Pool;
+
+// EIP-2929 / EIP-2930 的 gas
+// 地址: 冷访问 2600, 放进 access list 要 2400, 之后按热访问 100 计 -> 每个地址省 100
+// 存储槽: 冷读 2100, 放进 access list 要 1900, 之后按热读 100 计 -> 每个槽省 100
+// 前提是列表里的每一项交易里都会碰到, 没碰到的项白白多花钱
+const COLD_ACCOUNT_ACCESS_COST: i64 = 2600;
+const COLD_SLOAD_COST: i64 = 2100;
+const WARM_ACCESS_COST: i64 = 100;
+const ACCESS_LIST_ADDRESS_COST: i64 = 2400;
+const ACCESS_LIST_STORAGE_KEY_COST: i64 = 1900;
+
+// UniswapV2Pair 的存储槽: 6 token0, 7 token1, 8 reserve0/reserve1/blockTimestampLast, 12 unlocked
+// 每次 swap 都会碰到; price0/1CumulativeLast (9, 10) 同一个区块里只在第一次 swap 时更新, 不放进来
+const PAIR_SLOTS: [u64; 4] = [6, 7, 8, 12];
+
+// 根据路径推出 access list: 池子的存储槽和代币的余额槽
+pub struct AccessListBuilder {
+    // 代币 -> balanceOf mapping 所在的槽, 不知道的代币不放进列表
+    pub balance_slots: HashMap<H160, u64>,
+}
+
+impl AccessListBuilder {
+    pub fn new(balance_slots: HashMap<H160, u64>) -> Self {
+        Self { balance_slots }
+    }
+
+    // 只知道主网代币的余额槽, 其他链靠 eth_createAccessList
+    pub fn for_chain(chain_id: u64) -> Self {
+        match chain_id {
+            1 => Self::mainnet(),
+            _ => Self::new(HashMap::new()),
+        }
+    }
+
+    pub fn mainnet() -> Self {
+        let address = |addr: &str| H160::from_str(addr).unwrap();
+        // USDC 是代理合约, 实现合约也会被访问, 这里只管余额槽
+        Self::new(HashMap::from([
+            (address("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"), 9), // USDC
+            (address("0xdAC17F958D2ee523a2206206994597C13D831ec7"), 2), // USDT
+            (address("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"), 3), // WETH
+            (address("0x6B175474E89094C44Da98b810eCe1fC6f2e6A10C"), 2), // DAI
+            (address("0x2260FAC5E5542a773Aa0A9C8fBF3B3A74D2a9E2a"), 0), // WBTC
+        ]))
+    }
+
+    // balanceOf[holder] 的存储位置: keccak256(abi.encode(holder, slot))
+    pub fn balance_slot(holder: Address, slot: u64) -> H256 {
+        let encoded = abi::encode(&[Token::Address(holder), Token::Uint(U256::from(slot))]);
+        H256::from(keccak256(encoded))
+    }
+
+    // pools 按路径顺序
+    // 交易的 to (bot 合约) 本身就是热的; bot 的余额槽取决于合约怎么转账, 交给 eth_createAccessList
+    pub fn build(&self, pools: &[Pool]) -> AccessList {
+        let mut items: Vec<AccessListItem> = Vec::new();
+        let mut add = |address: Address, key: Option<H256>| {
+            let item = match items.iter_mut().find(|item| item.address == address) {
+                Some(item) => item,
+                None => {
+                    items.push(AccessListItem {
+                        address,
+                        storage_keys: Vec::new(),
+                    });
+                    items.last_mut().unwrap()
+                }
+            };
+            if let Some(key) = key {
+                if !item.storage_keys.contains(&key) {
+                    item.storage_keys.push(key);
+                }
+            }
+        };
+        for pool in pools {
+            for slot in PAIR_SLOTS {
+                add(pool.address, Some(H256::from_low_u64_be(slot)));
+            }
+            for token in [pool.token0, pool.token1] {
+                let slot = match self.balance_slots.get(&token) {
+                    Some(slot) => *slot,
+                    None => continue,
+                };
+                // swap 最后会读池子自己的两个余额
+                add(token, Some(Self::balance_slot(pool.address, slot)));
+            }
+        }
+        AccessList(items)
+    }
+
+    // 假设列表里的每一项都会被访问时省下的 gas
+    pub fn estimated_saving(access_list: &AccessList) -> i64 {
+        access_list.0.iter().fold(0, |acc, item| {
+            let keys = item.storage_keys.len() as i64;
+            acc + (COLD_ACCOUNT_ACCESS_COST - WARM_ACCESS_COST - ACCESS_LIST_ADDRESS_COST)
+                + keys * (COLD_SLOAD_COST - WARM_ACCESS_COST - ACCESS_LIST_STORAGE_KEY_COST)
+        })
+    }
+
+    // 用 eth_estimateGas 比较三种情况: 不加、加 candidate、加 eth_createAccessList 给的列表
+    // 只有比不加更省的时候才附上 access list; 估算失败 (例如单独执行会 revert) 时原样返回
+    pub async fn optimize<M: Middleware>(
+        &self,
+        provider: &M,
+        tx: Eip1559TransactionRequest,
+        candidate: AccessList,
+    ) -> Eip1559TransactionRequest {
+        let estimate = |access_list: AccessList| {
+            let typed = TypedTransaction::Eip1559(tx.clone().access_list(access_list));
+            async move { provider.estimate_gas(&typed, None).await }
+        };
+        let base_gas = match estimate(AccessList::default()).await {
+            Ok(gas) => gas,
+            Err(e) => {
+                info!("Error from estimate_gas: {:?}", e);
+                return tx;
+            }
+        };
+
+        let mut access_lists = vec![candidate];
+        let typed = TypedTransaction::Eip1559(tx.clone());
+        match provider.create_access_list(&typed, None).await {
+            Ok(result) => access_lists.push(result.access_list),
+            Err(e) => info!("Error from eth_createAccessList: {:?}", e),
+        }
+        let mut best: Option<(U256, AccessList)> = None;
+        for access_list in access_lists {
+            if access_list.0.is_empty() {
+                continue;
+            }
+            if let Ok(gas) = estimate(access_list.clone()).await {
+                if best.as_ref().map_or(true, |(best_gas, _)| gas < *best_gas) {
+                    best = Some((gas, access_list));
+                }
+            }
+        }
+
+        match best {
+            Some((gas, access_list)) if gas < base_gas => {
+                info!("Access list saves {:?} gas", base_gas - gas);
+                tx.access_list(access_list)
+            }
+            _ => tx,
+        }
+    }
+}
+
+#[cfg(test)]
+mod access_list_tests {
+    use super::*;
+    use crate::mock::{FixtureTransport, Fixtures};
+    use crate::pools::DexVariant;
+    use ethers_providers::Provider;
+    use serde_json::json;
+
+    fn pool(address: &str, token0: &str, token1: &str) -> Pool {
+        Pool {
+            address: H160::from_str(address).unwrap(),
+            version: DexVariant::UniswapV2,
+            token0: H160::from_str(token0).unwrap(),
+            token1: H160::from_str(token1).unwrap(),
+            decimals0: 6,
+            decimals1: 18,
+            fee: 300,
+            router: H160::zero(),
+        }
+    }
+
+    #[test]
+    fn build_merges_shared_tokens() {
+        let usdc = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+        let weth = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+        let unknown = "0x0000000000000000000000000000000000000001";
+        let pools = vec![
+            pool("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc", usdc, weth),
+            pool("0x397FF1542f962076d0BFE58eA045FfA2d347ACa0", usdc, weth),
+            pool("0x0000000000000000000000000000000000000002", unknown, weth),
+        ];
+        let access_list = AccessListBuilder::mainnet().build(&pools);
+        // 3 个池子, USDC 和 WETH; 不知道余额槽的代币不放进来
+        assert_eq!(access_list.0.len(), 5);
+        let weth_item = access_list
+            .0
+            .iter()
+            .find(|item| item.address == H160::from_str(weth).unwrap())
+            .unwrap();
+        assert_eq!(weth_item.storage_keys.len(), 3);
+        assert_eq!(
+            weth_item.storage_keys[0],
+            AccessListBuilder::balance_slot(pools[0].address, 3)
+        );
+        // 5 个地址, 3 * 4 个池子的槽 + 2 个 USDC 余额 + 3 个 WETH 余额
+        assert_eq!(
+            AccessListBuilder::estimated_saving(&access_list),
+            100 * (5 + 12 + 2 + 3)
+        );
+    }
+
+    fn order_tx() -> Eip1559TransactionRequest {
+        Eip1559TransactionRequest::new()
+            .to(H160::from_low_u64_be(0xb07))
+            .data(vec![0x01])
+    }
+
+    async fn optimize(fixtures: &Fixtures) -> Eip1559TransactionRequest {
+        let usdc = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+        let weth = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+        let pools = vec![pool(
+            "0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc",
+            usdc,
+            weth,
+        )];
+        let builder = AccessListBuilder::mainnet();
+        let provider = Provider::new(FixtureTransport::new(fixtures.clone()));
+        builder
+            .optimize(&provider, order_tx(), builder.build(&pools))
+            .await
+    }
+
+    #[tokio::test]
+    async fn optimize_picks_the_cheapest_saving_list() {
+        let created = json!([{
+            "address": "0x0000000000000000000000000000000000000b07",
+            "storageKeys": []
+        }]);
+        let fixtures = Fixtures::default();
+        fixtures.push(
+            "eth_createAccessList",
+            json!({"accessList": created, "gasUsed": "0x1d4c0"}),
+        );
+        // 不加 / candidate / eth_createAccessList 的列表
+        fixtures.push("eth_estimateGas", json!("0x1d4c0"));
+        fixtures.push("eth_estimateGas", json!("0x1d000"));
+        fixtures.push("eth_estimateGas", json!("0x1d200"));
+        let tx = optimize(&fixtures).await;
+        // candidate 最省: 池子和两个代币
+        assert_eq!(tx.access_list.0.len(), 3);
+        assert_eq!(fixtures.requests("eth_estimateGas").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn optimize_keeps_tx_without_saving() {
+        let fixtures = Fixtures::default();
+        fixtures.push(
+            "eth_createAccessList",
+            json!({"accessList": [], "gasUsed": "0x1d4c0"}),
+        );
+        fixtures.push("eth_estimateGas", json!("0x1d4c0"));
+        fixtures.push("eth_estimateGas", json!("0x1d600"));
+        let tx = optimize(&fixtures).await;
+        assert!(tx.access_list.0.is_empty());
+        // 空列表不估算
+        assert_eq!(fixtures.requests("eth_estimateGas").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn optimize_keeps_tx_when_estimate_fails() {
+        let fixtures = Fixtures::default();
+        fixtures.push(
+            "eth_estimateGas",
+            json!({"error": {"code": 3, "message": "execution reverted"}}),
+        );
+        let tx = optimize(&fixtures).await;
+        assert!(tx.access_list.0.is_empty());
+        assert!(fixtures.requests("eth_createAccessList").is_empty());
+    }
+}
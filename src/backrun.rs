@@ -0,0 +1,715 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ethers::{
+    types::{Bytes, Transaction, TxHash, H160, U256},
+    utils::keccak256,
+};
+use ethers_providers::{Provider, Ws};
+use log::info;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::bidding::{BidMode, Bidder};
+use crate::bundler::Bundler;
+use crate::config::{Config, RiskLimits};
+use crate::decoder::{decode_v2_swap, PendingSwap, SwapAmount};
+use crate::engine::{Action, ExecutionReport, Strategy};
+use crate::flashloan::{FlashloanQuote, FlashloanSelector};
+use crate::gas::GasModel;
+use crate::mev_share::{apply_hint_logs, Hint, MevShareBundle};
+use crate::paper::PaperTrade;
+use crate::paths::ArbPath;
+use crate::pools::Pool;
+use crate::pricing::PriceService;
+use crate::selector::Opportunity;
+use crate::simulator::UniswapV2Simulator;
+use crate::strategy::load_triangular_paths;
+use crate::streams::{Event, NewBlock};
+use crate::utils::{batch_get_uniswap_v2_reserves, get_touched_pool_reserves, Reserve};
+
+// 按 (router, 代币对) 索引池子, key 里的两个代币按地址排序
+// 同一个代币对在每个 DEX 上各有一个池子, 受害者的交易只走它调用的 router 对应的那个
+pub fn index_pools_by_tokens(pools: &HashMap<H160, Pool>) -> HashMap<(H160, H160, H160), Pool> {
+    let mut index = HashMap::new();
+    for pool in pools.values() {
+        index.insert(
+            pool_key(pool.router, pool.token0, pool.token1),
+            pool.clone(),
+        );
+    }
+    index
+}
+
+fn pool_key(router: H160, token_a: H160, token_b: H160) -> (H160, H160, H160) {
+    if token_a < token_b {
+        (router, token_a, token_b)
+    } else {
+        (router, token_b, token_a)
+    }
+}
+
+// 把受害者的交易作用在储备量副本上
+// 返回受影响的池子; 路径上有没追踪的池子, 或者交易本身会因为滑点 revert 时返回 None
+pub fn apply_pending_swap(
+    swap: &PendingSwap,
+    pool_index: &HashMap<(H160, H160, H160), Pool>,
+    reserves: &mut HashMap<H160, Reserve>,
+) -> Option<Vec<H160>> {
+    // 每一跳的池子和方向
+    let mut hops = Vec::new();
+    for i in 0..swap.path.len() - 1 {
+        let token_in = swap.path[i];
+        let token_out = swap.path[i + 1];
+        let pool = pool_index.get(&pool_key(swap.router, token_in, token_out))?;
+        let zero_for_one = pool.token0 == token_in;
+        hops.push((pool, zero_for_one));
+    }
+
+    // 先算出每一跳的金额: amounts[i] 是第 i 跳的输入, amounts[i + 1] 是输出
+    let get_in_out = |pool: &Pool, zero_for_one: bool, reserves: &HashMap<H160, Reserve>| {
+        let reserve = reserves.get(&pool.address)?;
+        if zero_for_one {
+            Some((reserve.reserve0, reserve.reserve1))
+        } else {
+            Some((reserve.reserve1, reserve.reserve0))
+        }
+    };
+    let mut amounts = vec![U256::zero(); swap.path.len()];
+    match swap.amount {
+        SwapAmount::ExactIn {
+            amount_in,
+            amount_out_min,
+        } => {
+            amounts[0] = amount_in;
+            for (i, (pool, zero_for_one)) in hops.iter().enumerate() {
+                let (reserve_in, reserve_out) = get_in_out(pool, *zero_for_one, reserves)?;
+                amounts[i + 1] = UniswapV2Simulator::get_amount_out(
+                    amounts[i],
+                    reserve_in,
+                    reserve_out,
+                    U256::from(pool.fee),
+                )?;
+            }
+            if amounts[hops.len()] < amount_out_min {
+                return None;
+            }
+        }
+        SwapAmount::ExactOut {
+            amount_out,
+            amount_in_max,
+        } => {
+            amounts[hops.len()] = amount_out;
+            for (i, (pool, zero_for_one)) in hops.iter().enumerate().rev() {
+                let (reserve_in, reserve_out) = get_in_out(pool, *zero_for_one, reserves)?;
+                amounts[i] = UniswapV2Simulator::get_amount_in(
+                    amounts[i + 1],
+                    reserve_in,
+                    reserve_out,
+                    U256::from(pool.fee),
+                )?;
+            }
+            if amounts[0] > amount_in_max {
+                return None;
+            }
+        }
+    }
+
+    // 更新储备量: 输入的一侧增加, 输出的一侧减少
+    let mut touched = Vec::new();
+    for (i, (pool, zero_for_one)) in hops.iter().enumerate() {
+        let reserve = reserves.get_mut(&pool.address)?;
+        if *zero_for_one {
+            reserve.reserve0 = reserve.reserve0 + amounts[i];
+            reserve.reserve1 = reserve.reserve1.checked_sub(amounts[i + 1])?;
+        } else {
+            reserve.reserve1 = reserve.reserve1 + amounts[i];
+            reserve.reserve0 = reserve.reserve0.checked_sub(amounts[i + 1])?;
+        }
+        touched.push(pool.address);
+    }
+    Some(touched)
+}
+
+// 尾随 pending 交易: 先把受害者的交易作用在储备量上, 再找经过这些池子的套利
+pub struct BackrunStrategy {
+    pub provider: Arc<Provider<Ws>>,
+    pub config: Config,
+    pub bundler: Arc<Bundler>,
+    pub routers: Vec<H160>,
+    pub usdc_address: H160,
+    pub usdc_decimals: i32,
+    pub paths: Vec<ArbPath>,
+    pub pools: HashMap<H160, Pool>,
+    pub pool_index: HashMap<(H160, H160, H160), Pool>,
+    pub reserves: HashMap<H160, Reserve>,
+    pub pricing: PriceService,
+    pub gas_model: GasModel,
+    pub bidder: Bidder,
+    pub flashloans: FlashloanSelector,
+    pub use_flashloan: bool,
+    // 套利交易附加按路径推出的 access list, 不调 RPC
+    // 受害者的交易排在前面, 套利单独估算通常会 revert, eth_estimateGas 给不出结果
+    pub use_access_list: bool,
+    pub min_profit: i128,
+    pub risk: RiskLimits,
+    // 已提交还没有结果的套利交易
+    pub pending_orders: HashSet<TxHash>,
+    // 打包时需要知道当前区块和下一个区块的 base fee
+    pub latest_block: NewBlock,
+    // 模拟盘, 不提交
+    pub dry_run: bool,
+}
+
+impl BackrunStrategy {
+    pub fn new(
+        config: &Config,
+        provider: Arc<Provider<Ws>>,
+        bundler: Arc<Bundler>,
+    ) -> Result<Self> {
+        let config = config.clone();
+        let usdc_address = config.base_token.address;
+        let native_token = config.chain.wrapped_native;
+        if native_token.is_zero() {
+            return Err(anyhow!("chain.wrapped_native is not set"));
+        }
+        Ok(Self {
+            provider,
+            config: config.clone(),
+            bundler,
+            routers: config.routers(),
+            usdc_address,
+            usdc_decimals: config.base_token.decimals as i32,
+            paths: Vec::new(),
+            pools: HashMap::new(),
+            pool_index: HashMap::new(),
+            reserves: HashMap::new(),
+            pricing: PriceService::new(native_token, usdc_address),
+            gas_model: GasModel::default(),
+            bidder: Bidder::new(config.thresholds.bid_policy()),
+            flashloans: FlashloanSelector::for_chain(config.chain.chain_id),
+            use_flashloan: config.thresholds.use_flashloan,
+            use_access_list: config.thresholds.use_access_list,
+            min_profit: config.thresholds.min_profit as i128,
+            risk: config.risk,
+            pending_orders: HashSet::new(),
+            latest_block: NewBlock::default(),
+            dry_run: config.dry_run.enabled,
+        })
+    }
+
+    async fn process_block(&mut self, block: NewBlock) {
+        match get_touched_pool_reserves(self.provider.clone(), block.block_number).await {
+            Ok(touched_reserves) => {
+                for (address, reserve) in touched_reserves {
+                    if self.reserves.contains_key(&address) {
+                        self.reserves.insert(address, reserve);
+                    }
+                }
+            }
+            Err(e) => info!("Error from get_touched_pool_reserves: {:?}", e),
+        }
+        self.pricing.update_route(&self.pools, &self.reserves);
+        self.bundler.nonces.on_new_block(block.block_number).await;
+        self.latest_block = block;
+    }
+
+    async fn process_pending_tx(&mut self, tx: Transaction) -> Option<Action> {
+        if self.latest_block.block_number.is_zero() {
+            return None;
+        }
+        let swap = decode_v2_swap(&tx, &self.routers)?;
+        // 只复制受害者路径上的池子, 不拷贝整个 reserves
+        let mut local_reserves = HashMap::new();
+        for token in swap.path.windows(2) {
+            let key = pool_key(swap.router, token[0], token[1]);
+            if let Some(pool) = self.pool_index.get(&key) {
+                if let Some(reserve) = self.reserves.get(&pool.address) {
+                    local_reserves.insert(pool.address, reserve.clone());
+                }
+            }
+        }
+        let touched_pools = apply_pending_swap(&swap, &self.pool_index, &mut local_reserves)?;
+        let (opp, signed_txs) = self
+            .backrun_txs(tx.hash, &touched_pools, local_reserves)
+            .await?;
+        if self.dry_run {
+            let block = self.latest_block.block_number;
+            let mut trade = PaperTrade::new("backrun", block, block + 1, vec![opp], signed_txs);
+            trade.victim = Some(swap.tx.rlp());
+            return Some(Action::PaperTrade(trade));
+        }
+        let mut txs = vec![swap.tx.rlp()];
+        txs.extend(signed_txs);
+        let bundle = self.bundler.to_bundle(txs, self.latest_block.block_number);
+        Some(Action::SendBundle(bundle))
+    }
+
+    // MEV-Share 的 hint 只有部分信息: 从公开的日志推出用户交易之后的储备量
+    // 只有哈希的用户交易放在 bundle 最前面, 收益按 refund_percent 退给用户
+    async fn process_hint(&mut self, hint: Hint) -> Option<Action> {
+        if self.latest_block.block_number.is_zero() {
+            return None;
+        }
+        let mut local_reserves = HashMap::new();
+        for address in hint.touched_pools(&self.pools) {
+            if let Some(reserve) = self.reserves.get(&address) {
+                local_reserves.insert(address, reserve.clone());
+            }
+        }
+        if local_reserves.is_empty() {
+            return None;
+        }
+        let touched_pools = apply_hint_logs(&hint, &self.pools, &mut local_reserves)?;
+        let (opp, signed_txs) = self
+            .backrun_txs(hint.hash, &touched_pools, local_reserves)
+            .await?;
+        let block = self.latest_block.block_number;
+        let max_block = block + self.config.thresholds.target_blocks;
+        if self.dry_run {
+            let mut trade = PaperTrade::new("mev_share", block, max_block, vec![opp], signed_txs);
+            trade.hint = Some(hint.hash);
+            return Some(Action::PaperTrade(trade));
+        }
+        let bundle = MevShareBundle::backrun(
+            hint.hash,
+            signed_txs,
+            block + 1,
+            max_block,
+            self.config.mev_share.refund_percent,
+        );
+        Some(Action::SendMevShareBundle(bundle))
+    }
+
+    // 只复制会用到的池子: 套利路径、能借基础代币的 V2 池子和 gas 定价路线, 再盖上用户交易改过的
+    fn post_victim_reserves(
+        &self,
+        touched_paths: &[(usize, &ArbPath)],
+        local_reserves: HashMap<H160, Reserve>,
+    ) -> HashMap<H160, Reserve> {
+        let mut addresses: Vec<H160> = touched_paths
+            .iter()
+            .flat_map(|(_, path)| (0..path.nhop).map(|i| path._get_pool(i).address))
+            .collect();
+        if self.use_flashloan {
+            addresses.extend(
+                self.pools
+                    .values()
+                    .filter(|pool| {
+                        pool.token0 == self.usdc_address || pool.token1 == self.usdc_address
+                    })
+                    .map(|pool| pool.address),
+            );
+        }
+        if let Some(route) = &self.pricing.route {
+            addresses.extend(route.pools.iter().map(|pool| pool.address));
+        }
+        let mut reserves = HashMap::new();
+        for address in addresses {
+            if let Some(reserve) = self.reserves.get(&address) {
+                reserves.insert(address, reserve.clone());
+            }
+        }
+        reserves.extend(local_reserves);
+        reserves
+    }
+
+    // local_reserves 是用户交易改过的池子在交易之后的储备量, 找经过 touched_pools 的最优套利
+    // 返回选中的机会和签好名的套利交易 (coinbase 模式最后还有一笔小费)
+    async fn backrun_txs(
+        &mut self,
+        victim: TxHash,
+        touched_pools: &[H160],
+        local_reserves: HashMap<H160, Reserve>,
+    ) -> Option<(Opportunity, Vec<Bytes>)> {
+        // 经过受影响池子的套利路径
+        let touched_paths: Vec<(usize, &ArbPath)> = self
+            .paths
+            .iter()
+            .enumerate()
+            .filter(|(_, path)| touched_pools.iter().any(|pool| path.has_pool(pool)))
+            .collect();
+        // 用户交易之后的储备量, 套利、闪电贷来源和 gas 定价都按这个算
+        let local_reserves = self.post_victim_reserves(&touched_paths, local_reserves);
+
+        // 找利润最高的路径
+        let unit = U256::from(10).pow(U256::from(self.usdc_decimals));
+        let mut best: Option<(usize, &ArbPath, U256, i128, i128, FlashloanQuote)> = None;
+        for (path_idx, path) in touched_paths {
+            let opt = path.optimize_amount_in(U256::from(1000), 10, &local_reserves);
+            let loan = if self.use_flashloan {
+                let path_pools: Vec<H160> =
+                    (0..path.nhop).map(|i| path._get_pool(i).address).collect();
+                self.flashloans.select(
+                    self.usdc_address,
+                    opt.0 * unit,
+                    &path_pools,
+                    &self.pools,
+                    &local_reserves,
+                )
+            } else {
+                None
+            }
+            .unwrap_or_else(FlashloanQuote::not_used);
+            let estimated_gas_usage = self.gas_model.estimate(path, &loan.flashloan);
+            let gas_cost_in_usdc = match self.pricing.gas_cost_in_base(
+                self.latest_block.next_base_fee,
+                estimated_gas_usage,
+                &local_reserves,
+            ) {
+                Some(gas_cost) => gas_cost,
+                None => continue,
+            };
+            let excess_profit = (opt.1.as_u128() as i128)
+                - (gas_cost_in_usdc.as_u128() as i128)
+                - (loan.fee.as_u128() as i128);
+            if opt.0 > U256::from(self.risk.max_amount_in) {
+                continue;
+            }
+            if excess_profit > self.min_profit
+                && best
+                    .as_ref()
+                    .map_or(true, |(_, _, _, profit, _, _)| excess_profit > *profit)
+            {
+                let gas_cost = gas_cost_in_usdc.as_u128() as i128;
+                best = Some((path_idx, path, opt.0, excess_profit, gas_cost, loan));
+            }
+        }
+        let (path_idx, path, amount_in, excess_profit, gas_cost, loan) = best?;
+        let opp = Opportunity::new(
+            path_idx,
+            path,
+            amount_in,
+            excess_profit,
+            gas_cost,
+            loan.clone(),
+        );
+        info!(
+            "Backrun {:?}: excess profit {:?} (amount in {:?})",
+            victim, excess_profit, amount_in
+        );
+
+        // 按预期利润出价
+        let expected_profit = self
+            .pricing
+            .base_to_native(U256::from(excess_profit as u128), &local_reserves)?;
+        let bid = self.bidder.bid(
+            expected_profit,
+            self.latest_block.next_base_fee,
+            self.gas_model.estimate(path, &loan.flashloan),
+        )?;
+        if bid.max_fee_per_gas > self.risk.max_fee_per_gas() {
+            info!("Max fee per gas {:?} over risk limit", bid.max_fee_per_gas);
+            return None;
+        }
+
+        let path_params = path.to_path_params();
+        let calldata = match self.bundler.order_calldata(
+            path_params,
+            amount_in * unit,
+            loan.flashloan.clone(),
+            loan.loan_from,
+        ) {
+            Ok(calldata) => calldata,
+            Err(e) => {
+                info!("Backrun {:?}: error encoding order: {:?}", victim, e);
+                return None;
+            }
+        };
+        let tip_recipient = match self.bidder.policy.mode {
+            BidMode::CoinbaseTransfer { recipient } => Some(recipient),
+            BidMode::PriorityFee => None,
+        };
+        let nonce = match self
+            .bundler
+            .reserve_bundle_nonces(1 + tip_recipient.is_some() as u64)
+            .await
+        {
+            Ok(nonce) => nonce,
+            Err(e) => {
+                info!("Error reserving nonces: {:?}", e);
+                return None;
+            }
+        };
+        let order_txs = self.bundler.order_txs(
+            vec![(calldata, self.gas_model.gas_limit(path, &loan.flashloan))],
+            nonce,
+            bid.max_priority_fee_per_gas,
+            bid.max_fee_per_gas,
+        );
+        // 受害者的交易在前, 我们的套利交易在后, coinbase 模式最后再加一笔小费
+        let mut unsigned_txs = order_txs;
+        // 列表里只有池子每次 swap 都会读写的槽和池子的代币余额槽, 每一项都会被访问, 一定省 gas
+        // warm / cold 按交易算, 受害者先碰过这些池子也不影响
+        if self.use_access_list {
+            let access_list = self.bundler.access_lists.build(&path.pools());
+            if !access_list.0.is_empty() {
+                unsigned_txs[0].access_list = access_list;
+            }
+        }
+        if let Some(recipient) = tip_recipient {
+            unsigned_txs.push(self.bundler.coinbase_tip_tx(
+                recipient,
+                bid.coinbase_transfer,
+                nonce + 1,
+                bid.max_priority_fee_per_gas,
+                bid.max_fee_per_gas,
+            ));
+        }
+        let mut txs = Vec::new();
+        for (i, tx) in unsigned_txs.into_iter().enumerate() {
+            let signed_tx = match self.bundler.sign_tx(tx).await {
+                Ok(signed_tx) => signed_tx,
+                Err(e) => {
+                    info!("Error signing tx: {:?}", e);
+                    return None;
+                }
+            };
+            if i == 0 {
+                self.pending_orders
+                    .insert(TxHash::from(keccak256(&signed_tx)));
+            }
+            txs.push(signed_tx);
+        }
+        Some((opp, txs))
+    }
+
+    // 只统计自己提交的 bundle, 模拟阶段就失败的和模拟盘的不计入上链率
+    fn process_execution(&mut self, report: ExecutionReport) {
+        let mut ours = false;
+        for tx_hash in &report.tx_hashes {
+            ours |= self.pending_orders.remove(tx_hash);
+        }
+        if ours && !report.simulation_failed && !self.dry_run {
+            self.bidder.record_inclusion(report.landed());
+        }
+    }
+}
+
+#[async_trait]
+impl Strategy for BackrunStrategy {
+    async fn sync_state(&mut self) -> Result<()> {
+        let (paths, pools) = load_triangular_paths(
+            self.provider.clone(),
+            self.config.chain.chain_id,
+            self.usdc_address,
+            &self.config.dexes,
+            &self.config.risk.blacklist_tokens,
+        )
+        .await?;
+        let pools_vec: Vec<Pool> = pools.values().cloned().collect();
+        self.reserves = batch_get_uniswap_v2_reserves(self.provider.clone(), pools_vec).await?;
+        self.pool_index = index_pools_by_tokens(&pools);
+        self.pricing.update_route(&pools, &self.reserves);
+        info!("Backrun pool count: {:?}", pools.len());
+        self.paths = paths;
+        self.pools = pools;
+        self.flashloans
+            .refresh(self.provider.clone(), &[self.usdc_address])
+            .await;
+        Ok(())
+    }
+
+    async fn process_event(&mut self, event: Event) -> Vec<Action> {
+        match event {
+            Event::Block(block) => {
+                self.process_block(block).await;
+                Vec::new()
+            }
+            Event::PendingTx(tx) => self.process_pending_tx(tx).await.into_iter().collect(),
+            Event::Hint(hint) => self.process_hint(hint).await.into_iter().collect(),
+            Event::Log(_) => Vec::new(),
+            Event::Execution(report) => {
+                self.process_execution(report);
+                Vec::new()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod backrun_tests {
+    use super::*;
+    use crate::pools::DexVariant;
+
+    fn token(i: u64) -> H160 {
+        H160::from_low_u64_be(i)
+    }
+
+    fn pool(address: u64, token0: u64, token1: u64) -> Pool {
+        Pool {
+            address: H160::from_low_u64_be(address),
+            version: DexVariant::UniswapV2,
+            token0: token(token0),
+            token1: token(token1),
+            decimals0: 18,
+            decimals1: 18,
+            fee: 300,
+            router: H160::zero(),
+        }
+    }
+
+    // 代币 1 -> 2 -> 3, 每个池子 1000 : 1000
+    fn setup() -> (HashMap<(H160, H160, H160), Pool>, HashMap<H160, Reserve>) {
+        let pools: HashMap<H160, Pool> = [pool(10, 1, 2), pool(11, 2, 3)]
+            .into_iter()
+            .map(|pool| (pool.address, pool))
+            .collect();
+        let reserves = pools
+            .keys()
+            .map(|address| {
+                (
+                    *address,
+                    Reserve {
+                        reserve0: U256::exp10(21),
+                        reserve1: U256::exp10(21),
+                    },
+                )
+            })
+            .collect();
+        (index_pools_by_tokens(&pools), reserves)
+    }
+
+    fn swap(path: Vec<u64>, amount: SwapAmount) -> PendingSwap {
+        PendingSwap {
+            tx: Transaction::default(),
+            router: H160::zero(),
+            path: path.into_iter().map(token).collect(),
+            amount,
+        }
+    }
+
+    #[test]
+    fn exact_in_moves_reserves_along_path() {
+        let (pool_index, mut reserves) = setup();
+        let amount_in = U256::exp10(19);
+        let swap = swap(
+            vec![1, 2, 3],
+            SwapAmount::ExactIn {
+                amount_in,
+                amount_out_min: U256::zero(),
+            },
+        );
+        let touched = apply_pending_swap(&swap, &pool_index, &mut reserves).unwrap();
+        assert_eq!(
+            touched,
+            vec![H160::from_low_u64_be(10), H160::from_low_u64_be(11)]
+        );
+
+        let fee = U256::from(300);
+        let mid =
+            UniswapV2Simulator::get_amount_out(amount_in, U256::exp10(21), U256::exp10(21), fee)
+                .unwrap();
+        let first = &reserves[&H160::from_low_u64_be(10)];
+        assert_eq!(first.reserve0, U256::exp10(21) + amount_in);
+        assert_eq!(first.reserve1, U256::exp10(21) - mid);
+        let second = &reserves[&H160::from_low_u64_be(11)];
+        assert_eq!(second.reserve0, U256::exp10(21) + mid);
+        assert!(second.reserve1 < U256::exp10(21));
+    }
+
+    #[test]
+    fn exact_out_in_reverse_direction() {
+        let (pool_index, mut reserves) = setup();
+        let amount_out = U256::exp10(18);
+        let swap = swap(
+            vec![3, 2],
+            SwapAmount::ExactOut {
+                amount_out,
+                amount_in_max: U256::exp10(19),
+            },
+        );
+        apply_pending_swap(&swap, &pool_index, &mut reserves).unwrap();
+        // 代币 3 是池子 11 的 token1
+        let reserve = &reserves[&H160::from_low_u64_be(11)];
+        assert_eq!(reserve.reserve0, U256::exp10(21) - amount_out);
+        assert!(reserve.reserve1 > U256::exp10(21));
+        assert_eq!(
+            reserves[&H160::from_low_u64_be(10)].reserve0,
+            U256::exp10(21)
+        );
+    }
+
+    #[test]
+    fn reverting_or_untracked_swaps_are_skipped() {
+        let (pool_index, mut reserves) = setup();
+        // 滑点保护: 最少要换出 10, 实际不到
+        let too_tight = swap(
+            vec![1, 2],
+            SwapAmount::ExactIn {
+                amount_in: U256::exp10(19),
+                amount_out_min: U256::exp10(19),
+            },
+        );
+        assert!(apply_pending_swap(&too_tight, &pool_index, &mut reserves).is_none());
+        let too_expensive = swap(
+            vec![1, 2],
+            SwapAmount::ExactOut {
+                amount_out: U256::exp10(19),
+                amount_in_max: U256::exp10(19),
+            },
+        );
+        assert!(apply_pending_swap(&too_expensive, &pool_index, &mut reserves).is_none());
+        // 没有追踪 1 / 3 的池子
+        let untracked = swap(
+            vec![1, 3],
+            SwapAmount::ExactIn {
+                amount_in: U256::exp10(18),
+                amount_out_min: U256::zero(),
+            },
+        );
+        assert!(apply_pending_swap(&untracked, &pool_index, &mut reserves).is_none());
+        // 失败的交易不改储备量
+        for reserve in reserves.values() {
+            assert_eq!(reserve.reserve0, U256::exp10(21));
+            assert_eq!(reserve.reserve1, U256::exp10(21));
+        }
+    }
+
+    #[test]
+    fn swaps_move_only_the_pool_of_their_router() {
+        // 两个 DEX 都有 1 / 2 的池子: 池子 10 在 router 0 上, 池子 20 在 router 0x99 上
+        let sushi = H160::from_low_u64_be(0x99);
+        let mut other = pool(20, 1, 2);
+        other.router = sushi;
+        let pools: HashMap<H160, Pool> = [pool(10, 1, 2), other]
+            .into_iter()
+            .map(|pool| (pool.address, pool))
+            .collect();
+        let pool_index = index_pools_by_tokens(&pools);
+        assert_eq!(pool_index.len(), 2);
+        let mut reserves: HashMap<H160, Reserve> = pools
+            .keys()
+            .map(|address| {
+                (
+                    *address,
+                    Reserve {
+                        reserve0: U256::exp10(21),
+                        reserve1: U256::exp10(21),
+                    },
+                )
+            })
+            .collect();
+
+        let mut sushi_swap = swap(
+            vec![1, 2],
+            SwapAmount::ExactIn {
+                amount_in: U256::exp10(19),
+                amount_out_min: U256::zero(),
+            },
+        );
+        sushi_swap.router = sushi;
+        let touched = apply_pending_swap(&sushi_swap, &pool_index, &mut reserves).unwrap();
+        assert_eq!(touched, vec![H160::from_low_u64_be(20)]);
+        assert!(reserves[&H160::from_low_u64_be(20)].reserve0 > U256::exp10(21));
+        assert_eq!(
+            reserves[&H160::from_low_u64_be(10)].reserve0,
+            U256::exp10(21)
+        );
+
+        // 没有池子的 router
+        sushi_swap.router = H160::from_low_u64_be(0x98);
+        assert!(apply_pending_swap(&sushi_swap, &pool_index, &mut reserves).is_none());
+    }
+}
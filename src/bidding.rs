@@ -0,0 +1,167 @@
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// 把利润让给 builder 的方式
+// 配置里写 "priority_fee" 或者 { coinbase_transfer = { recipient = "0x..." } }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BidMode {
+    // 提高 priority fee
+    PriorityFee,
+    // bundle 最后附加一笔直接转给 builder 的交易, priority fee 为 0
+    CoinbaseTransfer { recipient: Address },
+}
+
+#[derive(Debug, Clone)]
+pub struct BidPolicy {
+    pub mode: BidMode,
+    pub profit_share_bps: u64,     // 初始出价: 预期利润的比例
+    pub min_profit_share_bps: u64, // 自适应调整的下限
+    pub max_profit_share_bps: u64, // 自适应调整的上限
+    pub floor: U256,               // 出价总额下限 (wei)
+    pub ceiling: U256,             // 出价总额上限 (wei)
+    pub target_inclusion_rate: f64,
+    pub adjust_step_bps: u64,
+    pub window: usize, // 统计最近多少次提交的上链率
+}
+
+impl Default for BidPolicy {
+    fn default() -> Self {
+        Self {
+            mode: BidMode::PriorityFee,
+            profit_share_bps: 5000,
+            min_profit_share_bps: 1000,
+            max_profit_share_bps: 9000,
+            floor: U256::zero(),
+            ceiling: U256::MAX,
+            target_inclusion_rate: 0.5,
+            adjust_step_bps: 250,
+            window: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Bid {
+    pub max_priority_fee_per_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub coinbase_transfer: U256,
+}
+
+// 根据预期利润决定给 builder 多少, 并按历史上链率调整比例
+#[derive(Debug, Clone)]
+pub struct Bidder {
+    pub policy: BidPolicy,
+    pub profit_share_bps: u64,
+    pub history: VecDeque<bool>,
+}
+
+impl Bidder {
+    pub fn new(policy: BidPolicy) -> Self {
+        Self {
+            profit_share_bps: policy.profit_share_bps,
+            policy,
+            history: VecDeque::new(),
+        }
+    }
+
+    // expected_profit: 扣除 base fee 成本后的预期利润, 以原生代币计 (wei)
+    // 出价不能把利润全部让出去, 这种情况返回 None
+    pub fn bid(&self, expected_profit: U256, base_fee: U256, gas_usage: U256) -> Option<Bid> {
+        if expected_profit.is_zero() || gas_usage.is_zero() {
+            return None;
+        }
+        let share = expected_profit * U256::from(self.profit_share_bps) / U256::from(10000);
+        let total = std::cmp::min(std::cmp::max(share, self.policy.floor), self.policy.ceiling);
+        if total >= expected_profit {
+            return None;
+        }
+        match self.policy.mode {
+            BidMode::PriorityFee => {
+                let max_priority_fee_per_gas = total / gas_usage;
+                Some(Bid {
+                    max_priority_fee_per_gas,
+                    max_fee_per_gas: base_fee + max_priority_fee_per_gas,
+                    coinbase_transfer: U256::zero(),
+                })
+            }
+            BidMode::CoinbaseTransfer { .. } => Some(Bid {
+                max_priority_fee_per_gas: U256::zero(),
+                max_fee_per_gas: base_fee,
+                coinbase_transfer: total,
+            }),
+        }
+    }
+
+    pub fn inclusion_rate(&self) -> Option<f64> {
+        if self.history.is_empty() {
+            return None;
+        }
+        let included = self.history.iter().filter(|included| **included).count();
+        Some(included as f64 / self.history.len() as f64)
+    }
+
+    // 记录一次提交是否上链
+    // 上链率低于目标就提高比例, 明显高于目标就降低比例
+    pub fn record_inclusion(&mut self, included: bool) {
+        self.history.push_back(included);
+        while self.history.len() > self.policy.window {
+            self.history.pop_front();
+        }
+        let rate = match self.inclusion_rate() {
+            Some(rate) => rate,
+            None => return,
+        };
+        let step = self.policy.adjust_step_bps;
+        if rate < self.policy.target_inclusion_rate {
+            self.profit_share_bps = std::cmp::min(
+                self.profit_share_bps + step,
+                self.policy.max_profit_share_bps,
+            );
+        } else if rate > (self.policy.target_inclusion_rate + 1.0) / 2.0 {
+            self.profit_share_bps = std::cmp::max(
+                self.profit_share_bps.saturating_sub(step),
+                self.policy.min_profit_share_bps,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod bidding_tests {
+    use super::*;
+
+    #[test]
+    fn bid_is_clamped_and_below_profit() {
+        let policy = BidPolicy {
+            floor: U256::from(100),
+            ceiling: U256::from(4000),
+            ..Default::default()
+        };
+        let bidder = Bidder::new(policy);
+        // 50% 的 10000 = 5000, 被上限压到 4000
+        let bid = bidder
+            .bid(U256::from(10000), U256::from(7), U256::from(100))
+            .unwrap();
+        assert_eq!(bid.max_priority_fee_per_gas, U256::from(40));
+        assert_eq!(bid.max_fee_per_gas, U256::from(47));
+        // 下限 100 已经超过利润
+        assert!(bidder
+            .bid(U256::from(100), U256::from(7), U256::from(100))
+            .is_none());
+    }
+
+    #[test]
+    fn share_adapts_to_inclusion_rate() {
+        let mut bidder = Bidder::new(BidPolicy::default());
+        for _ in 0..4 {
+            bidder.record_inclusion(false);
+        }
+        assert_eq!(bidder.profit_share_bps, 6000);
+        for _ in 0..40 {
+            bidder.record_inclusion(true);
+        }
+        assert_eq!(bidder.profit_share_bps, 1000);
+    }
+}
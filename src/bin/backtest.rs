@@ -0,0 +1,62 @@
+use anyhow::{anyhow, Result};
+use dotenv::dotenv;
+use ethers_providers::Provider;
+use rust::{
+    config::Config, mock::FixtureTransport, pools::pool_cache_path, recorder::read_recordings,
+    replay::Backtest, strategy::load_triangular_paths,
+};
+use std::{path::Path, sync::Arc};
+
+// 用录下来的区块头和 Sync 日志回测三角套利, 不连节点
+// cargo run --features mock --bin backtest -- <events.jsonl | recorder 目录>...
+// 配置和实盘一样 (config.toml + 环境变量), 池子从缓存读
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv().ok();
+    let files: Vec<String> = std::env::args().skip(1).collect();
+    if files.is_empty() {
+        return Err(anyhow!(
+            "Usage: backtest <events.jsonl | recordings dir>..."
+        ));
+    }
+    let config = Config::load()?;
+    // 缓存按链和 DEX 列表分开, 要用同样的配置先跑一次实盘
+    let cache_path = pool_cache_path(config.chain.chain_id, &config.dexes);
+    if !cache_path.exists() {
+        return Err(anyhow!(
+            "{} not found, run the bot once with this chain and dexes to cache pools",
+            cache_path.display()
+        ));
+    }
+    // 池子从缓存读, provider 用不到
+    let provider = Arc::new(Provider::new(FixtureTransport::default()));
+    let (paths, pools) = load_triangular_paths(
+        provider,
+        config.chain.chain_id,
+        config.base_token.address,
+        &config.dexes,
+        &config.risk.blacklist_tokens,
+    )
+    .await?;
+
+    let mut events = Vec::new();
+    for file in &files {
+        events.extend(read_recordings(Path::new(file))?);
+    }
+    let mut backtest = Backtest::new(&config, paths, pools).await?;
+    let report = backtest.run(events).await;
+    for block in &report.blocks {
+        if block.opportunities.is_empty() {
+            continue;
+        }
+        println!(
+            "block {}: {} opportunities, estimated profit {}, latency budget {}ms",
+            block.block_number,
+            block.opportunities.len(),
+            block.estimated_profit(),
+            block.latency_budget_ms
+        );
+    }
+    println!("{}", report.summary());
+    Ok(())
+}
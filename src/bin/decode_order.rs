@@ -0,0 +1,53 @@
+use anyhow::{anyhow, Result};
+use dotenv::dotenv;
+use ethers::types::TxHash;
+use ethers_providers::{Http, Middleware, Provider};
+use rust::{
+    config::Config,
+    order::{parse_calldata, OrderParams, RouterRegistry, PACKED_ORDER_VERSION},
+};
+use std::str::FromStr;
+
+// 解码我们自己发出去的套利交易
+// cargo run --bin decode_order -- <calldata | tx hash>
+// 交易哈希用 rpc.https_url 查, 紧凑编码需要 order.router_registry (见 config.toml)
+// 只有这两种情况才读配置, ABI 编码的 calldata 不需要配置文件和环境变量
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv().ok();
+    let input = std::env::args()
+        .nth(1)
+        .ok_or(anyhow!("Usage: decode_order <calldata | tx hash>"))?;
+    let mut config = None;
+
+    // 32 字节的是交易哈希, 其他当作 calldata
+    let calldata = match TxHash::from_str(&input) {
+        Ok(tx_hash) if parse_calldata(&input)?.len() == 32 => {
+            let config = config.insert(Config::load()?);
+            let provider = Provider::<Http>::try_from(config.rpc.https_url.as_str())?;
+            let tx = provider
+                .get_transaction(tx_hash)
+                .await?
+                .ok_or(anyhow!("Transaction not found: {:?}", tx_hash))?;
+            println!("tx: {:?}", tx.hash);
+            println!("from: {:?}", tx.from);
+            println!("to: {:?}", tx.to);
+            println!("nonce: {}", tx.nonce);
+            println!("block: {:?}", tx.block_number);
+            tx.input.to_vec()
+        }
+        _ => parse_calldata(&input)?,
+    };
+
+    let registry = match calldata.first() {
+        Some(&PACKED_ORDER_VERSION) => match config {
+            Some(config) => config.order.registry(),
+            None => Config::load()?.order.registry(),
+        },
+        _ => RouterRegistry::default(),
+    };
+    let order = OrderParams::decode_any(&calldata, &registry)?;
+    println!("calldata: {} bytes", calldata.len());
+    println!("{}", order.explain());
+    Ok(())
+}
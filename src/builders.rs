@@ -0,0 +1,343 @@
+use anyhow::{anyhow, Result};
+use ethers::{
+    signers::Signer,
+    types::{TxHash, H256},
+    utils::keccak256,
+};
+use ethers_flashbots::BundleRequest;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::mev_share::MevShareBundle;
+use crate::signer::BotWallet;
+use crate::utils::http_client;
+
+// builder 对请求签名的方式
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SigningScheme {
+    // X-Flashbots-Signature 头, 用 SIGNING_KEY 签名
+    Flashbots,
+    None,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuilderConfig {
+    pub name: String,
+    pub url: String,
+    pub signing: SigningScheme,
+    pub methods: Vec<String>, // 支持的 RPC 方法, 例如 eth_sendBundle
+}
+
+impl BuilderConfig {
+    pub fn new(name: &str, url: &str, signing: SigningScheme, methods: Vec<&str>) -> Self {
+        Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            signing,
+            methods: methods.into_iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    pub fn supports(&self, method: &str) -> bool {
+        self.methods.iter().any(|m| m == method)
+    }
+}
+
+// 一个 builder 对一次请求的结果
+#[derive(Debug, Clone)]
+pub struct BuilderResult {
+    pub builder: String,
+    pub result: std::result::Result<Option<TxHash>, String>, // 成功时可能带 bundleHash
+}
+
+pub struct BuilderClient {
+    pub config: BuilderConfig,
+    pub signer: BotWallet,
+    pub http: reqwest::Client,
+}
+
+impl BuilderClient {
+    // timeout 是每个请求的上限, 用出块时间
+    pub fn new(config: BuilderConfig, signer: BotWallet, timeout: Duration) -> Self {
+        Self {
+            config,
+            signer,
+            http: http_client(timeout),
+        }
+    }
+
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+        let mut request = self
+            .http
+            .post(&self.config.url)
+            .header("Content-Type", "application/json");
+        if self.config.signing == SigningScheme::Flashbots {
+            let hash = H256::from(keccak256(body.as_bytes()));
+            let signature = self.signer.sign_message(format!("0x{:x}", hash)).await?;
+            request = request.header(
+                "X-Flashbots-Signature",
+                format!("{:?}:0x{}", self.signer.address(), signature),
+            );
+        }
+        let response: Value =
+            serde_json::from_str(&request.body(body).send().await?.text().await?)?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow!("{}: {}", self.config.name, error));
+        }
+        Ok(response.get("result").cloned().unwrap_or(Value::Null))
+    }
+
+    // 支持 eth_cancelBundle 的 builder 才带 replacementUuid
+    pub async fn send_bundle(
+        &self,
+        bundle: &BundleRequest,
+        replacement_uuid: Option<&str>,
+    ) -> BuilderResult {
+        let mut params = serde_json::to_value(bundle).unwrap_or(Value::Null);
+        if let (Some(uuid), Some(params)) = (replacement_uuid, params.as_object_mut()) {
+            if self.config.supports("eth_cancelBundle") {
+                params.insert("replacementUuid".to_string(), json!(uuid));
+            }
+        }
+        let result = match self.call("eth_sendBundle", json!([params])).await {
+            Ok(result) => Ok(result
+                .get("bundleHash")
+                .and_then(|hash| serde_json::from_value(hash.clone()).ok())),
+            Err(e) => Err(format!("{:?}", e)),
+        };
+        BuilderResult {
+            builder: self.config.name.clone(),
+            result,
+        }
+    }
+
+    pub async fn send_mev_share_bundle(&self, bundle: &MevShareBundle) -> BuilderResult {
+        let params = json!([bundle]);
+        let result = match self.call("mev_sendBundle", params).await {
+            Ok(result) => Ok(result
+                .get("bundleHash")
+                .and_then(|hash| serde_json::from_value(hash.clone()).ok())),
+            Err(e) => Err(format!("{:?}", e)),
+        };
+        BuilderResult {
+            builder: self.config.name.clone(),
+            result,
+        }
+    }
+
+    pub async fn cancel_bundle(&self, replacement_uuid: &str) -> BuilderResult {
+        let params = json!([{ "replacementUuid": replacement_uuid }]);
+        let result = match self.call("eth_cancelBundle", params).await {
+            Ok(_) => Ok(None),
+            Err(e) => Err(format!("{:?}", e)),
+        };
+        BuilderResult {
+            builder: self.config.name.clone(),
+            result,
+        }
+    }
+}
+
+// 随机生成 v4 格式的 UUID, 用来替换或者取消已经提交的 bundle
+pub fn new_replacement_uuid() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+// 同时发给所有支持 eth_sendBundle 的 builder, 按 builder 收集结果
+pub async fn send_bundle_to_builders(
+    builders: &[BuilderClient],
+    bundle: &BundleRequest,
+    replacement_uuid: Option<&str>,
+) -> Vec<BuilderResult> {
+    let requests = builders
+        .iter()
+        .filter(|builder| builder.config.supports("eth_sendBundle"))
+        .map(|builder| builder.send_bundle(bundle, replacement_uuid));
+    join_all(requests).await
+}
+
+pub async fn send_mev_share_bundle_to_builders(
+    builders: &[BuilderClient],
+    bundle: &MevShareBundle,
+) -> Vec<BuilderResult> {
+    let requests = builders
+        .iter()
+        .filter(|builder| builder.config.supports("mev_sendBundle"))
+        .map(|builder| builder.send_mev_share_bundle(bundle));
+    join_all(requests).await
+}
+
+pub async fn cancel_bundle_on_builders(
+    builders: &[BuilderClient],
+    replacement_uuid: &str,
+) -> Vec<BuilderResult> {
+    let requests = builders
+        .iter()
+        .filter(|builder| builder.config.supports("eth_cancelBundle"))
+        .map(|builder| builder.cancel_bundle(replacement_uuid));
+    join_all(requests).await
+}
+
+#[cfg(test)]
+mod builders_tests {
+    use super::*;
+    use crate::mock::{serve_fixtures, serve_http, Fixtures, HttpResponse, TEST_FLASHBOTS_KEY};
+    use crate::signer::KeySource;
+    use ethers::types::{Bytes, Signature, U64};
+    use std::str::FromStr;
+
+    fn wallet() -> BotWallet {
+        KeySource::PrivateKey(TEST_FLASHBOTS_KEY.to_string())
+            .load(1, Duration::from_secs(12))
+            .unwrap()
+    }
+
+    async fn builder(
+        name: &str,
+        signing: SigningScheme,
+        methods: Vec<&str>,
+    ) -> (BuilderClient, Fixtures) {
+        let bundle_hash = format!("{:?}", TxHash::from_low_u64_be(0xb1));
+        let fixtures = Fixtures::default();
+        fixtures.push("eth_sendBundle", json!({ "bundleHash": bundle_hash }));
+        fixtures.push("eth_cancelBundle", Value::Null);
+        serve(name, signing, methods, fixtures).await
+    }
+
+    async fn serve(
+        name: &str,
+        signing: SigningScheme,
+        methods: Vec<&str>,
+        fixtures: Fixtures,
+    ) -> (BuilderClient, Fixtures) {
+        let url = serve_fixtures(fixtures.clone()).await.unwrap();
+        let config = BuilderConfig::new(name, &url, signing, methods);
+        (
+            BuilderClient::new(config, wallet(), Duration::from_secs(12)),
+            fixtures,
+        )
+    }
+
+    // builder 不回复时请求按超时返回错误, 不会一直挂着
+    #[tokio::test]
+    async fn unresponsive_builder_times_out() {
+        let url = serve_http(|_| async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            HttpResponse::Json(Value::Null)
+        })
+        .await
+        .unwrap();
+        let config = BuilderConfig::new("slow", &url, SigningScheme::None, vec!["eth_sendBundle"]);
+        let client = BuilderClient::new(config, wallet(), Duration::from_millis(100));
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            client.call("eth_sendBundle", json!([])),
+        )
+        .await
+        .unwrap();
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn flashbots_signature_header() {
+        let (client, fixtures) = builder(
+            "flashbots",
+            SigningScheme::Flashbots,
+            vec!["eth_sendBundle"],
+        )
+        .await;
+        client.call("eth_sendBundle", json!([{}])).await.unwrap();
+
+        let request = fixtures.http_requests("eth_sendBundle").pop().unwrap();
+        let header = &request.headers["x-flashbots-signature"];
+        let (address, signature) = header.split_once(':').unwrap();
+        assert_eq!(address, format!("{:?}", client.signer.address()));
+        // 签名的是请求体哈希的十六进制字符串
+        let hash = H256::from(keccak256(request.body.as_bytes()));
+        let signature = Signature::from_str(signature).unwrap();
+        assert_eq!(
+            signature.recover(format!("0x{:x}", hash)).unwrap(),
+            client.signer.address()
+        );
+
+        let (client, fixtures) =
+            builder("beaverbuild", SigningScheme::None, vec!["eth_sendBundle"]).await;
+        client.call("eth_sendBundle", json!([{}])).await.unwrap();
+        let request = fixtures.http_requests("eth_sendBundle").pop().unwrap();
+        assert!(!request.headers.contains_key("x-flashbots-signature"));
+    }
+
+    #[tokio::test]
+    async fn fans_out_to_supporting_builders() {
+        let (flashbots, flashbots_fixtures) = builder(
+            "flashbots",
+            SigningScheme::Flashbots,
+            vec!["eth_sendBundle", "eth_cancelBundle"],
+        )
+        .await;
+        let (beaver, beaver_fixtures) =
+            builder("beaverbuild", SigningScheme::None, vec!["eth_sendBundle"]).await;
+        let (share, share_fixtures) =
+            builder("share", SigningScheme::Flashbots, vec!["mev_sendBundle"]).await;
+        // 返回错误的 builder 不影响其他 builder
+        let rejected = Fixtures::default();
+        rejected.push(
+            "eth_sendBundle",
+            json!({"error": {"code": -32000, "message": "bundle rejected"}}),
+        );
+        let (broken, _) = serve(
+            "broken",
+            SigningScheme::None,
+            vec!["eth_sendBundle"],
+            rejected,
+        )
+        .await;
+        let builders = vec![flashbots, beaver, share, broken];
+
+        let bundle = BundleRequest::new()
+            .push_transaction(Bytes::from(vec![1u8]))
+            .set_block(U64::from(100));
+        let results = send_bundle_to_builders(&builders, &bundle, Some("uuid")).await;
+        let names: Vec<&str> = results.iter().map(|r| r.builder.as_str()).collect();
+        assert_eq!(names, vec!["flashbots", "beaverbuild", "broken"]);
+        let bundle_hash = TxHash::from_low_u64_be(0xb1);
+        assert_eq!(results[0].result, Ok(Some(bundle_hash)));
+        assert_eq!(results[1].result, Ok(Some(bundle_hash)));
+        assert!(results[2].result.is_err());
+        assert!(share_fixtures.requests("eth_sendBundle").is_empty());
+
+        // 只有支持 eth_cancelBundle 的 builder 带 replacementUuid
+        let params = flashbots_fixtures.requests("eth_sendBundle");
+        assert_eq!(params[0][0]["replacementUuid"], json!("uuid"));
+        let params = beaver_fixtures.requests("eth_sendBundle");
+        assert!(params[0][0].get("replacementUuid").is_none());
+
+        let results = cancel_bundle_on_builders(&builders, "uuid").await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].builder, "flashbots");
+        assert_eq!(
+            flashbots_fixtures.requests("eth_cancelBundle")[0],
+            json!([{"replacementUuid": "uuid"}])
+        );
+    }
+}
@@ -1,109 +1,242 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use ethers::prelude::*;
 use ethers::types::{
     transaction::{eip2718::TypedTransaction, eip2930::AccessList},
     Address, Eip1559TransactionRequest, U256,
 };
+use ethers::utils::{keccak256, rlp};
 use ethers::{
-    abi,
     middleware::MiddlewareBuilder,
     providers::{Http, Middleware, Provider},
-    signers::{LocalWallet, Signer},
+    signers::Signer,
 };
 use ethers_flashbots::*;
+use log::info;
+use serde_json::{json, Value};
 use std::{str::FromStr, sync::Arc};
 use url::Url;
 
-use crate::constants::Env;
-
-abigen!(
-    ArbBot,
-    r#"[
-        function recoverToken(address token) external;
-        function approveRouter(address router, address[] memory tokens, bool force) external;
-    ]"#,
-);
+use crate::{
+    abi::V2ArbBot,
+    access_list::AccessListBuilder,
+    builders::{
+        cancel_bundle_on_builders, send_bundle_to_builders, send_mev_share_bundle_to_builders,
+        BuilderClient, BuilderConfig, BuilderResult,
+    },
+    config::{Config, SubmissionMode},
+    constants::Env,
+    mev_share::MevShareBundle,
+    nonce::{is_nonce_error, NonceManager},
+    order::OrderParams,
+    paths::PathParam,
+    pools::Pool,
+    signer::BotWallet,
+};
 
-#[derive(Debug, Clone)]
-pub struct PathParam {
-    pub router: Address,
-    pub token_in: Address,
-    pub token_out: Address,
+// 合约用 uint 区分闪电贷来源, 数值不能改
+// 合约要实现对应的回调才能用, 见 flashloan::supported_flashloans
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Flashloan {
+    NotUsed = 0,
+    Balancer = 1,
+    UniswapV2 = 2,
 }
 
-impl PathParam {
-    pub fn make_params(&self) -> Vec<abi::Token> {
-        vec![
-            abi::Token::Address(self.router.into()),
-            abi::Token::Address(self.token_in.into()),
-            abi::Token::Address(self.token_out.into()),
-        ]
+impl TryFrom<u8> for Flashloan {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Flashloan::NotUsed),
+            1 => Ok(Flashloan::Balancer),
+            2 => Ok(Flashloan::UniswapV2),
+            _ => Err(anyhow!("Unknown flashloan: {}", value)),
+        }
     }
 }
 
+// bundle 模拟失败, 和没有上链区分开
+#[derive(Debug, thiserror::Error)]
+pub enum SimulationError {
+    #[error("Simulation error: {0}")]
+    Error(String),
+    #[error("Simulation revert: {0}")]
+    Revert(String),
+}
+
+// 签好名的交易解码回来, from 设为签名者, 用于 eth_call / eth_estimateGas
+pub fn decode_signed_tx(signed_tx: &Bytes) -> Result<TypedTransaction> {
+    let (mut typed, signature) =
+        TypedTransaction::decode_signed(&rlp::Rlp::new(signed_tx.as_ref()))?;
+    typed.set_from(signature.recover(typed.sighash())?);
+    Ok(typed)
+}
+
+// 单独提交的一笔交易 (不走 bundle)
 #[derive(Debug, Clone)]
-pub enum Flashloan {
-    NotUsed = 0,
-    Balancer = 1,
-    UniswapV2 = 2,
+pub struct PrivateTx {
+    pub signed_tx: Bytes,
+    pub max_block_number: U64, // 过了这个区块还没上链就算失败
+    pub expected_profit: U256, // 扣 gas 之前的利润 (wei)
+    pub pools: Vec<Address>,   // conditional 模式下锁定这些池子的状态
 }
 
-type SignerProvider = SignerMiddleware<Provider<Http>, LocalWallet>;
+impl PrivateTx {
+    pub fn hash(&self) -> TxHash {
+        TxHash::from(keccak256(&self.signed_tx))
+    }
+}
+
+type SignerProvider = SignerMiddleware<Provider<Http>, BotWallet>;
 
 pub struct Bundler {
     pub env: Env,
-    pub sender: LocalWallet,
-    pub bot: ArbBot<SignerProvider>,
+    pub sender: BotWallet,
+    // 合约 owner, 发 approve / recoverToken; 没有单独配置时和 sender 相同
+    pub admin: BotWallet,
+    pub admin_provider: SignerProvider,
+    pub bot: V2ArbBot<SignerProvider>,
     pub provider: SignerProvider,
-    pub flashbots: SignerMiddleware<FlashbotsMiddleware<SignerProvider, LocalWallet>, LocalWallet>,
+    // 用来模拟 bundle 的 relay
+    pub flashbots: SignerMiddleware<FlashbotsMiddleware<SignerProvider, BotWallet>, BotWallet>,
+    // 提交 bundle 的 builder 列表
+    pub builders: Vec<BuilderClient>,
+    // 不走 bundle 时提交交易的 RPC (私有 RPC / sequencer)
+    pub submission_rpc: BuilderClient,
+    pub nonces: NonceManager,
+    pub access_lists: AccessListBuilder,
 }
 
 impl Bundler {
-    pub fn new<'a>() -> Self {
-        let env = Env::new();
+    pub fn new(config: &Config) -> Result<Self> {
+        Self::from_env(Env::from_config(config)?)
+    }
 
+    // 测试里用 mock::mock_env, 不读环境变量
+    // 密钥 / keystore 密码 / URL 有问题时在启动时返回错误
+    pub fn from_env(env: Env) -> Result<Self> {
+        let chain_id = env.chain_id.as_u64();
+        // builder 和远程签名的请求最多等一个区块
+        let timeout = env.config.chain.block_time();
         let sender = env
-            .private_key
-            .parse::<LocalWallet>()
-            .unwrap()
-            .with_chain_id(env.chain_id.as_u64());
+            .signers
+            .executor
+            .load(chain_id, timeout)
+            .context("Error loading executor key")?;
         let signer = env
-            .signing_key
-            .parse::<LocalWallet>()
-            .unwrap()
-            .with_chain_id(env.chain_id.as_u64());
+            .signers
+            .flashbots
+            .load(chain_id, timeout)
+            .context("Error loading flashbots key")?;
+        let admin = match &env.signers.admin {
+            Some(admin) => admin
+                .load(chain_id, timeout)
+                .context("Error loading admin key")?,
+            None => sender.clone(),
+        };
 
         let provider = Provider::<Http>::try_from(&env.https_url)
-            .unwrap()
-            .with_signer(sender.clone());
+            .with_context(|| format!("Invalid rpc url {}", env.https_url))?;
+        let admin_provider = provider.clone().with_signer(admin.clone());
+        let provider = provider.with_signer(sender.clone());
 
+        // 第一个支持 eth_callBundle 的 builder 用来模拟
+        let simulation_relay = env
+            .builders
+            .iter()
+            .find(|builder| builder.supports("eth_callBundle"))
+            .map(|builder| builder.url.clone())
+            .unwrap_or("https://relay.flashbots.net".to_string());
         let flashbots = SignerMiddleware::new(
             FlashbotsMiddleware::new(
                 provider.clone(),
-                Url::parse("https://relay.flashbots.net").unwrap(),
-                signer,
+                Url::parse(&simulation_relay).with_context(|| {
+                    format!("Invalid simulation relay url {}", simulation_relay)
+                })?,
+                signer.clone(),
             ),
             sender.clone(),
         );
+        let builders = env
+            .builders
+            .iter()
+            .map(|config| BuilderClient::new(config.clone(), signer.clone(), timeout))
+            .collect();
+        let submission = &env.config.submission;
+        let submission_url = match submission.rpc_url.is_empty() {
+            true => &env.https_url,
+            false => &submission.rpc_url,
+        };
+        let submission_rpc = BuilderClient::new(
+            BuilderConfig::new(
+                "submission",
+                submission_url,
+                submission.signing.clone(),
+                vec![],
+            ),
+            signer.clone(),
+            timeout,
+        );
 
         let client = Arc::new(provider.clone());
-        let bot = ArbBot::new(env.bot_address.parse::<Address>().unwrap(), client.clone());
+        let bot_address = env
+            .bot_address
+            .parse::<Address>()
+            .with_context(|| format!("Invalid bot address {}", env.bot_address))?;
+        let bot = V2ArbBot::new(bot_address, client.clone());
+
+        let nonces = NonceManager::new(sender.address());
+        let access_lists = AccessListBuilder::for_chain(env.chain_id.as_u64());
 
-        Self {
+        Ok(Self {
             env,
             sender,
+            admin,
+            admin_provider,
             bot,
             provider: provider,
             flashbots: flashbots,
-        }
+            builders,
+            submission_rpc,
+            nonces,
+            access_lists,
+        })
     }
 
     pub async fn _common_fields(&self) -> Result<(H160, U256, U64)> {
+        let nonce = self.reserve_nonces(1).await?;
+        Ok((self.sender.address(), nonce, self.env.chain_id))
+    }
+
+    // owner 才能调用的函数用 admin 发; admin 和 sender 是同一个地址时走 nonce 管理
+    pub async fn _admin_fields(&self) -> Result<(H160, U256, U64)> {
+        let admin = self.admin.address();
+        if admin == self.sender.address() {
+            return self._common_fields().await;
+        }
         let nonce = self
             .provider
-            .get_transaction_count(self.sender.address(), None)
+            .get_transaction_count(admin, Some(BlockNumber::Pending.into()))
             .await?;
-        Ok((self.sender.address(), U256::from(nonce), self.env.chain_id))
+        Ok((admin, nonce, self.env.chain_id))
+    }
+
+    // 单独发送的交易, 一次分配 count 个连续的 nonce
+    pub async fn reserve_nonces(&self, count: u64) -> Result<U256> {
+        self.nonces.reserve(&self.provider, count).await
+    }
+
+    // 一次分配 count 个连续的 nonce, 用于同一个 bundle 里的多笔交易
+    // 同一个区块里互相竞争的 bundle 拿到同一个起始 nonce, 见 NonceManager::reserve_bundle
+    pub async fn reserve_bundle_nonces(&self, count: u64) -> Result<U256> {
+        self.nonces.reserve_bundle(&self.provider, count).await
+    }
+
+    async fn check_nonce_error(&self, message: &str) {
+        if is_nonce_error(message) {
+            info!("Nonce error, resyncing: {}", message);
+            self.nonces.invalidate().await;
+        }
     }
 
     pub async fn sign_tx(&self, tx: Eip1559TransactionRequest) -> Result<Bytes> {
@@ -131,26 +264,193 @@ impl Bundler {
             .set_simulation_timestamp(0)
     }
 
-    pub async fn send_bundle(&self, bundle: BundleRequest) -> Result<TxHash> {
-        let simulated = self.flashbots.inner().simulate_bundle(&bundle).await?;
+    // 同样的交易, 每个目标区块一个 bundle: block_number + 1 ..= block_number + target_blocks
+    pub fn to_bundles<T: Into<BundleTransaction>>(
+        &self,
+        signed_txs: Vec<T>,
+        block_number: U64,
+        target_blocks: u64,
+    ) -> Vec<BundleRequest> {
+        let bundle = self.to_bundle(signed_txs, block_number);
+        (1..=target_blocks)
+            .map(|i| bundle.clone().set_block(block_number + i))
+            .collect()
+    }
+
+    // eth_callBundle 模拟, 有交易出错或 revert 时返回 SimulationError
+    pub async fn simulate_bundle(&self, bundle: &BundleRequest) -> Result<SimulatedBundle> {
+        let simulated = match self.flashbots.inner().simulate_bundle(bundle).await {
+            Ok(simulated) => simulated,
+            Err(e) => {
+                self.check_nonce_error(&format!("{:?}", e)).await;
+                return Err(e.into());
+            }
+        };
 
         for tx in &simulated.transactions {
             if let Some(e) = &tx.error {
-                return Err(anyhow!("Simulation error: {:?}", e));
+                self.check_nonce_error(e).await;
+                return Err(SimulationError::Error(e.clone()).into());
             }
             if let Some(r) = &tx.revert {
-                return Err(anyhow!("Simulation revert: {:?}", r));
+                return Err(SimulationError::Revert(r.clone()).into());
+            }
+        }
+        Ok(simulated)
+    }
+
+    // 先模拟, 模拟通过再提交给所有 builder, 模拟结果用来校准 gas 模型
+    // 是否上链由 tracker::BundleTracker 判断
+    pub async fn send_bundle(
+        &self,
+        bundle: &BundleRequest,
+        replacement_uuid: Option<&str>,
+    ) -> Result<(SimulatedBundle, Vec<BuilderResult>)> {
+        let simulated = self.simulate_bundle(bundle).await?;
+
+        let results = self.broadcast_bundle(bundle, replacement_uuid).await;
+        for result in &results {
+            info!("Builder {}: {:?}", result.builder, result.result);
+        }
+        if results.iter().all(|result| result.result.is_err()) {
+            return Err(anyhow!("No builder accepted the bundle"));
+        }
+        Ok((simulated, results))
+    }
+
+    // 并发提交给所有 builder, 返回每个 builder 的结果
+    pub async fn broadcast_bundle(
+        &self,
+        bundle: &BundleRequest,
+        replacement_uuid: Option<&str>,
+    ) -> Vec<BuilderResult> {
+        send_bundle_to_builders(&self.builders, bundle, replacement_uuid).await
+    }
+
+    // 用户的交易只有哈希, 没法用 eth_callBundle 模拟, 直接提交; 是否上链由 tracker 判断
+    pub async fn send_mev_share_bundle(
+        &self,
+        bundle: &MevShareBundle,
+    ) -> Result<Vec<BuilderResult>> {
+        let results = send_mev_share_bundle_to_builders(&self.builders, bundle).await;
+        for result in &results {
+            info!(
+                "Builder {} mev_sendBundle: {:?}",
+                result.builder, result.result
+            );
+        }
+        if results.iter().all(|result| result.result.is_err()) {
+            return Err(anyhow!("No builder accepted the MEV-Share bundle"));
+        }
+        Ok(results)
+    }
+
+    // 按 replacementUuid 取消之前提交的 bundle
+    pub async fn cancel_bundle(&self, replacement_uuid: &str) -> Result<Vec<BuilderResult>> {
+        let results = cancel_bundle_on_builders(&self.builders, replacement_uuid).await;
+        for result in &results {
+            info!("Builder {} cancel: {:?}", result.builder, result.result);
+        }
+        if results.iter().all(|result| result.result.is_err()) {
+            return Err(anyhow!("No builder cancelled the bundle"));
+        }
+        Ok(results)
+    }
+
+    // 单独提交一笔交易, 按 submission.mode 选 RPC 方法
+    // 没有 bundle 的原子性, 先确认最坏情况下也有利润; 私有 RPC 自带 revert 保护, 其他模式先 eth_call
+    pub async fn send_private_tx(&self, tx: &PrivateTx) -> Result<TxHash> {
+        let submission = &self.env.config.submission;
+        let typed = decode_signed_tx(&tx.signed_tx)?;
+        let max_fee = match &typed {
+            TypedTransaction::Eip1559(inner) => inner.max_fee_per_gas.unwrap_or_default(),
+            _ => typed.gas_price().unwrap_or_default(),
+        };
+        let gas_limit = typed.gas().cloned().unwrap_or_default();
+        if !submission.is_profitable(tx.expected_profit, gas_limit, max_fee) {
+            return Err(SimulationError::Error(format!(
+                "Unprofitable at max fee: profit {:?}, gas limit {:?}, max fee {:?}",
+                tx.expected_profit, gas_limit, max_fee
+            ))
+            .into());
+        }
+        if submission.mode != SubmissionMode::Private {
+            if let Err(e) = self.provider.call(&typed, None).await {
+                return Err(SimulationError::Revert(format!("{:?}", e)).into());
             }
         }
 
-        let pending_bundle = self.flashbots.inner().send_bundle(&bundle).await?;
-        let bundle_hash = pending_bundle.await?;
-        Ok(bundle_hash)
+        let result = match submission.mode {
+            SubmissionMode::Private => {
+                let params = json!([{
+                    "tx": tx.signed_tx,
+                    "maxBlockNumber": tx.max_block_number,
+                    "preferences": { "fast": true },
+                }]);
+                self.submission_rpc
+                    .call("eth_sendPrivateTransaction", params)
+                    .await
+            }
+            SubmissionMode::Conditional => {
+                let options = json!({
+                    "knownAccounts": self.known_accounts(&tx.pools).await?,
+                    "blockNumberMax": tx.max_block_number,
+                });
+                self.submission_rpc
+                    .call(
+                        "eth_sendRawTransactionConditional",
+                        json!([tx.signed_tx, options]),
+                    )
+                    .await
+            }
+            SubmissionMode::Public => {
+                self.submission_rpc
+                    .call("eth_sendRawTransaction", json!([tx.signed_tx]))
+                    .await
+            }
+            SubmissionMode::Bundle => return Err(anyhow!("Bundle mode does not send single txs")),
+        };
+        if let Err(e) = result {
+            self.check_nonce_error(&format!("{:?}", e)).await;
+            return Err(e);
+        }
+        Ok(tx.hash())
+    }
+
+    // 锁定池子的 reserve 槽 (slot 8), 上链前池子被别人动过时 sequencer 会拒绝这笔交易
+    async fn known_accounts(&self, pools: &[Address]) -> Result<Value> {
+        let slot = H256::from_low_u64_be(8);
+        let mut accounts = serde_json::Map::new();
+        for pool in pools {
+            let value = self.provider.get_storage_at(*pool, slot, None).await?;
+            accounts.insert(
+                format!("{:?}", pool),
+                json!({ format!("{:?}", slot): value }),
+            );
+        }
+        Ok(Value::Object(accounts))
     }
 
+    // from 是 admin 的交易 (approve_tx / transfer_out_tx) 用 admin 签名
     pub async fn send_tx(&self, tx: Eip1559TransactionRequest) -> Result<TxHash> {
-        let pending_tx = self.provider.send_transaction(tx, None).await?;
+        let admin = self.admin.address();
+        if admin != self.sender.address() && tx.from == Some(admin) {
+            let pending_tx = self.admin_provider.send_transaction(tx, None).await?;
+            let receipt = pending_tx.await?.ok_or_else(|| anyhow!("Tx dropped"))?;
+            return Ok(receipt.transaction_hash);
+        }
+        let nonce = tx.nonce;
+        let pending_tx = match self.provider.send_transaction(tx, None).await {
+            Ok(pending_tx) => pending_tx,
+            Err(e) => {
+                self.check_nonce_error(&format!("{:?}", e)).await;
+                return Err(e.into());
+            }
+        };
         let receipt = pending_tx.await?.ok_or_else(|| anyhow!("Tx dropped"))?;
+        if let Some(nonce) = nonce {
+            self.nonces.confirm(nonce).await;
+        }
         Ok(receipt.transaction_hash)
     }
 
@@ -176,6 +476,30 @@ impl Bundler {
         })
     }
 
+    // 直接转给 builder 的小费, 放在 bundle 最后
+    // nonce 和前面的套利交易一起用 reserve_bundle_nonces 分配
+    pub fn coinbase_tip_tx(
+        &self,
+        recipient: Address,
+        amount: U256,
+        nonce: U256,
+        max_priority_fee_per_gas: U256,
+        max_fee_per_gas: U256,
+    ) -> Eip1559TransactionRequest {
+        Eip1559TransactionRequest {
+            to: Some(NameOrAddress::Address(recipient)),
+            from: Some(self.sender.address()),
+            data: Some(Bytes(bytes::Bytes::new())),
+            value: Some(amount),
+            chain_id: Some(self.env.chain_id),
+            max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
+            max_fee_per_gas: Some(max_fee_per_gas),
+            gas: Some(U256::from(21000)),
+            nonce: Some(nonce),
+            access_list: AccessList::default(),
+        }
+    }
+
     pub async fn transfer_out_tx(
         &self,
         token: &str,
@@ -183,9 +507,9 @@ impl Bundler {
         max_fee_per_gas: U256,
     ) -> Result<Eip1559TransactionRequest> {
         let token_address = Address::from_str(token).unwrap();
-        let calldata = self.bot.encode("recoverToken", (token_address,))?;
+        let calldata = self.bot.recover_token(token_address).calldata().unwrap();
 
-        let common = self._common_fields().await?;
+        let common = self._admin_fields().await?;
         let to = NameOrAddress::Address(H160::from_str(&self.env.bot_address).unwrap());
         Ok(Eip1559TransactionRequest {
             to: Some(to),
@@ -216,10 +540,12 @@ impl Bundler {
             .collect();
         let calldata = self
             .bot
-            .encode("approveRouter", (router_address, token_addresses, force))?;
+            .approve_router(router_address, token_addresses, force)
+            .calldata()
+            .unwrap();
 
         let token_cnt = tokens.len();
-        let common = self._common_fields().await?;
+        let common = self._admin_fields().await?;
         let to = NameOrAddress::Address(H160::from_str(&self.env.bot_address).unwrap());
         Ok(Eip1559TransactionRequest {
             to: Some(to),
@@ -235,58 +561,161 @@ impl Bundler {
         })
     }
 
+    // 套利合约的 calldata: 没有函数选择器, 按 order.encoding 选择 abi 编码或紧凑编码
+    pub fn order_calldata(
+        &self,
+        paths: Vec<PathParam>,
+        amount_in: U256,
+        flashloan: Flashloan,
+        loan_from: Address,
+    ) -> Result<Bytes> {
+        let order = OrderParams::new(amount_in, flashloan, loan_from, paths);
+        // 紧凑编码失败 (router 没登记、金额超过 uint128) 时返回错误, 合约只认一种编码
+        order.encode_with(
+            self.env.config.order.encoding,
+            &self.env.config.order.registry(),
+        )
+    }
+
     pub async fn order_tx(
         &self,
         paths: Vec<PathParam>,
         amount_in: U256,
         flashloan: Flashloan,
         loan_from: Address,
+        gas_limit: U256,
         max_priority_fee_per_gas: U256,
         max_fee_per_gas: U256,
     ) -> Result<Eip1559TransactionRequest> {
-        let nhop = paths.len();
-
-        let mut params = Vec::new();
-        params.extend(vec![
-            abi::Token::Uint(amount_in),
-            abi::Token::Uint(U256::from(flashloan as u64)),
-            abi::Token::Address(loan_from),
-        ]);
-
-        for i in 0..nhop {
-            params.extend(paths[i].make_params());
-        }
-
-        let encoded = abi::encode(&params);
-        let calldata = Bytes::from(encoded);
-
+        let calldata = self.order_calldata(paths, amount_in, flashloan, loan_from)?;
         let common = self._common_fields().await?;
+        Ok(self._order_tx(
+            calldata,
+            common.1,
+            gas_limit,
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+        ))
+    }
+
+    fn _order_tx(
+        &self,
+        calldata: Bytes,
+        nonce: U256,
+        gas_limit: U256,
+        max_priority_fee_per_gas: U256,
+        max_fee_per_gas: U256,
+    ) -> Eip1559TransactionRequest {
         let to = NameOrAddress::Address(H160::from_str(&self.env.bot_address).unwrap());
-        Ok(Eip1559TransactionRequest {
+        Eip1559TransactionRequest {
             to: Some(to),
-            from: Some(common.0),
+            from: Some(self.sender.address()),
             data: Some(calldata),
             value: Some(U256::zero()),
-            chain_id: Some(common.2),
+            chain_id: Some(self.env.chain_id),
             max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
             max_fee_per_gas: Some(max_fee_per_gas),
-            gas: Some(U256::from(600000)),
-            nonce: Some(common.1),
+            gas: Some(gas_limit),
+            nonce: Some(nonce),
             access_list: AccessList::default(),
-        })
+        }
+    }
+
+    // 按路径给套利交易加上 access list, eth_estimateGas 确认省 gas 才加
+    pub async fn with_access_list(
+        &self,
+        tx: Eip1559TransactionRequest,
+        pools: &[Pool],
+    ) -> Eip1559TransactionRequest {
+        let candidate = self.access_lists.build(pools);
+        self.access_lists
+            .optimize(&self.provider, tx, candidate)
+            .await
+    }
+
+    // 同一个 bundle 里的多笔套利交易, nonce 从 first_nonce 开始依次递增
+    // first_nonce 用 reserve_bundle_nonces 一次分配好
+    // orders: (order_calldata 编码好的 calldata, gas limit), 编码失败的套利在分配 nonce 之前去掉
+    pub fn order_txs(
+        &self,
+        orders: Vec<(Bytes, U256)>,
+        first_nonce: U256,
+        max_priority_fee_per_gas: U256,
+        max_fee_per_gas: U256,
+    ) -> Vec<Eip1559TransactionRequest> {
+        orders
+            .into_iter()
+            .enumerate()
+            .map(|(i, (calldata, gas_limit))| {
+                self._order_tx(
+                    calldata,
+                    first_nonce + U256::from(i),
+                    gas_limit,
+                    max_priority_fee_per_gas,
+                    max_fee_per_gas,
+                )
+            })
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod bundler_tests {
     use super::*;
+    use crate::chains::ChainProfile;
     use crate::constants::{GWEI, WEI};
+    use crate::mock::{mock_env, offline_env, serve_fixtures, Fixtures, TEST_BOT_ADDRESS};
+    use crate::order::OrderEncoding;
+    use crate::signer::KeySource;
+    use serde_json::json;
+
+    #[test]
+    fn from_env_returns_startup_errors() {
+        // 找不到 keystore 文件
+        let mut env = mock_env("http://127.0.0.1:1").unwrap();
+        env.signers.executor = KeySource::Keystore {
+            path: "missing-keystore.json".to_string(),
+            password_file: Some("missing-password.txt".to_string()),
+        };
+        let err = Bundler::from_env(env).err().unwrap();
+        assert!(err.to_string().contains("executor"));
+
+        let mut env = mock_env("http://127.0.0.1:1").unwrap();
+        env.https_url = "not a url".to_string();
+        assert!(Bundler::from_env(env).is_err());
+
+        let mut env = mock_env("http://127.0.0.1:1").unwrap();
+        env.bot_address = "0x1234".to_string();
+        let err = Bundler::from_env(env).err().unwrap();
+        assert!(err.to_string().contains("bot address"));
+    }
 
     #[tokio::test]
     async fn bundler_test() {
-        let bundler = Bundler::new();
+        // 在 Polygon 上测试: 配置和 fixture 都是 CHAIN_ID=137, 不连节点
+        let polygon = ChainProfile::polygon();
+        let wmatic = polygon.wrapped_native.address;
+        let usdt = polygon.stables[2].address;
+        let sushiswap_router = polygon.dexes[1].router;
+        let fixtures = Fixtures::default();
+        fixtures.push("eth_chainId", json!("0x89"));
+        fixtures.push("eth_getTransactionCount", json!("0x0"));
+        let url = serve_fixtures(fixtures).await.unwrap();
+        let table = format!(
+            r#"
+            [rpc]
+            https_url = "{url}"
+            wss_url = "ws://127.0.0.1:1"
+
+            [chain]
+            chain_id = 137
+            bot_address = "{TEST_BOT_ADDRESS}"
+            "#
+        );
+        let config = Config::from_table(table.parse().unwrap()).unwrap();
+        let bundler = Bundler::from_env(offline_env(config, &url)).unwrap();
 
-        let tx = bundler
+        let transfer_in = bundler
             .transfer_in_tx(
                 U256::from(5) * *WEI,
                 U256::from(50) * *GWEI,
@@ -294,50 +723,197 @@ mod bundler_tests {
             )
             .await
             .unwrap();
-        // let tx_hash = bundler.send_tx(tx).await?;
-        // println!("{:?}", tx_hash);
-
-        let tx = bundler
+        let transfer_out = bundler
             .transfer_out_tx(
-                "0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270",
+                &format!("{:?}", wmatic),
                 U256::from(50) * *GWEI,
                 U256::from(200) * *GWEI,
             )
             .await
             .unwrap();
-        // let tx_hash = bundler.send_tx(tx).await?;
-        // println!("{:?}", tx_hash);
-
-        let tx = bundler
+        let approve = bundler
             .approve_tx(
-                "0x1b02dA8Cb0d097eB8D57A175b88c7D8b47997506",
-                vec!["0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270"],
+                &format!("{:?}", sushiswap_router),
+                vec![&format!("{:?}", wmatic)],
                 true,
                 U256::from(50) * *GWEI,
                 U256::from(200) * *GWEI,
             )
             .await
             .unwrap();
-        // let tx_hash = bundler.send_tx(tx).await?;
-        // println!("{:?}", tx_hash);
-
         let paths = vec![PathParam {
-            router: Address::from_str("0x1b02dA8Cb0d097eB8D57A175b88c7D8b47997506").unwrap(),
-            token_in: Address::from_str("0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270").unwrap(),
-            token_out: Address::from_str("0xc2132D05D31c914a87C6611C10748AEb04B58e8F").unwrap(),
+            router: sushiswap_router,
+            token_in: wmatic,
+            token_out: usdt,
         }];
-        let tx = bundler
+        let order = bundler
             .order_tx(
-                paths,
+                paths.clone(),
                 U256::from(1) * *WEI,
                 Flashloan::Balancer,
-                Address::from_str("0xBA12222222228d8Ba445958a75a0704d566BF2C8").unwrap(),
+                polygon.balancer_vault.unwrap(),
+                U256::from(600000),
                 U256::from(100) * *GWEI,
                 U256::from(300) * *GWEI,
             )
             .await
             .unwrap();
-        // let tx_hash = bundler.send_tx(tx).await?;
-        // println!("{:?}", tx_hash);
+
+        for tx in [&transfer_in, &transfer_out, &approve, &order] {
+            assert_eq!(tx.chain_id, Some(U64::from(137)));
+        }
+        assert_eq!(transfer_in.value, Some(U256::from(5) * *WEI));
+        assert_eq!(
+            order.data,
+            Some(
+                bundler
+                    .order_calldata(
+                        paths,
+                        U256::from(1) * *WEI,
+                        Flashloan::Balancer,
+                        polygon.balancer_vault.unwrap()
+                    )
+                    .unwrap()
+            )
+        );
+    }
+
+    async fn mock_bundler() -> (Bundler, Fixtures) {
+        let fixtures = Fixtures::from_json(include_str!("../fixtures/bundler.json")).unwrap();
+        let url = serve_fixtures(fixtures.clone()).await.unwrap();
+        (
+            Bundler::from_env(mock_env(&url).unwrap()).unwrap(),
+            fixtures,
+        )
+    }
+
+    #[tokio::test]
+    async fn builds_txs_offline() {
+        let ethereum = ChainProfile::ethereum();
+        let weth = ethereum.wrapped_native.address;
+        let usdc = ethereum.base_token().address;
+        let router = ethereum.dexes[0].router;
+        let (mut bundler, fixtures) = mock_bundler().await;
+        let bot = NameOrAddress::Address(TEST_BOT_ADDRESS.parse().unwrap());
+        let (priority_fee, max_fee) = (U256::from(2) * *GWEI, U256::from(100) * *GWEI);
+
+        let transfer_in = bundler
+            .transfer_in_tx(*WEI, priority_fee, max_fee)
+            .await
+            .unwrap();
+        let transfer_out = bundler
+            .transfer_out_tx(&format!("{:?}", weth), priority_fee, max_fee)
+            .await
+            .unwrap();
+        let approve = bundler
+            .approve_tx(
+                &format!("{:?}", router),
+                vec![&format!("{:?}", weth), &format!("{:?}", usdc)],
+                true,
+                priority_fee,
+                max_fee,
+            )
+            .await
+            .unwrap();
+        let paths = vec![PathParam {
+            router,
+            token_in: usdc,
+            token_out: weth,
+        }];
+        let order = bundler
+            .order_tx(
+                paths.clone(),
+                U256::from(1000),
+                Flashloan::NotUsed,
+                Address::zero(),
+                U256::from(600000),
+                priority_fee,
+                max_fee,
+            )
+            .await
+            .unwrap();
+
+        // nonce 只向节点取一次, 之后依次递增
+        assert_eq!(fixtures.requests("eth_getTransactionCount").len(), 1);
+        let txs = [&transfer_in, &transfer_out, &approve, &order];
+        for (i, tx) in txs.iter().enumerate() {
+            assert_eq!(tx.nonce, Some(U256::from(7 + i)));
+            assert_eq!(tx.from, Some(bundler.sender.address()));
+            assert_eq!(tx.to, Some(bot.clone()));
+            assert_eq!(tx.chain_id, Some(U64::from(1)));
+        }
+        assert_eq!(transfer_in.value, Some(*WEI));
+        assert_eq!(approve.gas, Some(U256::from(110000)));
+        assert_eq!(
+            order.data,
+            Some(
+                bundler
+                    .order_calldata(
+                        paths.clone(),
+                        U256::from(1000),
+                        Flashloan::NotUsed,
+                        Address::zero()
+                    )
+                    .unwrap()
+            )
+        );
+
+        // 同一个 bundle 的套利交易和小费用 reserve_bundle_nonces 一起分配
+        // bundle 从没上链的 nonce 开始, 不接在上面单独构建的交易后面
+        let first_nonce = bundler.reserve_bundle_nonces(2).await.unwrap();
+        assert_eq!(first_nonce, U256::from(7));
+        let tip = bundler.coinbase_tip_tx(
+            Address::zero(),
+            *GWEI,
+            first_nonce + 1,
+            priority_fee,
+            max_fee,
+        );
+        assert_eq!(tip.nonce, Some(U256::from(8)));
+        let signed = bundler.sign_tx(tip).await.unwrap();
+        assert_eq!(
+            decode_signed_tx(&signed).unwrap().from(),
+            Some(&bundler.sender.address())
+        );
+
+        // 紧凑编码时 router 没登记: 返回错误, 不退回 abi 编码
+        bundler.env.config.order.encoding = OrderEncoding::Packed;
+        assert!(bundler
+            .order_calldata(
+                paths.clone(),
+                U256::from(1000),
+                Flashloan::NotUsed,
+                Address::zero()
+            )
+            .is_err());
+        assert!(bundler
+            .order_tx(
+                paths,
+                U256::from(1000),
+                Flashloan::NotUsed,
+                Address::zero(),
+                U256::from(600000),
+                priority_fee,
+                max_fee,
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn simulates_and_broadcasts_offline() {
+        let (bundler, fixtures) = mock_bundler().await;
+        let nonce = bundler.reserve_bundle_nonces(1).await.unwrap();
+        let tip = bundler.coinbase_tip_tx(Address::zero(), *GWEI, nonce, *GWEI, *GWEI * 100);
+        let signed = bundler.sign_tx(tip).await.unwrap();
+        let bundles = bundler.to_bundles(vec![signed], U64::from(100), 2);
+        assert_eq!(bundles.len(), 2);
+        assert_eq!(bundles[1].block(), Some(U64::from(102)));
+
+        let (_, results) = bundler.send_bundle(&bundles[0], None).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].result.as_ref().unwrap().is_some());
+        assert_eq!(fixtures.requests("eth_callBundle").len(), 1);
+        assert_eq!(fixtures.requests("eth_sendBundle").len(), 1);
     }
 }
@@ -0,0 +1,345 @@
+use ethers::types::{Address, H160};
+use std::{str::FromStr, time::Duration};
+
+use crate::builders::{BuilderConfig, SigningScheme};
+use crate::config::default_builders;
+use crate::pools::DexVariant;
+
+// 内置的链配置, 只设置 CHAIN_ID 就能启动; 配置文件里写了的字段优先
+// 地址都来自各项目的官方部署文档
+#[derive(Debug, Clone)]
+pub struct ChainProfile {
+    pub chain_id: u64,
+    pub name: String,
+    pub wrapped_native: TokenInfo,
+    pub stables: Vec<TokenInfo>, // 第一个是默认的 base token
+    pub dexes: Vec<DexProfile>,  // V2 DEX 默认都会同步
+    pub multicall3: Address,
+    pub balancer_vault: Option<Address>,
+    pub aave_pool: Option<Address>, // 只是记录, 合约还没有 Aave 的回调
+    pub block_time: Duration,
+    // 有没有 Flashbots 风格的 bundle (eth_sendBundle), 没有的话 builders 可以为空
+    pub supports_bundles: bool,
+    pub builders: Vec<BuilderConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenInfo {
+    pub symbol: String,
+    pub address: Address,
+    pub decimals: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct DexProfile {
+    pub name: String,
+    pub variant: DexVariant,
+    pub factory: Address,
+    pub router: Address,
+    pub start_block: u64,
+    // V2 是 cfmms 的单位 (300 = 0.3%), V3 是池子的 fee (500 = 0.05%)
+    pub fee_tiers: Vec<u32>,
+}
+
+fn address(addr: &str) -> Address {
+    H160::from_str(addr).unwrap()
+}
+
+fn token(symbol: &str, addr: &str, decimals: u8) -> TokenInfo {
+    TokenInfo {
+        symbol: symbol.to_string(),
+        address: address(addr),
+        decimals,
+    }
+}
+
+fn dex(
+    name: &str,
+    variant: DexVariant,
+    factory: &str,
+    router: &str,
+    start_block: u64,
+    fee_tiers: Vec<u32>,
+) -> DexProfile {
+    DexProfile {
+        name: name.to_string(),
+        variant,
+        factory: address(factory),
+        router: address(router),
+        start_block,
+        fee_tiers,
+    }
+}
+
+// 所有链上地址相同
+const MULTICALL3: &str = "0xcA11bde05977b3631167028862bE2a173976CA11";
+const BALANCER_VAULT: &str = "0xBA12222222228d8Ba445958a75a0704d566BF2C8";
+const UNISWAP_V3_FEE_TIERS: [u32; 4] = [100, 500, 3000, 10000];
+
+impl ChainProfile {
+    pub fn get(chain_id: u64) -> Option<Self> {
+        Self::all()
+            .into_iter()
+            .find(|profile| profile.chain_id == chain_id)
+    }
+
+    pub fn all() -> Vec<Self> {
+        vec![
+            Self::ethereum(),
+            Self::polygon(),
+            Self::arbitrum(),
+            Self::base(),
+            Self::bsc(),
+        ]
+    }
+
+    pub fn ethereum() -> Self {
+        Self {
+            chain_id: 1,
+            name: "ethereum".to_string(),
+            wrapped_native: token("WETH", "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", 18),
+            stables: vec![
+                token("USDC", "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", 6),
+                token("USDT", "0xdAC17F958D2ee523a2206206994597C13D831ec7", 6),
+                token("DAI", "0x6B175474E89094C44Da98b810eCe1fC6f2e6A10C", 18),
+            ],
+            dexes: vec![
+                dex(
+                    "sushiswap",
+                    DexVariant::UniswapV2,
+                    "0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac",
+                    "0xd9e1cE17f2641f24aE83637ab66a2cca9C378B9F",
+                    10794229,
+                    vec![300],
+                ),
+                dex(
+                    "uniswap_v2",
+                    DexVariant::UniswapV2,
+                    "0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f",
+                    "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D",
+                    10000835,
+                    vec![300],
+                ),
+                dex(
+                    "uniswap_v3",
+                    DexVariant::UniswapV3,
+                    "0x1F98431c8aD98523631AE4a59f267346ea31F984",
+                    "0x68b3465833fb72A70ecDF485E0e4C7bD8665Fc45",
+                    12369621,
+                    UNISWAP_V3_FEE_TIERS.to_vec(),
+                ),
+            ],
+            multicall3: address(MULTICALL3),
+            balancer_vault: Some(address(BALANCER_VAULT)),
+            aave_pool: Some(address("0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2")),
+            block_time: Duration::from_secs(12),
+            supports_bundles: true,
+            builders: default_builders(),
+        }
+    }
+
+    pub fn polygon() -> Self {
+        Self {
+            chain_id: 137,
+            name: "polygon".to_string(),
+            wrapped_native: token("WMATIC", "0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270", 18),
+            stables: vec![
+                token("USDC", "0x3c499c542cEF5E3811e1192ce70d8cC03d5c3359", 6),
+                token("USDC.e", "0x2791Bca1f2de4661ED88A30C99A7a9449Aa84174", 6),
+                token("USDT", "0xc2132D05D31c914a87C6611C10748AEb04B58e8F", 6),
+                token("DAI", "0x8f3Cf7ad23Cd3CaDbD9735AFf958023239c6A063", 18),
+            ],
+            dexes: vec![
+                dex(
+                    "quickswap",
+                    DexVariant::UniswapV2,
+                    "0x5757371414417b8C6CAad45bAeF941aBc7d3Ab32",
+                    "0xa5E0829CaCEd8fFDD4De3c43696c57F7D7A678ff",
+                    4931780,
+                    vec![300],
+                ),
+                dex(
+                    "sushiswap",
+                    DexVariant::UniswapV2,
+                    "0xc35DADB65012eC5796536bD9864eD8773aBc74C4",
+                    "0x1b02dA8Cb0d097eB8D57A175b88c7D8b47997506",
+                    11333218,
+                    vec![300],
+                ),
+                dex(
+                    "uniswap_v3",
+                    DexVariant::UniswapV3,
+                    "0x1F98431c8aD98523631AE4a59f267346ea31F984",
+                    "0x68b3465833fb72A70ecDF485E0e4C7bD8665Fc45",
+                    22757547,
+                    UNISWAP_V3_FEE_TIERS.to_vec(),
+                ),
+            ],
+            multicall3: address(MULTICALL3),
+            balancer_vault: Some(address(BALANCER_VAULT)),
+            aave_pool: Some(address("0x794a61358D6845594F94dc1DB02A252b5b4814aD")),
+            block_time: Duration::from_secs(2),
+            supports_bundles: false,
+            builders: Vec::new(),
+        }
+    }
+
+    pub fn arbitrum() -> Self {
+        Self {
+            chain_id: 42161,
+            name: "arbitrum".to_string(),
+            wrapped_native: token("WETH", "0x82aF49447D8a07e3bd95BD0d56f35241523fBab1", 18),
+            stables: vec![
+                token("USDC", "0xaf88d065e77c8cC2239327C5EDb3A432268e5831", 6),
+                token("USDC.e", "0xFF970A61A04b1cA14834A43f5dE4533eBDDB5CC8", 6),
+                token("USDT", "0xFd086bC7CD5C481DCC9C85ebE478A1C0b69FCbb9", 6),
+                token("DAI", "0xDA10009cBd5D07dd0CeCc66161FC93D7c9000da1", 18),
+            ],
+            dexes: vec![
+                dex(
+                    "sushiswap",
+                    DexVariant::UniswapV2,
+                    "0xc35DADB65012eC5796536bD9864eD8773aBc74C4",
+                    "0x1b02dA8Cb0d097eB8D57A175b88c7D8b47997506",
+                    70,
+                    vec![300],
+                ),
+                dex(
+                    "uniswap_v3",
+                    DexVariant::UniswapV3,
+                    "0x1F98431c8aD98523631AE4a59f267346ea31F984",
+                    "0x68b3465833fb72A70ecDF485E0e4C7bD8665Fc45",
+                    165,
+                    UNISWAP_V3_FEE_TIERS.to_vec(),
+                ),
+            ],
+            multicall3: address(MULTICALL3),
+            balancer_vault: Some(address(BALANCER_VAULT)),
+            aave_pool: Some(address("0x794a61358D6845594F94dc1DB02A252b5b4814aD")),
+            block_time: Duration::from_millis(250),
+            supports_bundles: false,
+            builders: Vec::new(),
+        }
+    }
+
+    pub fn base() -> Self {
+        Self {
+            chain_id: 8453,
+            name: "base".to_string(),
+            wrapped_native: token("WETH", "0x4200000000000000000000000000000000000006", 18),
+            stables: vec![
+                token("USDC", "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913", 6),
+                token("USDbC", "0xd9aAEc86B65D86f6A7B5B1b0c42FFA531710b6CA", 6),
+                token("DAI", "0x50c5725949A6F0c72E6C4a641F24049A917DB0Cb", 18),
+            ],
+            dexes: vec![
+                dex(
+                    "uniswap_v2",
+                    DexVariant::UniswapV2,
+                    "0x8909Dc15e40173Ff4699343b6eB8132c65e18eC6",
+                    "0x4752ba5DBc23f44D87826276BF6Fd6b1C372aD24",
+                    6601915,
+                    vec![300],
+                ),
+                dex(
+                    "uniswap_v3",
+                    DexVariant::UniswapV3,
+                    "0x33128a8fC17869897dcE68Ed026d694621f6FDfD",
+                    "0x2626664c2603336E57B271c5C0b26F421741e481",
+                    1371680,
+                    UNISWAP_V3_FEE_TIERS.to_vec(),
+                ),
+            ],
+            multicall3: address(MULTICALL3),
+            balancer_vault: Some(address(BALANCER_VAULT)),
+            aave_pool: Some(address("0xA238Dd80C259a72e81d7e4664a9801593F98d1c5")),
+            block_time: Duration::from_secs(2),
+            supports_bundles: false,
+            builders: Vec::new(),
+        }
+    }
+
+    pub fn bsc() -> Self {
+        Self {
+            chain_id: 56,
+            name: "bsc".to_string(),
+            wrapped_native: token("WBNB", "0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c", 18),
+            // BSC 上的稳定币都是 18 位
+            stables: vec![
+                token("USDT", "0x55d398326f99059fF775485246999027B3197955", 18),
+                token("USDC", "0x8AC76a51cc950d9822D68b83fE1Ad97B32Cd580d", 18),
+            ],
+            dexes: vec![
+                dex(
+                    "pancakeswap_v2",
+                    DexVariant::UniswapV2,
+                    "0xcA143Ce32Fe78f1f7019d7d551a6402fC5350c73",
+                    "0x10ED43C718714eb63d5aA57B78B54704E256024E",
+                    6809737,
+                    vec![250],
+                ),
+                dex(
+                    "pancakeswap_v3",
+                    DexVariant::UniswapV3,
+                    "0x0BFbCF9fa4f9C56B0F40a671Ad40E0805A091865",
+                    "0x13f4EA83D0bd40E75C8222255bc855a974568Dd4",
+                    26956207,
+                    vec![100, 500, 2500, 10000],
+                ),
+            ],
+            multicall3: address(MULTICALL3),
+            balancer_vault: None,
+            aave_pool: Some(address("0x6807dc923806fE8Fd134338EABCA509979a7e0cB")),
+            block_time: Duration::from_millis(750),
+            supports_bundles: true,
+            builders: vec![BuilderConfig::new(
+                "48club",
+                "https://puissant-builder.48.club",
+                SigningScheme::None,
+                vec!["eth_sendBundle"],
+            )],
+        }
+    }
+
+    pub fn base_token(&self) -> &TokenInfo {
+        &self.stables[0]
+    }
+
+    // 按符号找包装原生代币或者稳定币
+    pub fn token(&self, symbol: &str) -> Option<&TokenInfo> {
+        std::iter::once(&self.wrapped_native)
+            .chain(self.stables.iter())
+            .find(|token| token.symbol == symbol)
+    }
+
+    pub fn v2_dexes(&self) -> Vec<&DexProfile> {
+        self.dexes
+            .iter()
+            .filter(|dex| matches!(dex.variant, DexVariant::UniswapV2))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod chains_tests {
+    use super::*;
+
+    #[test]
+    fn profiles_are_complete() {
+        let profiles = ChainProfile::all();
+        assert_eq!(profiles.len(), 5);
+        for profile in &profiles {
+            assert!(!profile.stables.is_empty(), "{}", profile.name);
+            assert!(!profile.v2_dexes().is_empty(), "{}", profile.name);
+            assert!(profile.dexes.iter().all(|dex| !dex.fee_tiers.is_empty()));
+            assert_eq!(profile.supports_bundles, !profile.builders.is_empty());
+            let same_chain = profiles.iter().filter(|p| p.chain_id == profile.chain_id);
+            assert_eq!(same_chain.count(), 1);
+        }
+        assert_eq!(
+            ChainProfile::get(137).unwrap().wrapped_native.symbol,
+            "WMATIC"
+        );
+        assert!(ChainProfile::get(10).is_none());
+    }
+}
@@ -0,0 +1,1106 @@
+use anyhow::{anyhow, Result};
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Duration};
+
+use crate::bidding::{Bid, BidMode, BidPolicy};
+use crate::builders::{BuilderConfig, SigningScheme};
+use crate::chains::{ChainProfile, DexProfile, TokenInfo};
+use crate::constants::get_blacklist_tokens;
+use crate::order::{OrderEncoding, RouterRegistry};
+
+// 配置文件 (TOML), 路径由 CONFIG_PATH 指定, 默认 config.toml; 文件不存在时全部用默认值
+// 环境变量可以覆盖文件里的值:
+// - 原来的 HTTPS_URL / WSS_URL / CHAIN_ID / BOT_ADDRESS / BUILDERS / ORDER_ENCODING / ROUTER_REGISTRY
+// - 任意字段: MEV__<SECTION>__<FIELD>, 例如 MEV__RISK__MAX_FEE_PER_GAS_GWEI=300
+// 私钥不放在配置文件里, [signers] 只写 keystore 或者远程签名服务, 见 signer::load_signer_config
+// 没写的 base_token / dexes / builders 等字段按 chain_id 从 chains::ChainProfile 里取
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub rpc: RpcConfig,
+    #[serde(default)]
+    pub chain: ChainConfig,
+    #[serde(default)]
+    pub base_token: BaseTokenConfig,
+    #[serde(default = "default_dexes")]
+    pub dexes: Vec<DexConfig>,
+    #[serde(default)]
+    pub thresholds: Thresholds,
+    #[serde(default = "default_builders")]
+    pub builders: Vec<BuilderConfig>,
+    #[serde(default)]
+    pub risk: RiskLimits,
+    #[serde(default)]
+    pub submission: SubmissionConfig,
+    #[serde(default)]
+    pub mev_share: MevShareConfig,
+    #[serde(default)]
+    pub dry_run: DryRunConfig,
+    #[serde(default)]
+    pub recorder: RecorderConfig,
+    #[serde(default)]
+    pub order: OrderConfig,
+    #[serde(default)]
+    pub signers: SignersConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RpcConfig {
+    pub https_url: String,
+    pub wss_url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainConfig {
+    pub chain_id: u64,
+    pub bot_address: Address,
+    pub block_time_ms: u64,
+    pub supports_bundles: bool,
+    // WETH / WMATIC / WBNB ..., 给 gas 换算成基础代币用; 没有 profile 的链必须自己写
+    pub wrapped_native: Address,
+}
+
+impl ChainConfig {
+    pub fn block_time(&self) -> Duration {
+        Duration::from_millis(self.block_time_ms)
+    }
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self {
+            chain_id: 1,
+            bot_address: Address::zero(),
+            block_time_ms: 12000,
+            supports_bundles: true,
+            wrapped_native: Address::zero(),
+        }
+    }
+}
+
+// 套利的起点和利润代币
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BaseTokenConfig {
+    pub address: Address,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+impl Default for BaseTokenConfig {
+    fn default() -> Self {
+        Self::from(ChainProfile::ethereum().base_token())
+    }
+}
+
+impl From<&TokenInfo> for BaseTokenConfig {
+    fn from(token: &TokenInfo) -> Self {
+        Self {
+            address: token.address,
+            symbol: token.symbol.clone(),
+            decimals: token.decimals,
+        }
+    }
+}
+
+// Uniswap V2 类的 DEX: 从 factory 的 start_block 开始同步池子
+// 下单时每一跳走池子所在 DEX 的 router
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DexConfig {
+    pub name: String,
+    pub factory: Address,
+    pub router: Address,
+    pub start_block: u64,
+    #[serde(default = "default_dex_fee")]
+    pub fee: u32, // cfmms 的单位, 300 = 0.3%
+}
+
+fn default_dex_fee() -> u32 {
+    300
+}
+
+impl From<&DexProfile> for DexConfig {
+    fn from(dex: &DexProfile) -> Self {
+        Self {
+            name: dex.name.clone(),
+            factory: dex.factory,
+            router: dex.router,
+            start_block: dex.start_block,
+            fee: dex
+                .fee_tiers
+                .first()
+                .cloned()
+                .unwrap_or_else(default_dex_fee),
+        }
+    }
+}
+
+fn default_dexes() -> Vec<DexConfig> {
+    profile_dexes(&ChainProfile::ethereum())
+}
+
+// 主网出块最多的几个 builder
+pub fn default_builders() -> Vec<BuilderConfig> {
+    vec![
+        BuilderConfig::new(
+            "flashbots",
+            "https://relay.flashbots.net",
+            SigningScheme::Flashbots,
+            vec![
+                "eth_sendBundle",
+                "eth_callBundle",
+                "eth_cancelBundle",
+                "mev_sendBundle",
+                "flashbots_getBundleStatsV2",
+            ],
+        ),
+        BuilderConfig::new(
+            "beaverbuild",
+            "https://rpc.beaverbuild.org",
+            SigningScheme::None,
+            vec!["eth_sendBundle"],
+        ),
+        BuilderConfig::new(
+            "titan",
+            "https://rpc.titanbuilder.xyz",
+            SigningScheme::None,
+            vec!["eth_sendBundle", "eth_cancelBundle"],
+        ),
+        BuilderConfig::new(
+            "rsync",
+            "https://rsync-builder.xyz",
+            SigningScheme::Flashbots,
+            vec!["eth_sendBundle", "eth_cancelBundle"],
+        ),
+    ]
+}
+
+// 同步 profile 里所有的 V2 DEX
+fn profile_dexes(profile: &ChainProfile) -> Vec<DexConfig> {
+    profile
+        .v2_dexes()
+        .into_iter()
+        .map(DexConfig::from)
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Thresholds {
+    pub min_profit: u64, // 扣除 gas 和闪电贷手续费后的最低利润, 基础代币的最小单位
+    pub target_blocks: u64,
+    pub profit_share_bps: u64, // 初始出价占预期利润的比例
+    pub bid_mode: BidMode,
+    pub bid_floor_gwei: u64,           // 出价总额下限
+    pub bid_ceiling_gwei: Option<u64>, // 出价总额上限, 不写就不限制
+    pub use_flashloan: bool,
+    pub use_access_list: bool,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            min_profit: 0,
+            target_blocks: 2,
+            profit_share_bps: 5000,
+            bid_mode: BidMode::PriorityFee,
+            bid_floor_gwei: 0,
+            bid_ceiling_gwei: None,
+            use_flashloan: true,
+            use_access_list: true,
+        }
+    }
+}
+
+impl Thresholds {
+    // 出价方式和金额范围来自配置, 自适应调整的参数用默认值
+    pub fn bid_policy(&self) -> BidPolicy {
+        BidPolicy {
+            mode: self.bid_mode.clone(),
+            profit_share_bps: self.profit_share_bps,
+            floor: U256::from(self.bid_floor_gwei) * U256::exp10(9),
+            ceiling: self
+                .bid_ceiling_gwei
+                .map(|ceiling| U256::from(ceiling) * U256::exp10(9))
+                .unwrap_or(U256::MAX),
+            ..BidPolicy::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskLimits {
+    pub max_amount_in: u64, // 单笔套利的最大输入, 基础代币的整数单位
+    pub max_fee_per_gas_gwei: u64,
+    pub max_orders_per_bundle: usize,
+    pub blacklist_tokens: Vec<Address>,
+}
+
+impl RiskLimits {
+    pub fn max_fee_per_gas(&self) -> U256 {
+        U256::from(self.max_fee_per_gas_gwei) * U256::exp10(9)
+    }
+}
+
+impl Default for RiskLimits {
+    fn default() -> Self {
+        Self {
+            max_amount_in: 1_000_000,
+            max_fee_per_gas_gwei: 1000,
+            max_orders_per_bundle: 5,
+            blacklist_tokens: get_blacklist_tokens(),
+        }
+    }
+}
+
+// 套利交易怎么提交
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubmissionMode {
+    // eth_sendBundle 给 builder
+    Bundle,
+    // eth_sendPrivateTransaction, 例如 Flashbots Protect 之类的私有 RPC
+    Private,
+    // eth_sendRawTransactionConditional, Arbitrum / OP Stack 的 sequencer 支持
+    Conditional,
+    // eth_sendRawTransaction, 进公开 mempool
+    Public,
+}
+
+// 不是 bundle 的时候交易单独提交, 没有原子性, 各个模式的 fee 也不一样:
+// - private: 私有 RPC 转给 builder, 和 bundle 一样按利润出价
+// - conditional / public: sequencer 先到先得, priority fee 固定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SubmissionConfig {
+    pub mode: SubmissionMode,
+    pub rpc_url: String, // 私有 RPC 或 sequencer 的地址, 空的时候用 rpc.https_url
+    pub signing: SigningScheme,
+    pub priority_fee_wei: u64,
+    pub max_base_fee_multiplier: u64, // max fee = next base fee * multiplier + priority fee
+    // 单独提交前要求 利润 >= 最坏情况的 gas 成本 (gas limit * max fee) * (1 + margin)
+    pub min_profit_margin_bps: u64,
+}
+
+impl Default for SubmissionConfig {
+    fn default() -> Self {
+        Self {
+            mode: SubmissionMode::Bundle,
+            rpc_url: String::new(),
+            signing: SigningScheme::None,
+            priority_fee_wei: 10_000_000,
+            max_base_fee_multiplier: 2,
+            min_profit_margin_bps: 1000,
+        }
+    }
+}
+
+impl SubmissionConfig {
+    pub fn uses_bundles(&self) -> bool {
+        self.mode == SubmissionMode::Bundle
+    }
+
+    // 返回 (max_priority_fee_per_gas, max_fee_per_gas)
+    pub fn fees(&self, bid: &Bid, next_base_fee: U256) -> (U256, U256) {
+        match self.mode {
+            SubmissionMode::Bundle | SubmissionMode::Private => {
+                (bid.max_priority_fee_per_gas, bid.max_fee_per_gas)
+            }
+            SubmissionMode::Conditional | SubmissionMode::Public => {
+                let priority_fee = U256::from(self.priority_fee_wei);
+                let max_fee =
+                    next_base_fee * U256::from(self.max_base_fee_multiplier) + priority_fee;
+                (priority_fee, max_fee)
+            }
+        }
+    }
+
+    // expected_profit 是还没扣 gas 的利润 (wei)
+    pub fn is_profitable(&self, expected_profit: U256, gas_limit: U256, max_fee: U256) -> bool {
+        let max_gas_cost = gas_limit * max_fee;
+        let required = max_gas_cost * U256::from(10000 + self.min_profit_margin_bps) / 10000;
+        expected_profit > required
+    }
+}
+
+// 订阅 MEV-Share 的 hint 事件流, 用 mev_sendBundle 尾随用户的交易 (只有主网)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MevShareConfig {
+    pub enabled: bool,
+    pub url: String,
+    pub refund_percent: u64, // bundle 收益退给用户的比例
+}
+
+impl Default for MevShareConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "https://mev-share.flashbots.net".to_string(),
+            refund_percent: 90,
+        }
+    }
+}
+
+// 模拟盘: 检测、定金额、构建、签名、模拟都照常执行, 但不提交
+// 每笔交易记到 ledger (CSV), 过 reconcile_blocks 个区块后和链上实际发生的对账, 见 paper::PaperExecutor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DryRunConfig {
+    pub enabled: bool,
+    pub ledger_path: String,
+    pub reconcile_blocks: u64,
+}
+
+impl Default for DryRunConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ledger_path: "paper_trades.csv".to_string(),
+            reconcile_blocks: 2,
+        }
+    }
+}
+
+// 把区块、Sync 日志和 pending 交易连同收到的时间录到 dir 下的 JSONL 文件, 见 recorder::EventRecorder
+// 单个文件超过 max_file_mb 换新文件, 最多留 max_files 个 (0 不删)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecorderConfig {
+    pub enabled: bool,
+    pub dir: String,
+    pub max_file_mb: u64,
+    pub max_files: usize,
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: "recordings".to_string(),
+            max_file_mb: 64,
+            max_files: 0,
+        }
+    }
+}
+
+// 下单 calldata 的编码, packed 时 router 用 router_registry 里的下标, 顺序必须和合约一致
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OrderConfig {
+    pub encoding: OrderEncoding,
+    pub router_registry: Vec<Address>,
+}
+
+impl OrderConfig {
+    pub fn registry(&self) -> RouterRegistry {
+        RouterRegistry::new(self.router_registry.clone())
+    }
+}
+
+// 三把钥匙从哪里来, 不写的用环境变量里的私钥
+// 环境变量 EXECUTOR_* / FLASHBOTS_* / ADMIN_* 优先于这里
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SignersConfig {
+    pub executor: Option<KeyConfig>,
+    pub flashbots: Option<KeyConfig>,
+    pub admin: Option<KeyConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum KeyConfig {
+    // 加密的 JSON keystore, 没有密码文件时在终端输入
+    Keystore {
+        path: String,
+        password_file: Option<String>,
+    },
+    // web3signer / clef 一类的远程签名服务
+    Remote {
+        url: String,
+        address: Address,
+    },
+}
+
+impl SignersConfig {
+    fn keys(&self) -> [(&str, &Option<KeyConfig>); 3] {
+        [
+            ("executor", &self.executor),
+            ("flashbots", &self.flashbots),
+            ("admin", &self.admin),
+        ]
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let path = std::env::var("CONFIG_PATH").unwrap_or("config.toml".to_string());
+        let mut value = if Path::new(&path).exists() {
+            let content = std::fs::read_to_string(&path)?;
+            content
+                .parse::<toml::Table>()
+                .map_err(|e| anyhow!("Invalid config file {}: {}", path, e))?
+        } else {
+            toml::Table::new()
+        };
+        apply_env_overrides(&mut value, std::env::vars())?;
+        Self::from_table(value)
+    }
+
+    // 先按 chain_id 补上 profile 里的默认值, 再反序列化和检查
+    pub fn from_table(mut table: toml::Table) -> Result<Self> {
+        let chain_id = table
+            .get("chain")
+            .and_then(|chain| chain.get("chain_id"))
+            .and_then(|chain_id| chain_id.as_integer())
+            .unwrap_or(1) as u64;
+        match ChainProfile::get(chain_id) {
+            Some(profile) => apply_profile_defaults(&mut table, &profile)?,
+            None if !table.contains_key("base_token") || !table.contains_key("dexes") => {
+                return Err(anyhow!(
+                    "Invalid config: chain {} has no built-in profile, base_token, dexes and chain.wrapped_native must be set",
+                    chain_id
+                ));
+            }
+            None => {}
+        }
+        let config: Config = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| anyhow!("Invalid config: {}", e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // 把所有问题一次列出来
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+        if !self.rpc.https_url.starts_with("http://") && !self.rpc.https_url.starts_with("https://")
+        {
+            errors.push("rpc.https_url (HTTPS_URL) must be an http(s) URL".to_string());
+        }
+        if !self.rpc.wss_url.starts_with("ws://") && !self.rpc.wss_url.starts_with("wss://") {
+            errors.push("rpc.wss_url (WSS_URL) must be a ws(s) URL".to_string());
+        }
+        if self.chain.chain_id == 0 {
+            errors.push("chain.chain_id (CHAIN_ID) must not be 0".to_string());
+        }
+        if self.chain.wrapped_native.is_zero() {
+            errors.push("chain.wrapped_native is not set".to_string());
+        }
+        if self.chain.block_time_ms == 0 {
+            errors.push("chain.block_time_ms must be greater than 0".to_string());
+        }
+        if self.chain.bot_address.is_zero() {
+            errors.push("chain.bot_address (BOT_ADDRESS) is not set".to_string());
+        }
+        if self.base_token.address.is_zero() {
+            errors.push("base_token.address is not set".to_string());
+        }
+        if self.base_token.decimals > 36 {
+            errors.push("base_token.decimals must be at most 36".to_string());
+        }
+        if self.dexes.is_empty() {
+            errors.push("dexes must contain at least one DEX".to_string());
+        }
+        for dex in &self.dexes {
+            if dex.factory.is_zero() || dex.router.is_zero() {
+                errors.push(format!(
+                    "dexes.{}: factory and router must be set",
+                    dex.name
+                ));
+            }
+            if dex.fee >= 10000 {
+                errors.push(format!(
+                    "dexes.{}: fee must be below 10000 (300 = 0.3%)",
+                    dex.name
+                ));
+            }
+        }
+        if self.thresholds.target_blocks == 0 {
+            errors.push("thresholds.target_blocks must be at least 1".to_string());
+        }
+        if self.thresholds.profit_share_bps > 10000 {
+            errors.push("thresholds.profit_share_bps must be at most 10000".to_string());
+        }
+        if let Some(ceiling) = self.thresholds.bid_ceiling_gwei {
+            if ceiling < self.thresholds.bid_floor_gwei {
+                errors.push(
+                    "thresholds.bid_ceiling_gwei must not be below bid_floor_gwei".to_string(),
+                );
+            }
+        }
+        if let BidMode::CoinbaseTransfer { recipient } = &self.thresholds.bid_mode {
+            if recipient.is_zero() {
+                errors
+                    .push("thresholds.bid_mode coinbase_transfer recipient is not set".to_string());
+            }
+            // 转账交易单独提交时不能保证和套利一起上链
+            if !self.submission.uses_bundles() {
+                errors.push(
+                    "thresholds.bid_mode coinbase_transfer requires submission.mode = bundle"
+                        .to_string(),
+                );
+            }
+        }
+        if self.submission.uses_bundles() {
+            if !self.chain.supports_bundles {
+                errors.push(
+                    "submission.mode is bundle but the chain has no bundle relay".to_string(),
+                );
+            }
+            if self.builders.is_empty() {
+                errors.push("builders must contain at least one builder".to_string());
+            }
+        }
+        if self.submission.mode == SubmissionMode::Private && self.submission.rpc_url.is_empty() {
+            errors.push("submission.rpc_url must be set for private mode".to_string());
+        }
+        if self.mev_share.enabled {
+            if !self.submission.uses_bundles() {
+                errors.push("mev_share requires submission.mode = bundle".to_string());
+            }
+            if !self.builders.iter().any(|b| b.supports("mev_sendBundle")) {
+                errors
+                    .push("mev_share requires a builder that supports mev_sendBundle".to_string());
+            }
+            if self.mev_share.refund_percent > 100 {
+                errors.push("mev_share.refund_percent must be at most 100".to_string());
+            }
+        }
+        if self.dry_run.enabled {
+            if self.dry_run.ledger_path.is_empty() {
+                errors.push("dry_run.ledger_path must be set".to_string());
+            }
+            if self.dry_run.reconcile_blocks == 0 {
+                errors.push("dry_run.reconcile_blocks must be at least 1".to_string());
+            }
+        }
+        if self.recorder.enabled {
+            if self.recorder.dir.is_empty() {
+                errors.push("recorder.dir must be set".to_string());
+            }
+            if self.recorder.max_file_mb == 0 {
+                errors.push("recorder.max_file_mb must be at least 1".to_string());
+            }
+        }
+        if self.submission.max_base_fee_multiplier == 0 {
+            errors.push("submission.max_base_fee_multiplier must be at least 1".to_string());
+        }
+        for builder in &self.builders {
+            if !builder.url.starts_with("http://") && !builder.url.starts_with("https://") {
+                errors.push(format!(
+                    "builders.{}: url must be an http(s) URL",
+                    builder.name
+                ));
+            }
+        }
+        if self.risk.max_amount_in == 0 {
+            errors.push("risk.max_amount_in must be greater than 0".to_string());
+        }
+        if self.risk.max_fee_per_gas_gwei == 0 {
+            errors.push("risk.max_fee_per_gas_gwei must be greater than 0".to_string());
+        }
+        if self.risk.max_orders_per_bundle == 0 {
+            errors.push("risk.max_orders_per_bundle must be at least 1".to_string());
+        }
+        if self.order.encoding == OrderEncoding::Packed {
+            if self.order.router_registry.is_empty() {
+                errors.push("order.encoding = packed requires order.router_registry".to_string());
+            }
+            if self.order.router_registry.len() > 256 {
+                errors.push("order.router_registry must have at most 256 routers".to_string());
+            }
+            for dex in &self.dexes {
+                if !self.order.router_registry.contains(&dex.router) {
+                    errors.push(format!(
+                        "dexes.{}: router is not in order.router_registry",
+                        dex.name
+                    ));
+                }
+            }
+        }
+        for (name, key) in self.signers.keys() {
+            match key {
+                Some(KeyConfig::Keystore { path, .. }) if path.is_empty() => {
+                    errors.push(format!("signers.{}.path must be set", name));
+                }
+                Some(KeyConfig::Remote { url, address }) => {
+                    if !url.starts_with("http://") && !url.starts_with("https://") {
+                        errors.push(format!("signers.{}.url must be an http(s) URL", name));
+                    }
+                    if address.is_zero() {
+                        errors.push(format!("signers.{}.address must be set", name));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("Invalid config:\n  - {}", errors.join("\n  - ")))
+        }
+    }
+
+    pub fn routers(&self) -> Vec<Address> {
+        self.dexes.iter().map(|dex| dex.router).collect()
+    }
+
+    // 打印用: RPC 和 builder 的 URL 里常带 API key, 只保留协议和域名
+    pub fn redacted(&self) -> String {
+        let mut config = self.clone();
+        config.rpc.https_url = redact_url(&config.rpc.https_url);
+        config.rpc.wss_url = redact_url(&config.rpc.wss_url);
+        if !config.submission.rpc_url.is_empty() {
+            config.submission.rpc_url = redact_url(&config.submission.rpc_url);
+        }
+        for builder in &mut config.builders {
+            builder.url = redact_url(&builder.url);
+        }
+        for key in [
+            &mut config.signers.executor,
+            &mut config.signers.flashbots,
+            &mut config.signers.admin,
+        ] {
+            if let Some(KeyConfig::Remote { url, .. }) = key {
+                *url = redact_url(url);
+            }
+        }
+        toml::to_string_pretty(&config).unwrap_or_default()
+    }
+}
+
+// 配置文件和环境变量里都没有的字段用 profile 的值
+fn apply_profile_defaults(table: &mut toml::Table, profile: &ChainProfile) -> Result<()> {
+    let chain = table
+        .entry("chain")
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+        .as_table_mut()
+        .ok_or(anyhow!("Invalid config: chain is not a table"))?;
+    chain
+        .entry("wrapped_native")
+        .or_insert(toml::Value::String(format!(
+            "{:?}",
+            profile.wrapped_native.address
+        )));
+    chain
+        .entry("block_time_ms")
+        .or_insert(toml::Value::Integer(profile.block_time.as_millis() as i64));
+    let supports_bundles = chain
+        .entry("supports_bundles")
+        .or_insert(toml::Value::Boolean(profile.supports_bundles))
+        .as_bool()
+        .unwrap_or(profile.supports_bundles);
+    // 没有 bundle relay 的链默认走 sequencer
+    let submission = table
+        .entry("submission")
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+        .as_table_mut()
+        .ok_or(anyhow!("Invalid config: submission is not a table"))?;
+    if !submission.contains_key("mode") {
+        let mode = match supports_bundles {
+            true => SubmissionMode::Bundle,
+            false => SubmissionMode::Conditional,
+        };
+        submission.insert("mode".to_string(), toml::Value::try_from(mode)?);
+    }
+    if !table.contains_key("base_token") {
+        let base_token = BaseTokenConfig::from(profile.base_token());
+        table.insert("base_token".to_string(), toml::Value::try_from(base_token)?);
+    }
+    if !table.contains_key("dexes") {
+        let dexes = profile_dexes(profile);
+        table.insert("dexes".to_string(), toml::Value::try_from(dexes)?);
+    }
+    if !table.contains_key("builders") {
+        let builders = profile.builders.clone();
+        table.insert("builders".to_string(), toml::Value::try_from(builders)?);
+    }
+    Ok(())
+}
+
+pub fn redact_url(url: &str) -> String {
+    let (scheme, rest) = match url.split_once("://") {
+        Some(parts) => parts,
+        None => return "<redacted>".to_string(),
+    };
+    // user:password@host
+    let host = rest.split('/').next().unwrap_or_default();
+    let host = host.rsplit('@').next().unwrap_or_default();
+    let host = host.split('?').next().unwrap_or_default();
+    if host.len() + scheme.len() + 3 == url.len() {
+        format!("{}://{}", scheme, host)
+    } else {
+        format!("{}://{}/<redacted>", scheme, host)
+    }
+}
+
+// 原来的环境变量 -> 配置路径
+const LEGACY_ENV: [(&str, &str, &str); 5] = [
+    ("HTTPS_URL", "rpc", "https_url"),
+    ("WSS_URL", "rpc", "wss_url"),
+    ("CHAIN_ID", "chain", "chain_id"),
+    ("BOT_ADDRESS", "chain", "bot_address"),
+    ("ORDER_ENCODING", "order", "encoding"),
+];
+
+pub fn apply_env_overrides(
+    table: &mut toml::Table,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<()> {
+    for (key, value) in vars {
+        let path: Vec<String> = if let Some(path) = key.strip_prefix("MEV__") {
+            path.split("__").map(|part| part.to_lowercase()).collect()
+        } else if key == "BUILDERS" {
+            // JSON 格式的 builder 列表
+            let builders: Vec<BuilderConfig> =
+                serde_json::from_str(&value).map_err(|e| anyhow!("Invalid BUILDERS: {}", e))?;
+            table.insert("builders".to_string(), toml::Value::try_from(builders)?);
+            continue;
+        } else if key == "ROUTER_REGISTRY" {
+            // 逗号分隔的 router 地址
+            let routers: Vec<toml::Value> = value
+                .split(',')
+                .map(|router| router.trim())
+                .filter(|router| !router.is_empty())
+                .map(|router| toml::Value::String(router.to_string()))
+                .collect();
+            let order = table
+                .entry("order")
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .ok_or(anyhow!("Config override {}: order is not a table", key))?;
+            order.insert("router_registry".to_string(), toml::Value::Array(routers));
+            continue;
+        } else {
+            match LEGACY_ENV.iter().find(|(name, _, _)| *name == key) {
+                Some((_, section, field)) => vec![section.to_string(), field.to_string()],
+                None => continue,
+            }
+        };
+        if path.len() != 2 || path.iter().any(|part| part.is_empty()) {
+            return Err(anyhow!(
+                "Invalid config override {}: expected MEV__SECTION__FIELD",
+                key
+            ));
+        }
+        let section = table
+            .entry(path[0].clone())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or(anyhow!(
+                "Config override {}: {} is not a table",
+                key,
+                path[0]
+            ))?;
+        section.insert(path[1].clone(), parse_override(&value));
+    }
+    Ok(())
+}
+
+// 数字、布尔值、数组按 TOML 解析, 其他的 (包括 0x 开头的地址) 当作字符串
+fn parse_override(value: &str) -> toml::Value {
+    if value.starts_with("0x") {
+        return toml::Value::String(value.to_string());
+    }
+    match format!("value = {}", value).parse::<toml::Table>() {
+        Ok(mut table) => table.remove("value").unwrap(),
+        Err(_) => toml::Value::String(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn file_with_env_overrides() {
+        let mut table: toml::Table = r#"
+            [rpc]
+            https_url = "https://eth-mainnet.example.com/v2/secret-key"
+            wss_url = "wss://eth-mainnet.example.com/v2/secret-key"
+
+            [chain]
+            bot_address = "0x00000000000000000000000000000000000000b0"
+
+            [risk]
+            max_orders_per_bundle = 2
+        "#
+        .parse()
+        .unwrap();
+        apply_env_overrides(
+            &mut table,
+            vars(&[
+                ("CHAIN_ID", "137"),
+                ("MEV__RISK__MAX_FEE_PER_GAS_GWEI", "300"),
+                ("MEV__THRESHOLDS__USE_FLASHLOAN", "false"),
+                ("PATH", "/usr/bin"),
+            ]),
+        )
+        .unwrap();
+        let config = Config::from_table(table).unwrap();
+        assert_eq!(config.chain.chain_id, 137);
+        assert_eq!(config.risk.max_fee_per_gas_gwei, 300);
+        assert_eq!(config.risk.max_orders_per_bundle, 2);
+        assert!(!config.thresholds.use_flashloan);
+        // 没写的字段来自 Polygon 的 profile
+        let polygon = ChainProfile::polygon();
+        assert_eq!(config.base_token.address, polygon.base_token().address);
+        // profile 里所有的 V2 DEX 都同步, fee 取 profile 的第一档
+        let v2_dexes = polygon.v2_dexes();
+        assert!(v2_dexes.len() > 1);
+        assert_eq!(config.dexes.len(), v2_dexes.len());
+        for (dex, profile) in config.dexes.iter().zip(&v2_dexes) {
+            assert_eq!(dex.router, profile.router);
+            assert_eq!(dex.fee, profile.fee_tiers[0]);
+        }
+        let pancakeswap = DexConfig::from(ChainProfile::bsc().v2_dexes()[0]);
+        assert_eq!(pancakeswap.fee, 250);
+        assert!(!config.chain.supports_bundles);
+        assert_eq!(config.submission.mode, SubmissionMode::Conditional);
+        assert!(config.builders.is_empty());
+
+        let redacted = config.redacted();
+        assert!(!redacted.contains("secret-key"));
+        assert!(redacted.contains("https://eth-mainnet.example.com/<redacted>"));
+    }
+
+    #[test]
+    fn validate_lists_every_error() {
+        let config: Config = toml::Value::Table(toml::Table::new()).try_into().unwrap();
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("rpc.https_url"));
+        assert!(error.contains("rpc.wss_url"));
+        assert!(error.contains("chain.bot_address"));
+        assert!(error.contains("chain.wrapped_native"));
+
+        // 没有 profile 的链必须自己写 base_token 和 dexes
+        let unknown_chain = Config::from_table("[chain]\nchain_id = 10".parse().unwrap());
+        assert!(unknown_chain
+            .unwrap_err()
+            .to_string()
+            .contains("no built-in profile"));
+        let optimism = |chain: &str| {
+            let table = format!(
+                r#"
+                [rpc]
+                https_url = "https://opt-mainnet.example.com"
+                wss_url = "wss://opt-mainnet.example.com"
+
+                [chain]
+                chain_id = 10
+                bot_address = "0x00000000000000000000000000000000000000b0"
+                {chain}
+
+                [base_token]
+                address = "0x0b2C639c533813f4Aa9D7837CAf62653d097Ff85"
+                symbol = "USDC"
+                decimals = 6
+
+                [[dexes]]
+                name = "velodrome"
+                factory = "0x00000000000000000000000000000000000000f1"
+                router = "0x00000000000000000000000000000000000000f2"
+                start_block = 0
+                "#
+            );
+            Config::from_table(table.parse().unwrap())
+        };
+        let error = optimism("").unwrap_err().to_string();
+        assert!(error.contains("chain.wrapped_native is not set"));
+        let config =
+            optimism(r#"wrapped_native = "0x4200000000000000000000000000000000000006""#).unwrap();
+        assert_eq!(
+            config.chain.wrapped_native,
+            "0x4200000000000000000000000000000000000006"
+                .parse::<Address>()
+                .unwrap()
+        );
+
+        let mut dry_run = config.clone();
+        dry_run.dry_run.enabled = true;
+        dry_run.dry_run.reconcile_blocks = 0;
+        let error = dry_run.validate().unwrap_err().to_string();
+        assert!(error.contains("dry_run.reconcile_blocks"));
+
+        let mut dex_fee = config.clone();
+        dex_fee.dexes[0].fee = 10000;
+        let error = dex_fee.validate().unwrap_err().to_string();
+        assert!(error.contains("fee must be below 10000"));
+
+        let mut recorder = config.clone();
+        recorder.recorder.enabled = true;
+        recorder.recorder.max_file_mb = 0;
+        let error = recorder.validate().unwrap_err().to_string();
+        assert!(error.contains("recorder.max_file_mb"));
+
+        let typo: Result<Config, _> =
+            toml::Value::Table("[risk]\nmax_amount = 1".parse().unwrap()).try_into();
+        assert!(typo.is_err());
+    }
+
+    #[test]
+    fn example_config_parses() {
+        let example: Config = std::fs::read_to_string("config.example.toml")
+            .unwrap()
+            .parse::<toml::Table>()
+            .map(toml::Value::Table)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(example.dexes.len(), 2);
+        assert!(example.dexes.iter().all(|dex| dex.fee == 300));
+        assert_eq!(example.base_token.symbol, "USDC");
+        assert_eq!(
+            serde_json::to_value(&example.builders).unwrap(),
+            serde_json::to_value(default_builders()).unwrap()
+        );
+    }
+
+    #[test]
+    fn bid_policy_from_thresholds() {
+        let table: toml::Table = r#"
+            [rpc]
+            https_url = "https://eth-mainnet.example.com"
+            wss_url = "wss://eth-mainnet.example.com"
+
+            [chain]
+            bot_address = "0x00000000000000000000000000000000000000b0"
+
+            [thresholds]
+            profit_share_bps = 7000
+            bid_mode = { coinbase_transfer = { recipient = "0x00000000000000000000000000000000000000c0" } }
+            bid_floor_gwei = 1000
+            bid_ceiling_gwei = 5000000
+        "#
+        .parse()
+        .unwrap();
+        let config = Config::from_table(table).unwrap();
+        let policy = config.thresholds.bid_policy();
+        assert_eq!(
+            policy.mode,
+            BidMode::CoinbaseTransfer {
+                recipient: Address::from_low_u64_be(0xc0)
+            }
+        );
+        assert_eq!(policy.profit_share_bps, 7000);
+        assert_eq!(policy.floor, U256::from(1000) * U256::exp10(9));
+        assert_eq!(policy.ceiling, U256::from(5_000_000) * U256::exp10(9));
+
+        // 默认 priority fee, 不限制上限
+        let policy = Thresholds::default().bid_policy();
+        assert_eq!(policy.mode, BidMode::PriorityFee);
+        assert_eq!(policy.ceiling, U256::MAX);
+
+        let mut config = config;
+        config.thresholds.bid_ceiling_gwei = Some(10);
+        config.thresholds.bid_mode = BidMode::CoinbaseTransfer {
+            recipient: Address::zero(),
+        };
+        config.submission.mode = SubmissionMode::Private;
+        config.submission.rpc_url = "https://rpc.example.com".to_string();
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("bid_ceiling_gwei"));
+        assert!(error.contains("recipient is not set"));
+        assert!(error.contains("requires submission.mode = bundle"));
+    }
+
+    #[test]
+    fn order_and_signers() {
+        let ethereum = ChainProfile::ethereum();
+        let router = ethereum.dexes[0].router;
+        let other_router = ethereum.dexes[1].router;
+        let mut table: toml::Table = r#"
+            [rpc]
+            https_url = "https://eth-mainnet.example.com"
+            wss_url = "wss://eth-mainnet.example.com"
+
+            [chain]
+            bot_address = "0x00000000000000000000000000000000000000b0"
+
+            [signers.executor]
+            source = "keystore"
+            path = "keys/executor.json"
+
+            [signers.flashbots]
+            source = "remote"
+            url = "https://signer.example.com/secret-key"
+            address = "0x00000000000000000000000000000000000000f0"
+        "#
+        .parse()
+        .unwrap();
+        apply_env_overrides(
+            &mut table,
+            vars(&[
+                ("ORDER_ENCODING", "packed"),
+                (
+                    "ROUTER_REGISTRY",
+                    &format!("{:?}, {:?}, {:?}", Address::zero(), router, other_router),
+                ),
+            ]),
+        )
+        .unwrap();
+        let config = Config::from_table(table.clone()).unwrap();
+        assert_eq!(config.order.encoding, OrderEncoding::Packed);
+        assert_eq!(config.order.registry().index_of(router), Some(1));
+        assert!(matches!(
+            config.signers.executor,
+            Some(KeyConfig::Keystore { ref path, password_file: None }) if path == "keys/executor.json"
+        ));
+        assert!(config.signers.admin.is_none());
+        assert!(!config.redacted().contains("secret-key"));
+
+        // packed 时 DEX 的 router 必须登记过
+        apply_env_overrides(
+            &mut table,
+            vars(&[("ROUTER_REGISTRY", &format!("{:?}", Address::zero()))]),
+        )
+        .unwrap();
+        let error = Config::from_table(table.clone()).unwrap_err().to_string();
+        assert!(error.contains("router is not in order.router_registry"));
+        apply_env_overrides(&mut table, vars(&[("ROUTER_REGISTRY", "")])).unwrap();
+        let error = Config::from_table(table).unwrap_err().to_string();
+        assert!(error.contains("requires order.router_registry"));
+    }
+
+    #[test]
+    fn submission_fee_policies() {
+        let gwei = U256::exp10(9);
+        let bid = Bid {
+            max_priority_fee_per_gas: gwei * 5,
+            max_fee_per_gas: gwei * 35,
+            coinbase_transfer: U256::zero(),
+        };
+        let mut submission = SubmissionConfig {
+            mode: SubmissionMode::Private,
+            ..SubmissionConfig::default()
+        };
+        assert_eq!(submission.fees(&bid, gwei * 30), (gwei * 5, gwei * 35));
+
+        // sequencer 先到先得: 固定 priority fee, max fee 留出 base fee 上涨的空间
+        submission.mode = SubmissionMode::Conditional;
+        let (priority_fee, max_fee) = submission.fees(&bid, gwei / 10);
+        assert_eq!(priority_fee, U256::from(10_000_000));
+        assert_eq!(max_fee, gwei / 5 + priority_fee);
+
+        // 最坏情况 0.2 gwei * 300000 gas = 60000 gwei, 加 10% 的余量
+        let gas_limit = U256::from(300_000);
+        assert!(!submission.is_profitable(gwei * 66_000, gas_limit, gwei / 5));
+        assert!(submission.is_profitable(gwei * 66_001, gas_limit, gwei / 5));
+    }
+}
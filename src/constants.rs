@@ -1,8 +1,13 @@
+use anyhow::Result;
 use ethers::{
     prelude::Lazy,
     types::{Address, H160, U256, U64},
 };
 use std::str::FromStr;
+
+use crate::builders::BuilderConfig;
+use crate::config::Config;
+use crate::signer::{load_signer_config, SignerConfig};
 // 普通静态变量必须在编译时就能确定值
 // 复杂计算或运行时的值无法直接用作静态变量
 // Lazy 允许第一次访问时才进行初始化
@@ -16,16 +21,23 @@ pub struct Env {
     pub https_url: String,
     pub wss_url: String,
     pub chain_id: U64,
-    pub private_key: String,
-    pub signing_key: String,
+    pub signers: SignerConfig,
     pub bot_address: String,
+    pub builders: Vec<BuilderConfig>,
+    pub config: Config,
 }
 pub fn get_env(key: &str) -> String {
     std::env::var(key).unwrap()
 }
 impl Env {
-    pub fn new() -> Self {
-        Env {
+    pub fn load() -> Result<Self> {
+        Self::from_config(&Config::load()?)
+    }
+
+    // 配置之外只有私钥从环境变量读, 见 signer::load_signer_config
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let config = config.clone();
+        Ok(Env {
             //"HTTPS_URL" 存储在程序的只读数据段 位于程序的只读数据段（.rodata 段） 和程序代码一起加载到内存中
             // 这段内存：
             // - 是只读的
@@ -37,13 +49,14 @@ impl Env {
             // rust 注意性能：
             // 静态数据是程序的固定部分，由操作系统统一管理
             // 堆内存是动态申请的资源，需要及时释放以避免浪费
-            https_url: get_env("HTTPS_URL"),
-            wss_url: get_env("WSS_URL"),
-            chain_id: U64::from_str(&get_env("CHAIN_ID")).unwrap(),
-            private_key: get_env("PRIVATE_KEY"),
-            signing_key: get_env("SIGNING_KEY"),
-            bot_address: get_env("BOT_ADDRESS"),
-        }
+            https_url: config.rpc.https_url.clone(),
+            wss_url: config.rpc.wss_url.clone(),
+            chain_id: U64::from(config.chain.chain_id),
+            signers: load_signer_config(&config.signers)?,
+            bot_address: format!("{:?}", config.chain.bot_address),
+            builders: config.builders.clone(),
+            config,
+        })
     }
 }
 pub fn get_blacklist_tokens() -> Vec<H160> {
@@ -0,0 +1,227 @@
+use ethers::{
+    abi::AbiDecode,
+    prelude::abigen,
+    types::{Transaction, H160, U256},
+};
+
+abigen!(
+    UniswapV2Router,
+    r#"[
+        function swapExactTokensForTokens(uint256 amountIn, uint256 amountOutMin, address[] path, address to, uint256 deadline) external returns (uint256[] amounts)
+        function swapTokensForExactTokens(uint256 amountOut, uint256 amountInMax, address[] path, address to, uint256 deadline) external returns (uint256[] amounts)
+        function swapExactETHForTokens(uint256 amountOutMin, address[] path, address to, uint256 deadline) external payable returns (uint256[] amounts)
+        function swapTokensForExactETH(uint256 amountOut, uint256 amountInMax, address[] path, address to, uint256 deadline) external returns (uint256[] amounts)
+        function swapExactTokensForETH(uint256 amountIn, uint256 amountOutMin, address[] path, address to, uint256 deadline) external returns (uint256[] amounts)
+        function swapETHForExactTokens(uint256 amountOut, address[] path, address to, uint256 deadline) external payable returns (uint256[] amounts)
+    ]"#,
+);
+
+// 交易金额: 固定输入 / 固定输出
+#[derive(Debug, Clone)]
+pub enum SwapAmount {
+    ExactIn {
+        amount_in: U256,
+        amount_out_min: U256,
+    },
+    ExactOut {
+        amount_out: U256,
+        amount_in_max: U256,
+    },
+}
+
+// 从 pending 交易里解析出来的 Uniswap V2 路由交易
+#[derive(Debug, Clone)]
+pub struct PendingSwap {
+    pub tx: Transaction,
+    pub router: H160,
+    pub path: Vec<H160>,
+    pub amount: SwapAmount,
+}
+
+// 只解析发给 routers 的 swap 调用, 其他交易返回 None
+// 不处理 SupportingFeeOnTransferTokens 系列, 转账扣税的代币没法用储备量模拟
+pub fn decode_v2_swap(tx: &Transaction, routers: &[H160]) -> Option<PendingSwap> {
+    let router = tx.to?;
+    if !routers.contains(&router) {
+        return None;
+    }
+    let call = UniswapV2RouterCalls::decode(&tx.input).ok()?;
+    let (path, amount) = match call {
+        UniswapV2RouterCalls::SwapExactTokensForTokens(c) => (
+            c.path,
+            SwapAmount::ExactIn {
+                amount_in: c.amount_in,
+                amount_out_min: c.amount_out_min,
+            },
+        ),
+        UniswapV2RouterCalls::SwapTokensForExactTokens(c) => (
+            c.path,
+            SwapAmount::ExactOut {
+                amount_out: c.amount_out,
+                amount_in_max: c.amount_in_max,
+            },
+        ),
+        // ETH 输入的金额就是交易的 value
+        UniswapV2RouterCalls::SwapExactETHForTokens(c) => (
+            c.path,
+            SwapAmount::ExactIn {
+                amount_in: tx.value,
+                amount_out_min: c.amount_out_min,
+            },
+        ),
+        UniswapV2RouterCalls::SwapTokensForExactETH(c) => (
+            c.path,
+            SwapAmount::ExactOut {
+                amount_out: c.amount_out,
+                amount_in_max: c.amount_in_max,
+            },
+        ),
+        UniswapV2RouterCalls::SwapExactTokensForETH(c) => (
+            c.path,
+            SwapAmount::ExactIn {
+                amount_in: c.amount_in,
+                amount_out_min: c.amount_out_min,
+            },
+        ),
+        UniswapV2RouterCalls::SwapETHForExactTokens(c) => (
+            c.path,
+            SwapAmount::ExactOut {
+                amount_out: c.amount_out,
+                amount_in_max: tx.value,
+            },
+        ),
+    };
+    if path.len() < 2 {
+        return None;
+    }
+    Some(PendingSwap {
+        tx: tx.clone(),
+        router,
+        path,
+        amount,
+    })
+}
+
+#[cfg(test)]
+mod decoder_tests {
+    use super::*;
+    use ethers::types::Bytes;
+    use std::str::FromStr;
+
+    const ROUTER: &str = "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D";
+    const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    const WETH: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+    const DAI: &str = "0x6B175474E89094C44Da98b810eCe1fC6f2e6A10C";
+
+    fn address(addr: &str) -> H160 {
+        H160::from_str(addr).unwrap()
+    }
+
+    fn router_tx(to: &str, input: &str, value: U256) -> Transaction {
+        Transaction {
+            to: Some(address(to)),
+            input: Bytes::from_str(input).unwrap(),
+            value,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decodes_router_calldata() {
+        let routers = vec![address(ROUTER)];
+        // swapExactTokensForTokens(5000 USDC, 2 DAI, [USDC, WETH, DAI], to, deadline)
+        let input = concat!(
+            "38ed1739",
+            "000000000000000000000000000000000000000000000000000000012a05f200",
+            "0000000000000000000000000000000000000000000000001bc16d674ec80000",
+            "00000000000000000000000000000000000000000000000000000000000000a0",
+            "00000000000000000000000000000000000000000000000000000000000000c0",
+            "000000000000000000000000000000000000000000000000000000006553f100",
+            "0000000000000000000000000000000000000000000000000000000000000003",
+            "000000000000000000000000a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            "000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+            "0000000000000000000000006b175474e89094c44da98b810ece1fc6f2e6a10c",
+        );
+        let swap = decode_v2_swap(&router_tx(ROUTER, input, U256::zero()), &routers).unwrap();
+        assert_eq!(swap.router, address(ROUTER));
+        assert_eq!(swap.path, vec![address(USDC), address(WETH), address(DAI)]);
+        match swap.amount {
+            SwapAmount::ExactIn {
+                amount_in,
+                amount_out_min,
+            } => {
+                assert_eq!(amount_in, U256::from(5_000_000_000u64));
+                assert_eq!(amount_out_min, U256::exp10(18) * 2);
+            }
+            _ => panic!("expected exact in"),
+        }
+
+        // swapExactETHForTokens: 输入是交易的 value
+        let input = concat!(
+            "7ff36ab5",
+            "0000000000000000000000000000000000000000000000000000000124101100",
+            "0000000000000000000000000000000000000000000000000000000000000080",
+            "00000000000000000000000000000000000000000000000000000000000000c0",
+            "000000000000000000000000000000000000000000000000000000006553f100",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+            "000000000000000000000000a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+        );
+        let swap = decode_v2_swap(&router_tx(ROUTER, input, U256::exp10(18)), &routers).unwrap();
+        assert_eq!(swap.path, vec![address(WETH), address(USDC)]);
+        match swap.amount {
+            SwapAmount::ExactIn {
+                amount_in,
+                amount_out_min,
+            } => {
+                assert_eq!(amount_in, U256::exp10(18));
+                assert_eq!(amount_out_min, U256::from(4_900_000_000u64));
+            }
+            _ => panic!("expected exact in"),
+        }
+
+        // swapTokensForExactETH(1 ETH, 最多 2100 USDC, [USDC, WETH], to, deadline)
+        let input = concat!(
+            "4a25d94a",
+            "0000000000000000000000000000000000000000000000000de0b6b3a7640000",
+            "000000000000000000000000000000000000000000000000000000007d2b7500",
+            "00000000000000000000000000000000000000000000000000000000000000a0",
+            "00000000000000000000000000000000000000000000000000000000000000c0",
+            "000000000000000000000000000000000000000000000000000000006553f100",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "000000000000000000000000a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            "000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+        );
+        let swap = decode_v2_swap(&router_tx(ROUTER, input, U256::zero()), &routers).unwrap();
+        match swap.amount {
+            SwapAmount::ExactOut {
+                amount_out,
+                amount_in_max,
+            } => {
+                assert_eq!(amount_out, U256::exp10(18));
+                assert_eq!(amount_in_max, U256::from(2_100_000_000u64));
+            }
+            _ => panic!("expected exact out"),
+        }
+        // 发给别的合约的同样 calldata 不解析
+        assert!(decode_v2_swap(&router_tx(USDC, input, U256::zero()), &routers).is_none());
+    }
+
+    #[test]
+    fn skips_fee_on_transfer_swaps() {
+        // swapExactTokensForTokensSupportingFeeOnTransferTokens
+        let input = concat!(
+            "5c11d795",
+            "000000000000000000000000000000000000000000000000000000012a05f200",
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            "00000000000000000000000000000000000000000000000000000000000000a0",
+            "00000000000000000000000000000000000000000000000000000000000000c0",
+            "000000000000000000000000000000000000000000000000000000006553f100",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "000000000000000000000000a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            "000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+        );
+        let tx = router_tx(ROUTER, input, U256::zero());
+        assert!(decode_v2_swap(&tx, &[address(ROUTER)]).is_none());
+    }
+}
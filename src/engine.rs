@@ -0,0 +1,314 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use ethers::types::{Eip1559TransactionRequest, TxHash, U256, U64};
+use ethers_flashbots::BundleRequest;
+use log::info;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::{
+    sync::{
+        broadcast::{error::RecvError, Sender},
+        mpsc,
+    },
+    task::JoinSet,
+};
+
+use crate::bundler::{PrivateTx, SimulationError};
+use crate::mev_share::MevShareBundle;
+use crate::paper::PaperTrade;
+use crate::streams::Event;
+use crate::tracker::{BundleOutcome, OutcomeRecord};
+
+// 策略产生的动作, 由执行器负责提交
+#[derive(Debug, Clone)]
+pub enum Action {
+    SendBundle(BundleRequest),
+    // 带 replacementUuid 提交, 同一个 uuid 再提交会替换之前的 bundle
+    ReplaceBundle(BundleRequest, String),
+    // 按 replacementUuid 取消
+    CancelBundle(String),
+    SendTx(Eip1559TransactionRequest),
+    // 没有 bundle relay 时单独提交的套利交易, 见 config::SubmissionMode
+    SendPrivateTx(PrivateTx),
+    // 尾随 MEV-Share hint 的 bundle, 用 mev_sendBundle 提交
+    SendMevShareBundle(MevShareBundle),
+    // 模拟盘: 只模拟和记账, 见 paper::PaperExecutor
+    PaperTrade(PaperTrade),
+}
+
+// 一次提交的结果: bundle 里的交易哈希, 目标区块, 上链时是 bundle/交易哈希
+// simulation_failed 表示在模拟阶段就失败了, 没有真正提交
+// outcome 是 bundle 追踪的结果, 提交之前就失败时为 None
+// simulated_gas 是 eth_callBundle 模拟出来的每笔交易的 gas_used, 没上链也可以用来校准 gas 模型
+#[derive(Debug, Clone)]
+pub struct ExecutionReport {
+    pub tx_hashes: Vec<TxHash>,
+    pub target_block: Option<U64>,
+    pub replacement_uuid: Option<String>,
+    pub result: std::result::Result<TxHash, String>,
+    pub simulation_failed: bool,
+    pub outcome: Option<OutcomeRecord>,
+    pub simulated_gas: HashMap<TxHash, U256>,
+}
+
+impl ExecutionReport {
+    pub fn new(action: &Action, result: Result<TxHash>) -> Self {
+        let (tx_hashes, target_block, replacement_uuid) = match action {
+            Action::SendBundle(bundle) => (bundle.transaction_hashes(), bundle.block(), None),
+            Action::ReplaceBundle(bundle, uuid) => (
+                bundle.transaction_hashes(),
+                bundle.block(),
+                Some(uuid.clone()),
+            ),
+            Action::CancelBundle(uuid) => (Vec::new(), None, Some(uuid.clone())),
+            Action::SendTx(_) => (Vec::new(), None, None),
+            Action::SendPrivateTx(tx) => (vec![tx.hash()], Some(tx.max_block_number), None),
+            Action::SendMevShareBundle(bundle) => (
+                bundle.transaction_hashes(),
+                Some(bundle.inclusion.block),
+                None,
+            ),
+            Action::PaperTrade(trade) => (trade.tx_hashes.clone(), Some(trade.max_block), None),
+        };
+        let simulation_failed = match &result {
+            Ok(_) => false,
+            Err(e) => e.downcast_ref::<SimulationError>().is_some(),
+        };
+        Self {
+            tx_hashes,
+            target_block,
+            replacement_uuid,
+            result: result.map_err(|e| format!("{:?}", e)),
+            simulation_failed,
+            outcome: None,
+            simulated_gas: HashMap::new(),
+        }
+    }
+
+    // 按追踪结果生成报告, 只有上链时 result 是 Ok
+    pub fn from_outcome(action: &Action, record: OutcomeRecord) -> Self {
+        let result = match &record.outcome {
+            BundleOutcome::Included { .. } => match action {
+                Action::SendPrivateTx(tx) => Ok(tx.hash()),
+                _ => Ok(record.bundle_hash.unwrap_or_default()),
+            },
+            BundleOutcome::NotIncluded => Err(anyhow::anyhow!("Bundle not included")),
+            BundleOutcome::Reverted { tx_hash, reason } => Err(anyhow::anyhow!(
+                "Bundle included but {:?} reverted: {}",
+                tx_hash,
+                reason
+            )),
+        };
+        let mut report = Self::new(action, result);
+        report.outcome = Some(record);
+        report
+    }
+
+    // 出价是否赢了: revert 的 bundle 也上链了
+    pub fn landed(&self) -> bool {
+        match &self.outcome {
+            Some(record) => !matches!(record.outcome, BundleOutcome::NotIncluded),
+            None => self.result.is_ok(),
+        }
+    }
+}
+
+// 策略: 启动时同步状态, 之后把每个事件转换成动作
+#[async_trait]
+pub trait Strategy: Send {
+    async fn sync_state(&mut self) -> Result<()>;
+    async fn process_event(&mut self, event: Event) -> Vec<Action>;
+}
+
+// 执行器: 提交策略产生的动作, 等到有结果后返回报告
+// handles 决定哪些动作归这个执行器, 例如 PaperTrade 只交给 PaperExecutor
+#[async_trait]
+pub trait Executor: Send + Sync {
+    fn handles(&self, action: &Action) -> bool;
+    async fn execute(&self, action: Action) -> ExecutionReport;
+}
+
+// 把事件广播连接到多个策略, 每个动作交给第一个 handles 它的执行器
+pub struct Engine {
+    pub event_sender: Sender<Event>,
+    pub strategies: Vec<Box<dyn Strategy>>,
+    pub executors: Vec<Arc<dyn Executor>>,
+}
+
+impl Engine {
+    pub fn new(event_sender: Sender<Event>) -> Self {
+        Self {
+            event_sender,
+            strategies: Vec::new(),
+            executors: Vec::new(),
+        }
+    }
+
+    pub fn add_strategy(&mut self, strategy: Box<dyn Strategy>) {
+        self.strategies.push(strategy);
+    }
+
+    pub fn add_executor(&mut self, executor: Arc<dyn Executor>) {
+        self.executors.push(executor);
+    }
+
+    // 同步所有策略的状态, 然后启动策略和分发动作的任务
+    // 执行结果会作为 Event::Execution 发回事件广播
+    pub async fn run(self) -> Result<JoinSet<()>> {
+        let (action_sender, mut action_receiver) = mpsc::channel::<Action>(512);
+        let mut set = JoinSet::new();
+
+        let executors = self.executors;
+        let event_sender = self.event_sender.clone();
+        set.spawn(async move {
+            while let Some(action) = action_receiver.recv().await {
+                let executor = match executors.iter().find(|executor| executor.handles(&action)) {
+                    Some(executor) => executor.clone(),
+                    None => {
+                        info!("No executor for {:?}", action);
+                        continue;
+                    }
+                };
+                // 执行器会等到目标区块, 每个动作单独一个任务
+                let event_sender = event_sender.clone();
+                tokio::spawn(async move {
+                    let report = executor.execute(action).await;
+                    if let Err(e) = &report.result {
+                        info!("Executor error: {}", e);
+                    }
+                    match event_sender.send(Event::Execution(report)) {
+                        Ok(_) => {}
+                        Err(_) => {}
+                    }
+                });
+            }
+        });
+
+        for mut strategy in self.strategies {
+            strategy.sync_state().await?;
+            let mut event_receiver = self.event_sender.subscribe();
+            let action_sender = action_sender.clone();
+            set.spawn(async move {
+                loop {
+                    match event_receiver.recv().await {
+                        Ok(event) => {
+                            for action in strategy.process_event(event).await {
+                                if action_sender.send(action).await.is_err() {
+                                    info!("Action channel closed");
+                                }
+                            }
+                        }
+                        Err(RecvError::Lagged(n)) => info!("Strategy lagged {} events", n),
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            });
+        }
+
+        Ok(set)
+    }
+}
+
+#[cfg(test)]
+mod engine_tests {
+    use super::*;
+    use crate::streams::NewBlock;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use tokio::sync::broadcast;
+
+    // 每个区块产生一笔 PaperTrade 和一个 CancelBundle
+    struct FakeStrategy;
+
+    #[async_trait]
+    impl Strategy for FakeStrategy {
+        async fn sync_state(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn process_event(&mut self, event: Event) -> Vec<Action> {
+            match event {
+                Event::Block(block) => vec![
+                    Action::PaperTrade(PaperTrade::new(
+                        "fake",
+                        block.block_number,
+                        block.block_number + 1,
+                        Vec::new(),
+                        Vec::new(),
+                    )),
+                    Action::CancelBundle("uuid".to_string()),
+                ],
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    // 记录收到的动作, paper 为 true 时只接 PaperTrade
+    struct FakeExecutor {
+        paper: bool,
+        received: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Executor for FakeExecutor {
+        fn handles(&self, action: &Action) -> bool {
+            matches!(action, Action::PaperTrade(_)) == self.paper
+        }
+
+        async fn execute(&self, action: Action) -> ExecutionReport {
+            let name = match &action {
+                Action::PaperTrade(_) => "PaperTrade",
+                Action::CancelBundle(_) => "CancelBundle",
+                _ => "other",
+            };
+            self.received.lock().unwrap().push(name.to_string());
+            ExecutionReport::new(&action, Ok(TxHash::zero()))
+        }
+    }
+
+    #[tokio::test]
+    async fn routes_each_action_to_its_executor() {
+        let (event_sender, _) = broadcast::channel(16);
+        let mut events = event_sender.subscribe();
+        let paper = Arc::new(Mutex::new(Vec::new()));
+        let bundles = Arc::new(Mutex::new(Vec::new()));
+
+        let mut engine = Engine::new(event_sender.clone());
+        engine.add_strategy(Box::new(FakeStrategy));
+        engine.add_executor(Arc::new(FakeExecutor {
+            paper: false,
+            received: bundles.clone(),
+        }));
+        engine.add_executor(Arc::new(FakeExecutor {
+            paper: true,
+            received: paper.clone(),
+        }));
+        let _set = engine.run().await.unwrap();
+
+        event_sender
+            .send(Event::Block(NewBlock {
+                block_number: U64::from(100),
+                ..Default::default()
+            }))
+            .unwrap();
+
+        // 两个动作的执行结果都会回到事件广播
+        let mut reports = Vec::new();
+        while reports.len() < 2 {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if let Event::Execution(report) = event {
+                reports.push(report);
+            }
+        }
+
+        assert_eq!(*paper.lock().unwrap(), vec!["PaperTrade".to_string()]);
+        assert_eq!(*bundles.lock().unwrap(), vec!["CancelBundle".to_string()]);
+        assert!(reports.iter().all(|report| report.result.is_ok()));
+        assert!(reports
+            .iter()
+            .any(|report| report.replacement_uuid.as_deref() == Some("uuid")));
+    }
+}
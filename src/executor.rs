@@ -0,0 +1,127 @@
+use anyhow::anyhow;
+use async_trait::async_trait;
+use ethers::types::TxHash;
+use ethers_flashbots::BundleRequest;
+use log::info;
+use std::sync::Arc;
+
+use crate::bundler::{Bundler, PrivateTx};
+use crate::engine::{Action, ExecutionReport, Executor};
+use crate::mev_share::MevShareBundle;
+use crate::tracker::BundleTracker;
+
+// 把 bundle 提交给所有配置的 builder, 普通交易走公开 mempool
+// 单独提交的套利交易按 submission.mode 发给私有 RPC 或 sequencer
+pub struct BundleExecutor {
+    pub bundler: Arc<Bundler>,
+    pub tracker: BundleTracker,
+}
+
+impl BundleExecutor {
+    pub fn new(bundler: Arc<Bundler>, tracker: BundleTracker) -> Self {
+        Self { bundler, tracker }
+    }
+
+    // send_bundle 先模拟, 模拟失败不会提交
+    // 提交之后由 tracker 盯着目标区块给出结果, 报告里带上模拟出来的 gas_used
+    async fn submit_bundle(
+        &self,
+        action: &Action,
+        bundle: &BundleRequest,
+        replacement_uuid: Option<&str>,
+    ) -> ExecutionReport {
+        let (simulated, builders) = match self.bundler.send_bundle(bundle, replacement_uuid).await {
+            Ok(sent) => sent,
+            Err(e) => return ExecutionReport::new(action, Err(e)),
+        };
+        let mut report = match self.tracker.track(bundle, builders).await {
+            Ok(record) => ExecutionReport::from_outcome(action, record),
+            Err(e) => ExecutionReport::new(action, Err(e)),
+        };
+        report.simulated_gas = simulated
+            .transactions
+            .iter()
+            .map(|tx| (tx.hash, tx.gas_used))
+            .collect();
+        report
+    }
+
+    // 按我们自己的交易追踪, 最晚到 maxBlock
+    async fn submit_mev_share_bundle(
+        &self,
+        action: &Action,
+        bundle: &MevShareBundle,
+    ) -> ExecutionReport {
+        let builders = match self.bundler.send_mev_share_bundle(bundle).await {
+            Ok(builders) => builders,
+            Err(e) => return ExecutionReport::new(action, Err(e)),
+        };
+        let tx_hash = bundle
+            .transaction_hashes()
+            .first()
+            .cloned()
+            .unwrap_or_default();
+        let max_block = bundle.inclusion.max_block.unwrap_or(bundle.inclusion.block);
+        match self.tracker.track_tx(tx_hash, max_block).await {
+            Ok(mut record) => {
+                record.bundle_hash = builders
+                    .iter()
+                    .find_map(|result| result.result.clone().ok().flatten());
+                record.builders = builders;
+                ExecutionReport::from_outcome(action, record)
+            }
+            Err(e) => ExecutionReport::new(action, Err(e)),
+        }
+    }
+
+    async fn submit_private_tx(&self, action: &Action, tx: &PrivateTx) -> ExecutionReport {
+        let tx_hash = match self.bundler.send_private_tx(tx).await {
+            Ok(tx_hash) => tx_hash,
+            Err(e) => return ExecutionReport::new(action, Err(e)),
+        };
+        match self.tracker.track_tx(tx_hash, tx.max_block_number).await {
+            Ok(record) => ExecutionReport::from_outcome(action, record),
+            Err(e) => ExecutionReport::new(action, Err(e)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executor for BundleExecutor {
+    // PaperTrade 归 PaperExecutor
+    fn handles(&self, action: &Action) -> bool {
+        !matches!(action, Action::PaperTrade(_))
+    }
+
+    async fn execute(&self, action: Action) -> ExecutionReport {
+        match &action {
+            Action::SendBundle(bundle) => self.submit_bundle(&action, bundle, None).await,
+            Action::ReplaceBundle(bundle, uuid) => {
+                self.submit_bundle(&action, bundle, Some(uuid)).await
+            }
+            Action::CancelBundle(uuid) => {
+                let result = self
+                    .bundler
+                    .cancel_bundle(uuid)
+                    .await
+                    .map(|_| TxHash::zero());
+                ExecutionReport::new(&action, result)
+            }
+            Action::SendTx(tx) => {
+                let result = self.bundler.send_tx(tx.clone()).await;
+                if let Ok(tx_hash) = &result {
+                    info!("Tx sent: {:?}", tx_hash);
+                }
+                ExecutionReport::new(&action, result)
+            }
+            Action::SendPrivateTx(tx) => self.submit_private_tx(&action, tx).await,
+            Action::SendMevShareBundle(bundle) => {
+                self.submit_mev_share_bundle(&action, bundle).await
+            }
+            Action::PaperTrade(_) => ExecutionReport::new(
+                &action,
+                Err(anyhow!("Paper trades are handled by PaperExecutor")),
+            ),
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use ethers::{
+    abi::Abi,
+    types::{Address, H160, U256},
+};
+use ethers_providers::Middleware;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::abi::{v2_arb_bot::V2ARBBOT_ABI, ERC20};
+use crate::bundler::Flashloan;
+use crate::chains::ChainProfile;
+use crate::pools::Pool;
+use crate::utils::Reserve;
+
+// 选中的闪电贷来源
+#[derive(Debug, Clone)]
+pub struct FlashloanQuote {
+    pub flashloan: Flashloan,
+    pub loan_from: Address,
+    pub fee: U256, // 需要多还的数量, 和借款同一个代币
+}
+
+impl FlashloanQuote {
+    // 不用闪电贷, 用合约里自己的资金
+    pub fn not_used() -> Self {
+        Self {
+            flashloan: Flashloan::NotUsed,
+            loan_from: Address::zero(),
+            fee: U256::zero(),
+        }
+    }
+}
+
+// Uniswap V2 flash swap 要还 amount * 100000 / (100000 - fee), 多出来的部分就是手续费
+// fee 是池子的费率 (300 = 0.3%, PancakeSwap 是 250), 向上取整
+pub fn uniswap_v2_flash_fee(amount: U256, fee: u32) -> U256 {
+    amount * U256::from(fee) / U256::from(100_000 - fee) + U256::one()
+}
+
+// 闪电贷来源回调合约的函数, 合约里没有这个函数就不能用这个来源
+pub fn flashloan_callback(flashloan: &Flashloan) -> Option<&'static str> {
+    match flashloan {
+        Flashloan::NotUsed => None,
+        Flashloan::Balancer => Some("receiveFlashLoan"),
+        Flashloan::UniswapV2 => Some("uniswapV2Call"),
+    }
+}
+
+// 合约 ABI 支持的闪电贷来源
+pub fn supported_flashloans(abi: &Abi) -> HashSet<Flashloan> {
+    [Flashloan::Balancer, Flashloan::UniswapV2]
+        .into_iter()
+        .filter(|flashloan| {
+            flashloan_callback(flashloan).is_some_and(|name| abi.functions.contains_key(name))
+        })
+        .collect()
+}
+
+// 按代币可借数量和手续费自动选择闪电贷来源: Balancer 0%, Uniswap V2 按池子费率
+// V2ArbBot 合约只有 receiveFlashLoan 和 uniswapV2Call 两个回调, 还不能用 Aave V3 / Uniswap V3 借
+#[derive(Debug, Clone)]
+pub struct FlashloanSelector {
+    pub balancer_vault: Option<Address>,
+    pub supported: HashSet<Flashloan>,
+    // (来源, 代币) -> (loan_from, 可借数量), refresh 更新
+    pub liquidity: HashMap<(Flashloan, H160), (Address, U256)>,
+}
+
+impl FlashloanSelector {
+    pub fn new(balancer_vault: Option<Address>) -> Self {
+        Self {
+            balancer_vault,
+            supported: supported_flashloans(&V2ARBBOT_ABI),
+            liquidity: HashMap::new(),
+        }
+    }
+
+    // Balancer Vault 地址来自 chains::ChainProfile
+    pub fn for_chain(chain_id: u64) -> Self {
+        Self::new(ChainProfile::get(chain_id).and_then(|profile| profile.balancer_vault))
+    }
+
+    pub fn supports(&self, flashloan: &Flashloan) -> bool {
+        self.supported.contains(flashloan)
+    }
+
+    // 查询 Balancer Vault 里 tokens 的可借数量
+    pub async fn refresh<M: Middleware + 'static>(&mut self, provider: Arc<M>, tokens: &[H160]) {
+        let mut liquidity = HashMap::new();
+        let balancer_vault = self
+            .balancer_vault
+            .filter(|_| self.supports(&Flashloan::Balancer));
+        if let Some(vault) = balancer_vault {
+            for token in tokens {
+                let erc20 = ERC20::new(*token, provider.clone());
+                if let Ok(balance) = erc20.balance_of(vault).call().await {
+                    liquidity.insert((Flashloan::Balancer, *token), (vault, balance));
+                }
+            }
+        }
+        self.liquidity = liquidity;
+    }
+
+    fn available(&self, flashloan: Flashloan, token: H160, amount: U256) -> Option<Address> {
+        if !self.supports(&flashloan) {
+            return None;
+        }
+        match self.liquidity.get(&(flashloan, token)) {
+            Some((loan_from, balance)) if *balance >= amount => Some(*loan_from),
+            _ => None,
+        }
+    }
+
+    // 合约支持的来源里手续费最低、并且借得出 amount 的来源
+    // V2 flash swap 从追踪中的池子里找, 不能用路径上的池子 (重入锁)
+    // reserves 要用交易执行时的储备量, 尾随时是受害者交易之后的
+    pub fn select(
+        &self,
+        token: H160,
+        amount: U256,
+        path_pools: &[H160],
+        v2_pools: &HashMap<H160, Pool>,
+        reserves: &HashMap<H160, Reserve>,
+    ) -> Option<FlashloanQuote> {
+        let mut candidates = Vec::new();
+        if let Some(loan_from) = self.available(Flashloan::Balancer, token, amount) {
+            candidates.push(FlashloanQuote {
+                flashloan: Flashloan::Balancer,
+                loan_from,
+                fee: U256::zero(),
+            });
+        }
+        // 储备量最多的 V2 池子
+        let v2_pool = v2_pools
+            .values()
+            .filter(|pool| !path_pools.contains(&pool.address))
+            .filter_map(|pool| {
+                let reserve = reserves.get(&pool.address)?;
+                if pool.token0 == token {
+                    Some((pool, reserve.reserve0))
+                } else if pool.token1 == token {
+                    Some((pool, reserve.reserve1))
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|(_, balance)| *balance);
+        if let Some((pool, balance)) = v2_pool.filter(|_| self.supports(&Flashloan::UniswapV2)) {
+            if balance > amount {
+                candidates.push(FlashloanQuote {
+                    flashloan: Flashloan::UniswapV2,
+                    loan_from: pool.address,
+                    fee: uniswap_v2_flash_fee(amount, pool.fee),
+                });
+            }
+        }
+        candidates.into_iter().min_by_key(|quote| quote.fee)
+    }
+}
+
+#[cfg(test)]
+mod flashloan_tests {
+    use super::*;
+    use crate::pools::DexVariant;
+
+    fn token(address: u64) -> H160 {
+        H160::from_low_u64_be(address)
+    }
+
+    fn v2_pool(address: u64, token0: H160, token1: H160) -> Pool {
+        Pool {
+            address: H160::from_low_u64_be(address),
+            version: DexVariant::UniswapV2,
+            token0,
+            token1,
+            decimals0: 6,
+            decimals1: 18,
+            fee: 300,
+            router: H160::zero(),
+        }
+    }
+
+    // 代币 0x1 在 Balancer 有 1000000, 两个 V2 池子里分别有 500000 和 2000000
+    fn selector() -> (
+        FlashloanSelector,
+        HashMap<H160, Pool>,
+        HashMap<H160, Reserve>,
+    ) {
+        let usdc = token(1);
+        let mut selector = FlashloanSelector::new(Some(H160::from_low_u64_be(0xba)));
+        selector.liquidity.insert(
+            (Flashloan::Balancer, usdc),
+            (H160::from_low_u64_be(0xba), U256::from(1_000_000)),
+        );
+        let pools = HashMap::from([
+            (token(0xa01), v2_pool(0xa01, usdc, token(2))),
+            (token(0xa02), v2_pool(0xa02, token(3), usdc)),
+        ]);
+        let reserves = HashMap::from([
+            (
+                token(0xa01),
+                Reserve {
+                    reserve0: U256::from(500_000),
+                    reserve1: U256::from(1),
+                },
+            ),
+            (
+                token(0xa02),
+                Reserve {
+                    reserve0: U256::from(1),
+                    reserve1: U256::from(2_000_000),
+                },
+            ),
+        ]);
+        (selector, pools, reserves)
+    }
+
+    #[test]
+    fn only_contract_callbacks_are_supported() {
+        let supported = supported_flashloans(&V2ARBBOT_ABI);
+        assert_eq!(
+            supported,
+            HashSet::from([Flashloan::Balancer, Flashloan::UniswapV2])
+        );
+
+        let mainnet = FlashloanSelector::for_chain(1);
+        assert_eq!(mainnet.supported, supported);
+        assert_eq!(
+            mainnet.balancer_vault,
+            ChainProfile::ethereum().balancer_vault
+        );
+        assert!(FlashloanSelector::for_chain(10).balancer_vault.is_none());
+    }
+
+    #[test]
+    fn selects_the_cheapest_supported_provider() {
+        let (mut selector, mut pools, reserves) = selector();
+        let usdc = token(1);
+
+        // Balancer 没有手续费
+        let quote = selector
+            .select(usdc, U256::from(800_000), &[], &pools, &reserves)
+            .unwrap();
+        assert_eq!(quote.flashloan, Flashloan::Balancer);
+        assert_eq!(quote.loan_from, token(0xba));
+
+        // Balancer 不够借时, 用最深的 V2 池子
+        let quote = selector
+            .select(usdc, U256::from(1_500_000), &[], &pools, &reserves)
+            .unwrap();
+        assert_eq!(quote.flashloan, Flashloan::UniswapV2);
+        assert_eq!(quote.loan_from, token(0xa02));
+        assert_eq!(quote.fee, U256::from(1_500_000 * 3 / 997 + 1));
+        // 路径上的池子不能借
+        assert!(selector
+            .select(
+                usdc,
+                U256::from(1_500_000),
+                &[token(0xa02)],
+                &pools,
+                &reserves
+            )
+            .is_none());
+
+        // 手续费按池子的费率, PancakeSwap 0.25%
+        pools.get_mut(&token(0xa02)).unwrap().fee = 250;
+        let quote = selector
+            .select(usdc, U256::from(1_500_000), &[], &pools, &reserves)
+            .unwrap();
+        assert_eq!(quote.fee, U256::from(1_500_000 * 250 / 99_750 + 1));
+
+        // 合约没有 uniswapV2Call 时只能用 Balancer
+        selector.supported.remove(&Flashloan::UniswapV2);
+        assert!(selector
+            .select(usdc, U256::from(1_500_000), &[], &pools, &reserves)
+            .is_none());
+
+        // 都借不出来
+        assert!(selector
+            .select(usdc, U256::from(5_000_000), &[], &pools, &reserves)
+            .is_none());
+    }
+}
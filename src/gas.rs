@@ -0,0 +1,202 @@
+use ethers::types::{H160, U256};
+use std::collections::{HashMap, HashSet};
+
+use crate::bundler::Flashloan;
+use crate::paths::ArbPath;
+use crate::pools::DexVariant;
+
+// 套利交易的 gas 模型: 按跳数、DEX 类型、闪电贷来源和代币冷/热存储估算
+// 默认值是粗略估计, 运行中用收据和 eth_callBundle 模拟的 gas_used 校准
+#[derive(Debug, Clone)]
+pub struct GasModel {
+    pub base_gas: u64,           // 21000 + 合约入口 + calldata
+    pub uniswap_v2_hop_gas: u64, // 每一跳 V2 swap
+    pub uniswap_v3_hop_gas: u64, // 每一跳 V3 swap
+    pub cold_token_gas: u64,     // 交易里第一次碰到的代币 (冷存储)
+    pub warm_token_gas: u64,     // 已经碰过的代币
+    pub balancer_flashloan_gas: u64,
+    pub uniswap_v2_flashloan_gas: u64,
+    pub gas_limit_buffer_bps: u64, // gas limit 在估算值上多给的比例
+    pub smoothing: f64,            // 校准时新样本的权重
+    // 跳数和闪电贷来源不同, 估算误差也不同, 分开校准
+    pub corrections: HashMap<(u8, Flashloan), GasCorrection>,
+}
+
+// 一类路径的修正量
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GasCorrection {
+    pub correction: i64,
+    pub samples: u64,
+}
+
+impl Default for GasModel {
+    fn default() -> Self {
+        Self {
+            base_gas: 60000,
+            uniswap_v2_hop_gas: 60000,
+            uniswap_v3_hop_gas: 100000,
+            cold_token_gas: 20000,
+            warm_token_gas: 5000,
+            balancer_flashloan_gas: 40000,
+            uniswap_v2_flashloan_gas: 30000,
+            gas_limit_buffer_bps: 3000,
+            smoothing: 0.2,
+            corrections: HashMap::new(),
+        }
+    }
+}
+
+impl GasModel {
+    pub fn flashloan_gas(&self, flashloan: &Flashloan) -> u64 {
+        match flashloan {
+            Flashloan::NotUsed => 0,
+            Flashloan::Balancer => self.balancer_flashloan_gas,
+            Flashloan::UniswapV2 => self.uniswap_v2_flashloan_gas,
+        }
+    }
+
+    // 不含校准修正的估算
+    pub fn raw_estimate(&self, path: &ArbPath, flashloan: &Flashloan) -> u64 {
+        let mut gas = self.base_gas + self.flashloan_gas(flashloan);
+        let mut seen_tokens: HashSet<H160> = HashSet::new();
+        for i in 0..path.nhop {
+            let pool = path._get_pool(i);
+            gas += match pool.version {
+                DexVariant::UniswapV2 => self.uniswap_v2_hop_gas,
+                DexVariant::UniswapV3 => self.uniswap_v3_hop_gas,
+            };
+            for token in [pool.token0, pool.token1] {
+                gas += if seen_tokens.insert(token) {
+                    self.cold_token_gas
+                } else {
+                    self.warm_token_gas
+                };
+            }
+        }
+        gas
+    }
+
+    // 还没有样本时为 0
+    pub fn correction(&self, nhop: u8, flashloan: &Flashloan) -> i64 {
+        self.corrections
+            .get(&(nhop, flashloan.clone()))
+            .map(|c| c.correction)
+            .unwrap_or(0)
+    }
+
+    // 用于利润计算的 gas 使用量
+    pub fn estimate(&self, path: &ArbPath, flashloan: &Flashloan) -> U256 {
+        let gas = self.raw_estimate(path, flashloan) as i64 + self.correction(path.nhop, flashloan);
+        U256::from(gas.max(21000) as u64)
+    }
+
+    // 交易的 gas limit, 在估算值上留一些余量
+    pub fn gas_limit(&self, path: &ArbPath, flashloan: &Flashloan) -> U256 {
+        let gas = self.estimate(path, flashloan);
+        gas * U256::from(10000 + self.gas_limit_buffer_bps) / U256::from(10000)
+    }
+
+    // 用一次实际执行(收据或者本地模拟)的 gas_used 校准
+    // 修正量是同样跳数、同样闪电贷来源的估算误差的指数移动平均
+    pub fn calibrate(&mut self, path: &ArbPath, flashloan: &Flashloan, gas_used: U256) {
+        let error = gas_used.as_u64() as i64 - self.raw_estimate(path, flashloan) as i64;
+        let smoothing = self.smoothing;
+        let entry = self
+            .corrections
+            .entry((path.nhop, flashloan.clone()))
+            .or_default();
+        entry.correction = if entry.samples == 0 {
+            error
+        } else {
+            ((1.0 - smoothing) * entry.correction as f64 + smoothing * error as f64) as i64
+        };
+        entry.samples += 1;
+    }
+}
+
+#[cfg(test)]
+mod gas_tests {
+    use super::*;
+    use crate::pools::Pool;
+
+    fn pool(address: u64, token0: u64, token1: u64, version: DexVariant) -> Pool {
+        Pool {
+            address: H160::from_low_u64_be(address),
+            version,
+            token0: H160::from_low_u64_be(token0),
+            token1: H160::from_low_u64_be(token1),
+            decimals0: 18,
+            decimals1: 18,
+            fee: 300,
+            router: H160::zero(),
+        }
+    }
+
+    // 1 -> 2 -> 3 -> 1, 第二跳是 V3
+    fn path(nhop: u8) -> ArbPath {
+        ArbPath {
+            nhop,
+            pool_1: pool(0xa01, 1, 2, DexVariant::UniswapV2),
+            pool_2: pool(0xa02, 2, 3, DexVariant::UniswapV3),
+            pool_3: pool(0xa03, 3, 1, DexVariant::UniswapV2),
+            zero_for_one_1: true,
+            zero_for_one_2: true,
+            zero_for_one_3: true,
+        }
+    }
+
+    #[test]
+    fn estimates_hops_tokens_and_flashloan() {
+        let model = GasModel::default();
+        // 3 个代币各冷读一次, 之后的 3 次是热读
+        let expected = 60000 + 60000 * 2 + 100000 + 20000 * 3 + 5000 * 3;
+        assert_eq!(model.raw_estimate(&path(3), &Flashloan::NotUsed), expected);
+        assert_eq!(
+            model.raw_estimate(&path(3), &Flashloan::Balancer),
+            expected + 40000
+        );
+        assert_eq!(
+            model.estimate(&path(3), &Flashloan::NotUsed),
+            U256::from(expected)
+        );
+        assert_eq!(
+            model.gas_limit(&path(3), &Flashloan::NotUsed),
+            U256::from(expected * 13000 / 10000)
+        );
+    }
+
+    #[test]
+    fn calibrates_per_hop_count_and_flashloan() {
+        let mut model = GasModel::default();
+        let raw = model.raw_estimate(&path(3), &Flashloan::Balancer);
+
+        // 第一个样本直接作为修正量, 之后按 smoothing 做移动平均
+        model.calibrate(&path(3), &Flashloan::Balancer, U256::from(raw + 10000));
+        assert_eq!(model.correction(3, &Flashloan::Balancer), 10000);
+        model.calibrate(&path(3), &Flashloan::Balancer, U256::from(raw + 20000));
+        assert_eq!(model.correction(3, &Flashloan::Balancer), 12000);
+        assert_eq!(
+            model.estimate(&path(3), &Flashloan::Balancer),
+            U256::from(raw + 12000)
+        );
+
+        // 其他闪电贷来源和跳数不受影响
+        assert_eq!(model.correction(3, &Flashloan::NotUsed), 0);
+        assert_eq!(model.correction(2, &Flashloan::Balancer), 0);
+        let raw_2 = model.raw_estimate(&path(2), &Flashloan::Balancer);
+        model.calibrate(&path(2), &Flashloan::Balancer, U256::from(raw_2 - 5000));
+        assert_eq!(model.correction(2, &Flashloan::Balancer), -5000);
+        assert_eq!(model.correction(3, &Flashloan::Balancer), 12000);
+        assert_eq!(model.corrections[&(3, Flashloan::Balancer)].samples, 2);
+    }
+
+    #[test]
+    fn estimate_is_at_least_a_transfer() {
+        let mut model = GasModel::default();
+        model.calibrate(&path(3), &Flashloan::NotUsed, U256::from(1000));
+        assert_eq!(
+            model.estimate(&path(3), &Flashloan::NotUsed),
+            U256::from(21000)
+        );
+    }
+}
@@ -1,8 +1,34 @@
 pub mod abi;
+pub mod access_list;
+pub mod backrun;
+pub mod bidding;
+pub mod builders;
+pub mod bundler;
+pub mod chains;
+pub mod config;
 pub mod constants;
+pub mod decoder;
+pub mod engine;
+pub mod executor;
+pub mod flashloan;
+pub mod gas;
+pub mod mev_share;
+// 离线回放 RPC 的 mock 和回测, 只在测试和 mock feature (backtest, benches) 里编译
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod nonce;
+pub mod order;
+pub mod paper;
 pub mod paths;
 pub mod pools;
+pub mod pricing;
+pub mod recorder;
+#[cfg(any(test, feature = "mock"))]
+pub mod replay;
+pub mod selector;
+pub mod signer;
 pub mod simulator;
 pub mod strategy;
 pub mod streams;
+pub mod tracker;
 pub mod utils;
@@ -1,13 +1,23 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::Result;
 use dotenv::dotenv;
 use ethers_providers::{Provider, Ws};
+use log::info;
 use rust::{
-    constants::Env,
-    paths::generate_triangular_paths,
-    strategy::event_handler,
-    streams::{stream_new_block, Event},
+    backrun::BackrunStrategy,
+    bundler::Bundler,
+    config::Config,
+    engine::Engine,
+    executor::BundleExecutor,
+    paper::PaperExecutor,
+    recorder::run_recorder,
+    strategy::TriangularStrategy,
+    streams::{
+        stream_mev_share_hints, stream_new_block, stream_pending_transactions, stream_sync_logs,
+        Event,
+    },
+    tracker::BundleTracker,
     utils::setup_logger,
 };
 use tokio::{
@@ -19,8 +29,10 @@ use tokio::{
 async fn main() -> Result<()> {
     dotenv().ok();
     setup_logger()?;
-    let env = Env::new();
-    let ws = Provider::<Ws>::connect(env.wss_url).await?;
+    // 配置有问题时在这里报错退出, 不要等到策略里 panic
+    let config = Config::load()?;
+    info!("Config:\n{}", config.redacted());
+    let ws = Provider::<Ws>::connect(config.rpc.wss_url.clone()).await?;
     // ws所有权被Arc获取 或者move
     let ws_provider = Arc::new(ws);
     let (event_sender, _): (Sender<Event>, _) = broadcast::channel(512);
@@ -28,11 +40,61 @@ async fn main() -> Result<()> {
     // 获取区块信息
     set.spawn(stream_new_block(ws_provider.clone(), event_sender.clone()));
     // 获取pending交易
-    // we're not using the mempool data here, but uncomment it to use pending txs
-    // set.spawn(stream_pending_transactions(
-    //     provider.clone(),
-    //     event_sender.clone(),
-    // ));
-    set.spawn(event_handler(ws_provider.clone(), event_sender.clone()));
+    set.spawn(stream_pending_transactions(
+        ws_provider.clone(),
+        event_sender.clone(),
+    ));
+    // MEV-Share 的 hint, 交给 backrun 策略
+    if config.mev_share.enabled {
+        set.spawn(stream_mev_share_hints(
+            config.mev_share.url.clone(),
+            config.chain.block_time(),
+            event_sender.clone(),
+        ));
+    }
+    // 录下区块、Sync 日志和 pending 交易, 给 backtest 回放
+    if config.recorder.enabled {
+        info!("Recording events to {}", config.recorder.dir);
+        set.spawn(stream_sync_logs(ws_provider.clone(), event_sender.clone()));
+        set.spawn(run_recorder(config.recorder.clone(), event_sender.clone()));
+    }
+
+    let bundler = Arc::new(Bundler::new(&config)?);
+    let mut engine = Engine::new(event_sender.clone());
+    // 三角套利
+    engine.add_strategy(Box::new(TriangularStrategy::new(
+        &config,
+        ws_provider.clone(),
+        bundler.clone(),
+    )?));
+    // 尾随 pending 交易的套利, 要和受害者的交易放在同一个 bundle 里
+    if config.submission.uses_bundles() {
+        engine.add_strategy(Box::new(BackrunStrategy::new(
+            &config,
+            ws_provider.clone(),
+            bundler.clone(),
+        )?));
+    } else {
+        info!(
+            "Submission mode {:?}: backrun strategy disabled",
+            config.submission.mode
+        );
+    }
+    // 两个策略的利润都是 base token
+    let mut tracker = BundleTracker::new(bundler.clone(), Some(config.base_token.address));
+    // 出块快的链查得勤一点
+    tracker.poll_interval = Duration::from_millis((config.chain.block_time_ms / 4).min(1000));
+    // 模拟盘: 只模拟和记账, 不提交
+    if config.dry_run.enabled {
+        info!("Dry run: paper trades go to {}", config.dry_run.ledger_path);
+        engine.add_executor(Arc::new(PaperExecutor::new(bundler.clone(), tracker)));
+    } else {
+        engine.add_executor(Arc::new(BundleExecutor::new(bundler.clone(), tracker)));
+    }
+
+    let mut engine_set = engine.run().await?;
+    while let Some(res) = engine_set.join_next().await {
+        info!("Engine task closed: {:?}", res);
+    }
     Ok(())
 }
@@ -0,0 +1,465 @@
+use ethers::{
+    abi::{decode, ParamType, Token},
+    types::{Address, Bytes, H160, H256, U256, U64},
+    utils::keccak256,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::pools::Pool;
+use crate::utils::Reserve;
+
+// MEV-Share 事件流里的一条 hint, 用户只公开了部分信息, 没公开的字段是 null
+// https://docs.flashbots.net/flashbots-mev-share/searchers/event-stream
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hint {
+    pub hash: H256,
+    #[serde(default)]
+    pub logs: Option<Vec<HintLog>>,
+    #[serde(default)]
+    pub txs: Option<Vec<HintTx>>,
+    #[serde(default)]
+    pub mev_gas_price: Option<U256>,
+    #[serde(default)]
+    pub gas_used: Option<U256>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HintLog {
+    pub address: Address,
+    pub topics: Vec<H256>,
+    #[serde(default)]
+    pub data: Bytes,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HintTx {
+    #[serde(default)]
+    pub to: Option<Address>,
+    #[serde(default)]
+    pub function_selector: Option<Bytes>,
+    #[serde(default)]
+    pub call_data: Option<Bytes>,
+}
+
+impl Hint {
+    pub fn logs(&self) -> &[HintLog] {
+        self.logs.as_deref().unwrap_or_default()
+    }
+
+    pub fn txs(&self) -> &[HintTx] {
+        self.txs.as_deref().unwrap_or_default()
+    }
+
+    // hint 里出现的我们追踪的池子: 日志的地址, 或者交易直接调用池子
+    pub fn touched_pools(&self, pools: &HashMap<H160, Pool>) -> Vec<H160> {
+        let mut touched: Vec<H160> = Vec::new();
+        let addresses = self
+            .logs()
+            .iter()
+            .map(|log| log.address)
+            .chain(self.txs().iter().filter_map(|tx| tx.to));
+        for address in addresses {
+            if pools.contains_key(&address) && !touched.contains(&address) {
+                touched.push(address);
+            }
+        }
+        touched
+    }
+}
+
+// 把 hint 里的 Uniswap V2 日志作用在储备量上
+// Sync 直接给出交易之后的储备量; Swap 给出输入输出金额; data 没有公开的日志用不了
+// 返回更新过的池子, 一个都没有时返回 None
+pub fn apply_hint_logs(
+    hint: &Hint,
+    pools: &HashMap<H160, Pool>,
+    reserves: &mut HashMap<H160, Reserve>,
+) -> Option<Vec<H160>> {
+    let sync_topic = H256::from(keccak256("Sync(uint112,uint112)"));
+    let swap_topic = H256::from(keccak256(
+        "Swap(address,uint256,uint256,uint256,uint256,address)",
+    ));
+    let uint = |token: &Token| token.clone().into_uint().unwrap_or_default();
+    let mut updated = Vec::new();
+    // 同一笔交易里 Swap 后面跟着 Sync, 有 Sync 的池子就不再用 Swap
+    let synced: Vec<H160> = hint
+        .logs()
+        .iter()
+        .filter(|log| log.topics.first() == Some(&sync_topic) && log.data.len() == 64)
+        .map(|log| log.address)
+        .collect();
+    for log in hint.logs() {
+        if !pools.contains_key(&log.address) {
+            continue;
+        }
+        let topic = match log.topics.first() {
+            Some(topic) => *topic,
+            None => continue,
+        };
+        let reserve = match reserves.get_mut(&log.address) {
+            Some(reserve) => reserve,
+            None => continue,
+        };
+        if topic == sync_topic {
+            let data = match decode(&[ParamType::Uint(112), ParamType::Uint(112)], &log.data) {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            reserve.reserve0 = uint(&data[0]);
+            reserve.reserve1 = uint(&data[1]);
+        } else if topic == swap_topic && !synced.contains(&log.address) {
+            let data = match decode(&vec![ParamType::Uint(256); 4], &log.data) {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            let (amount0_in, amount1_in) = (uint(&data[0]), uint(&data[1]));
+            let (amount0_out, amount1_out) = (uint(&data[2]), uint(&data[3]));
+            reserve.reserve0 = (reserve.reserve0 + amount0_in).checked_sub(amount0_out)?;
+            reserve.reserve1 = (reserve.reserve1 + amount1_in).checked_sub(amount1_out)?;
+        } else {
+            continue;
+        }
+        if !updated.contains(&log.address) {
+            updated.push(log.address);
+        }
+    }
+    if updated.is_empty() {
+        None
+    } else {
+        Some(updated)
+    }
+}
+
+// Server-Sent Events 解析: 按空行分隔事件, 只关心 data 字段, 冒号开头的是注释 (心跳)
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 收到的数据可能在任意位置被切开 (包括多字节字符中间), 不完整的行留到下一次
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(idx) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=idx).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+            } else if let Some(data) = line.strip_prefix("data:") {
+                self.data
+                    .push(data.strip_prefix(' ').unwrap_or(data).to_string());
+            }
+        }
+        events
+    }
+}
+
+// mev_sendBundle 的格式
+// https://docs.flashbots.net/flashbots-auction/advanced/rpc-endpoint#mev_sendbundle
+#[derive(Debug, Clone, Serialize)]
+pub struct MevShareBundle {
+    pub version: String,
+    pub inclusion: Inclusion,
+    pub body: Vec<BundleItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validity: Option<Validity>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Inclusion {
+    pub block: U64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_block: Option<U64>,
+}
+
+// 用户的交易只有哈希, 我们的交易是签好名的原始交易
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum BundleItem {
+    Hash {
+        hash: H256,
+    },
+    Tx {
+        tx: Bytes,
+        #[serde(rename = "canRevert")]
+        can_revert: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Validity {
+    pub refund: Vec<Refund>,
+}
+
+// body 里第 body_idx 笔交易的发送者拿到 bundle 收益的 percent%
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Refund {
+    pub body_idx: usize,
+    pub percent: u64,
+}
+
+impl MevShareBundle {
+    // 尾随 hint 的交易: 用户的交易在前, 我们的交易在后, 收益按 refund_percent 退给用户
+    pub fn backrun(
+        hint_hash: H256,
+        signed_txs: Vec<Bytes>,
+        block: U64,
+        max_block: U64,
+        refund_percent: u64,
+    ) -> Self {
+        let mut body = vec![BundleItem::Hash { hash: hint_hash }];
+        body.extend(signed_txs.into_iter().map(|tx| BundleItem::Tx {
+            tx,
+            can_revert: false,
+        }));
+        Self {
+            version: "v0.1".to_string(),
+            inclusion: Inclusion {
+                block,
+                max_block: Some(max_block),
+            },
+            body,
+            validity: Some(Validity {
+                refund: vec![Refund {
+                    body_idx: 0,
+                    percent: refund_percent,
+                }],
+            }),
+        }
+    }
+
+    // 我们自己的交易哈希
+    pub fn transaction_hashes(&self) -> Vec<H256> {
+        self.body
+            .iter()
+            .filter_map(|item| match item {
+                BundleItem::Tx { tx, .. } => Some(H256::from(keccak256(tx))),
+                BundleItem::Hash { .. } => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod mev_share_tests {
+    use super::*;
+    use crate::mock::{serve_http, HttpResponse};
+    use crate::pools::DexVariant;
+    use crate::streams::{stream_mev_share_hints, Event};
+    use ethers::abi::encode;
+    use std::{
+        str::FromStr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tokio::sync::broadcast;
+
+    const POOL: &str = "0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc";
+    const HINT_HASH: &str = "0x1111111111111111111111111111111111111111111111111111111111111111";
+
+    fn pools() -> HashMap<H160, Pool> {
+        let address = H160::from_str(POOL).unwrap();
+        HashMap::from([(
+            address,
+            Pool {
+                address,
+                version: DexVariant::UniswapV2,
+                token0: H160::from_low_u64_be(1),
+                token1: H160::from_low_u64_be(2),
+                decimals0: 6,
+                decimals1: 18,
+                fee: 300,
+                router: H160::zero(),
+            },
+        )])
+    }
+
+    fn hint_json(logs: serde_json::Value) -> String {
+        serde_json::json!({
+            "hash": HINT_HASH,
+            "logs": logs,
+            "txs": null,
+            "mevGasPrice": "0x3b9aca00",
+            "gasUsed": "0x30d40",
+        })
+        .to_string()
+    }
+
+    fn uints(values: &[u64]) -> String {
+        let tokens: Vec<Token> = values.iter().map(|v| Token::Uint((*v).into())).collect();
+        format!("0x{}", hex::encode(encode(&tokens)))
+    }
+
+    #[test]
+    fn sse_parser_handles_split_chunks() {
+        let mut parser = SseParser::new();
+        assert!(parser.feed(b": ping\n\nda").is_empty());
+        assert!(parser.feed(b"ta: {\"a\":\r\n").is_empty());
+        let events = parser.feed(b"data: 1}\r\n\r\ndata:2\n\n");
+        assert_eq!(events, vec!["{\"a\":\n1}".to_string(), "2".to_string()]);
+        assert!(parser.feed(b"\n\n").is_empty());
+    }
+
+    #[test]
+    fn hint_logs_update_reserves() {
+        let pools = pools();
+        let pool = H160::from_str(POOL).unwrap();
+        let reserves = || {
+            HashMap::from([(
+                pool,
+                Reserve {
+                    reserve0: U256::from(1000),
+                    reserve1: U256::from(2000),
+                },
+            )])
+        };
+        let sync = format!("{:?}", H256::from(keccak256("Sync(uint112,uint112)")));
+        let swap = format!(
+            "{:?}",
+            H256::from(keccak256(
+                "Swap(address,uint256,uint256,uint256,uint256,address)"
+            ))
+        );
+
+        // 只有 Swap: 按金额推出储备量
+        let hint: Hint = serde_json::from_str(&hint_json(serde_json::json!([
+            {"address": POOL, "topics": [swap], "data": uints(&[100, 0, 0, 150])},
+        ])))
+        .unwrap();
+        let mut local = reserves();
+        assert_eq!(apply_hint_logs(&hint, &pools, &mut local), Some(vec![pool]));
+        assert_eq!(local[&pool].reserve0, U256::from(1100));
+        assert_eq!(local[&pool].reserve1, U256::from(1850));
+
+        // 有 Sync 时以 Sync 为准
+        let hint: Hint = serde_json::from_str(&hint_json(serde_json::json!([
+            {"address": POOL, "topics": [swap], "data": uints(&[100, 0, 0, 150])},
+            {"address": POOL, "topics": [sync], "data": uints(&[1101, 1849])},
+        ])))
+        .unwrap();
+        let mut local = reserves();
+        apply_hint_logs(&hint, &pools, &mut local).unwrap();
+        assert_eq!(local[&pool].reserve0, U256::from(1101));
+        assert_eq!(local[&pool].reserve1, U256::from(1849));
+
+        // 没有公开 data 的日志用不了
+        let hint: Hint = serde_json::from_str(&hint_json(serde_json::json!([
+            {"address": POOL, "topics": [swap], "data": "0x"},
+        ])))
+        .unwrap();
+        assert_eq!(hint.touched_pools(&pools), vec![pool]);
+        assert_eq!(apply_hint_logs(&hint, &pools, &mut reserves()), None);
+    }
+
+    #[test]
+    fn backrun_bundle_format() {
+        let hint_hash = H256::from_str(HINT_HASH).unwrap();
+        let tx = Bytes::from(vec![0x02, 0xf8]);
+        let bundle =
+            MevShareBundle::backrun(hint_hash, vec![tx.clone()], 100.into(), 102.into(), 90);
+        let json = serde_json::to_value(&bundle).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "version": "v0.1",
+                "inclusion": {"block": "0x64", "maxBlock": "0x66"},
+                "body": [
+                    {"hash": HINT_HASH},
+                    {"tx": "0x02f8", "canRevert": false},
+                ],
+                "validity": {"refund": [{"bodyIdx": 0, "percent": 90}]},
+            })
+        );
+        assert_eq!(
+            bundle.transaction_hashes(),
+            vec![H256::from(keccak256(&tx))]
+        );
+    }
+
+    // 本地的假事件流: 一个心跳和一条被切成两段发送的 hint
+    #[tokio::test]
+    async fn stream_receives_hints() {
+        let url = serve_http(|_| async {
+            let event = format!("data: {}\n\n", hint_json(serde_json::Value::Null));
+            let (first, second) = event.split_at(20);
+            HttpResponse::EventStream(vec![
+                ": ping\n\n".to_string(),
+                first.to_string(),
+                second.to_string(),
+            ])
+        })
+        .await
+        .unwrap();
+
+        let (event_sender, mut event_receiver) = broadcast::channel(16);
+        tokio::spawn(stream_mev_share_hints(
+            url,
+            Duration::from_secs(12),
+            event_sender,
+        ));
+        let event = tokio::time::timeout(Duration::from_secs(5), event_receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        match event {
+            Event::Hint(hint) => {
+                assert_eq!(hint.hash, H256::from_str(HINT_HASH).unwrap());
+                assert!(hint.logs().is_empty());
+                assert_eq!(hint.gas_used, Some(U256::from(200_000)));
+            }
+            _ => panic!("expected a hint"),
+        }
+    }
+
+    // 第一次连接什么都不发, 超过 HINT_STREAM_IDLE_BLOCKS 个区块后重连, 第二次连接收到 hint
+    #[tokio::test]
+    async fn stalled_stream_reconnects() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        let url = serve_http(move |_| {
+            let connection = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match connection {
+                    0 => HttpResponse::EventStream(vec![]),
+                    _ => HttpResponse::EventStream(vec![format!(
+                        "data: {}\n\n",
+                        hint_json(serde_json::Value::Null)
+                    )]),
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        let (event_sender, mut event_receiver) = broadcast::channel(16);
+        tokio::spawn(stream_mev_share_hints(
+            url,
+            Duration::from_millis(50),
+            event_sender,
+        ));
+        let event = tokio::time::timeout(Duration::from_secs(5), event_receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event, Event::Hint(_)));
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+}
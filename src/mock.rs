@@ -0,0 +1,376 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use ethers::types::U64;
+use ethers_providers::{JsonRpcClient, JsonRpcError, MockError};
+use log::info;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Debug,
+    future::Future,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::config::Config;
+use crate::constants::Env;
+use crate::signer::{KeySource, SignerConfig};
+
+// 离线测试用的 JSON-RPC 返回值 (fixture), 按方法名回放, 不需要 HTTPS_URL / WSS_URL
+// fixture 文件: {"eth_chainId": ["0x1"], "eth_getLogs": [[...], [...]]}
+// 每个方法的返回值按顺序用, 只剩一个时一直返回它; {"error": {...}} 当作 JSON-RPC 错误
+#[derive(Debug, Clone, Default)]
+pub struct Fixtures {
+    responses: Arc<Mutex<HashMap<String, VecDeque<Value>>>>,
+    // 收到的请求: (方法名, 参数), 测试里检查参数用
+    requests: Arc<Mutex<Vec<(String, Value)>>>,
+    // serve_fixtures 收到的 HTTP 请求, 测试里检查签名头用
+    http_requests: Arc<Mutex<Vec<HttpRequest>>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: String,
+    pub headers: HashMap<String, String>, // 名字都是小写
+    pub body: String,
+}
+
+impl Fixtures {
+    pub fn from_json(json: &str) -> Result<Self> {
+        let table: HashMap<String, Vec<Value>> = serde_json::from_str(json)?;
+        let fixtures = Self::default();
+        for (method, responses) in table {
+            for response in responses {
+                fixtures.push(&method, response);
+            }
+        }
+        Ok(fixtures)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn push(&self, method: &str, response: Value) -> &Self {
+        self.responses
+            .lock()
+            .unwrap()
+            .entry(method.to_string())
+            .or_default()
+            .push_back(response);
+        self
+    }
+
+    // 换掉某个方法的返回值, 回测时每个区块换一次 eth_getLogs
+    pub fn set(&self, method: &str, response: Value) -> &Self {
+        self.responses
+            .lock()
+            .unwrap()
+            .insert(method.to_string(), VecDeque::from([response]));
+        self
+    }
+
+    pub fn respond(&self, method: &str, params: Value) -> Result<Value, JsonRpcError> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push((method.to_string(), params));
+        let mut responses = self.responses.lock().unwrap();
+        let queue = match responses.get_mut(method) {
+            Some(queue) if !queue.is_empty() => queue,
+            _ => {
+                return Err(JsonRpcError {
+                    code: -32601,
+                    message: format!("No fixture for {}", method),
+                    data: None,
+                })
+            }
+        };
+        let response = match queue.len() {
+            1 => queue[0].clone(),
+            _ => queue.pop_front().unwrap_or_default(),
+        };
+        match response.as_object() {
+            Some(object) if object.len() == 1 && object.contains_key("error") => Err(
+                serde_json::from_value(object["error"].clone()).unwrap_or(JsonRpcError {
+                    code: -32000,
+                    message: object["error"].to_string(),
+                    data: None,
+                }),
+            ),
+            _ => Ok(response),
+        }
+    }
+
+    // 某个方法收到过的所有请求参数
+    pub fn requests(&self, method: &str) -> Vec<Value> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(m, _)| m == method)
+            .map(|(_, params)| params.clone())
+            .collect()
+    }
+
+    // 所有方法收到的请求数
+    pub fn request_count(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+
+    pub fn http_requests(&self, method: &str) -> Vec<HttpRequest> {
+        self.http_requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.method == method)
+            .cloned()
+            .collect()
+    }
+}
+
+// Provider::new(FixtureTransport::new(fixtures)), 代替 Provider<Ws> / Provider<Http>
+#[derive(Debug, Clone, Default)]
+pub struct FixtureTransport {
+    pub fixtures: Fixtures,
+}
+
+impl FixtureTransport {
+    pub fn new(fixtures: Fixtures) -> Self {
+        Self { fixtures }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for FixtureTransport {
+    type Error = MockError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, MockError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let params = serde_json::to_value(params)?;
+        let result = self
+            .fixtures
+            .respond(method, params)
+            .map_err(MockError::JsonRpcError)?;
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+// 本地的 HTTP JSON-RPC 服务, 给只收 URL 的代码用 (Provider<Http>, builder / relay)
+// 返回 http://127.0.0.1:<port>
+pub async fn serve_fixtures(fixtures: Fixtures) -> Result<String> {
+    serve_http(move |request| {
+        let fixtures = fixtures.clone();
+        async move {
+            fixtures.http_requests.lock().unwrap().push(request.clone());
+            let body: Value = serde_json::from_str(&request.body).unwrap_or_default();
+            HttpResponse::Json(
+                match fixtures.respond(&request.method, body["params"].clone()) {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": body["id"], "result": result}),
+                    Err(error) => json!({
+                        "jsonrpc": "2.0",
+                        "id": body["id"],
+                        "error": {"code": error.code, "message": error.message, "data": error.data},
+                    }),
+                },
+            )
+        }
+    })
+    .await
+}
+
+// serve_http 的回复
+pub enum HttpResponse {
+    Json(Value),
+    // text/event-stream: 分段发送, 发完保持连接直到客户端关闭
+    EventStream(Vec<String>),
+}
+
+// 本地的 HTTP 服务, 每个请求交给 handler, 测试里的假 RPC / 签名服务 / 事件流共用
+// 返回 http://127.0.0.1:<port>
+pub async fn serve_http<F, Fut>(handler: F) -> Result<String>
+where
+    F: Fn(HttpRequest) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = HttpResponse> + Send,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let handler = Arc::new(handler);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_request(stream, handler.as_ref()).await {
+                    info!("Error from mock HTTP server: {:?}", e);
+                }
+            });
+        }
+    });
+    Ok(url)
+}
+
+// 每个连接只处理一个请求, 回复后关闭
+async fn handle_request<F, Fut>(mut stream: TcpStream, handler: &F) -> Result<()>
+where
+    F: Fn(HttpRequest) -> Fut,
+    Fut: Future<Output = HttpResponse>,
+{
+    let request = match read_request(&mut stream).await? {
+        Some(request) => request,
+        None => return Ok(()),
+    };
+    match handler(request).await {
+        HttpResponse::Json(response) => {
+            let response = response.to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.len(),
+                response
+            );
+            stream.write_all(response.as_bytes()).await?;
+        }
+        HttpResponse::EventStream(parts) => {
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\n")
+                .await?;
+            for part in parts {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                stream.write_all(part.as_bytes()).await?;
+                stream.flush().await?;
+            }
+            let mut chunk = [0u8; 64];
+            while stream.read(&mut chunk).await? > 0 {}
+        }
+    }
+    Ok(())
+}
+
+// 读一个完整的请求; method 是 JSON-RPC 的方法名, 不是 JSON 请求体时为空
+async fn read_request(stream: &mut TcpStream) -> Result<Option<HttpRequest>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        let request = String::from_utf8_lossy(&buf).to_string();
+        if let Some(idx) = request.find("\r\n\r\n") {
+            let headers: HashMap<String, String> = request[..idx]
+                .lines()
+                .filter_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    Some((name.trim().to_lowercase(), value.trim().to_string()))
+                })
+                .collect();
+            let length = headers
+                .get("content-length")
+                .and_then(|value| value.parse::<usize>().ok())
+                .unwrap_or(0);
+            if buf.len() >= idx + 4 + length {
+                let body = request[idx + 4..idx + 4 + length].to_string();
+                let method = serde_json::from_str::<Value>(&body)
+                    .ok()
+                    .and_then(|body| body["method"].as_str().map(|m| m.to_string()))
+                    .unwrap_or_default();
+                return Ok(Some(HttpRequest {
+                    method,
+                    headers,
+                    body,
+                }));
+            }
+        }
+    }
+}
+
+// 测试私钥, 不要在链上用
+pub const TEST_EXECUTOR_KEY: &str =
+    "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+pub const TEST_FLASHBOTS_KEY: &str =
+    "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+pub const TEST_BOT_ADDRESS: &str = "0x00000000000000000000000000000000000000b0";
+
+// 主网配置, RPC 和唯一的 builder 都指向 url (serve_fixtures 返回的地址)
+// 不读环境变量和 config.toml
+pub fn mock_env(url: &str) -> Result<Env> {
+    let table = format!(
+        r#"
+        [rpc]
+        https_url = "{url}"
+        wss_url = "ws://127.0.0.1:1"
+
+        [chain]
+        chain_id = 1
+        bot_address = "{TEST_BOT_ADDRESS}"
+
+        [[builders]]
+        name = "mock"
+        url = "{url}"
+        signing = "Flashbots"
+        methods = ["eth_sendBundle", "eth_callBundle", "eth_cancelBundle"]
+        "#
+    )
+    .parse()?;
+    Ok(offline_env(Config::from_table(table)?, url))
+}
+
+// 用已有的配置, HTTPS RPC 指向 url, 用测试私钥签名; 回测时用
+pub fn offline_env(mut config: Config, url: &str) -> Env {
+    config.rpc.https_url = url.to_string();
+    Env {
+        https_url: config.rpc.https_url.clone(),
+        wss_url: config.rpc.wss_url.clone(),
+        chain_id: U64::from(config.chain.chain_id),
+        signers: SignerConfig {
+            executor: KeySource::PrivateKey(TEST_EXECUTOR_KEY.to_string()),
+            flashbots: KeySource::PrivateKey(TEST_FLASHBOTS_KEY.to_string()),
+            admin: None,
+        },
+        bot_address: format!("{:?}", config.chain.bot_address),
+        builders: config.builders.clone(),
+        config,
+    }
+}
+
+#[cfg(test)]
+mod mock_tests {
+    use super::*;
+    use ethers::types::U256;
+    use ethers_providers::{Http, Middleware, Provider};
+
+    #[tokio::test]
+    async fn fixtures_replay_in_order() {
+        let fixtures = Fixtures::from_json(
+            r#"{
+                "eth_blockNumber": ["0x1", "0x2"],
+                "eth_gasPrice": [{"error": {"code": -32000, "message": "boom"}}]
+            }"#,
+        )
+        .unwrap();
+        let provider = Provider::new(FixtureTransport::new(fixtures.clone()));
+        assert_eq!(provider.get_block_number().await.unwrap(), U64::from(1));
+        assert_eq!(provider.get_block_number().await.unwrap(), U64::from(2));
+        // 最后一个一直用下去
+        assert_eq!(provider.get_block_number().await.unwrap(), U64::from(2));
+        assert!(provider.get_gas_price().await.is_err());
+        assert!(provider.get_chainid().await.is_err());
+        assert_eq!(fixtures.requests("eth_blockNumber").len(), 3);
+
+        // 同一份 fixture 通过 HTTP
+        fixtures.push("eth_chainId", json!("0x89"));
+        let url = serve_fixtures(fixtures.clone()).await.unwrap();
+        let http = Provider::<Http>::try_from(url).unwrap();
+        assert_eq!(http.get_chainid().await.unwrap(), U256::from(137));
+        assert!(http.get_gas_price().await.is_err());
+    }
+}
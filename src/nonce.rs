@@ -0,0 +1,202 @@
+use anyhow::{anyhow, Result};
+use ethers::types::{Address, BlockNumber, U256, U64};
+use ethers_providers::Middleware;
+use tokio::sync::Mutex;
+
+#[derive(Debug, Default)]
+struct NonceState {
+    synced: bool,
+    confirmed: U256, // 链上 (含 mempool) 下一个可用的 nonce
+    next: U256,      // 单独发送的交易在当前区块里下一个分配出去的 nonce
+    block: U64,
+}
+
+// 发送钱包的本地 nonce 管理
+// 启动时同步一次, 之后在本地分配; 上链的交易通过 confirm 推进 confirmed
+// 单独发送的交易 (reserve) 在区块内依次递增, 新区块从 confirmed 重新开始
+// 同一个区块里的 bundle 互相竞争 (三角套利和尾随共用一个钱包), 最多只有一个能上链,
+// 所以 reserve_bundle 每次都从 confirmed 开始, 竞争的 bundle 拿到同一个起始 nonce;
+// 递增分配的话后面的 bundle 要等前面的上链, 前面的没上链它们也都作废
+pub struct NonceManager {
+    pub address: Address,
+    state: Mutex<NonceState>,
+}
+
+impl NonceManager {
+    pub fn new(address: Address) -> Self {
+        Self {
+            address,
+            state: Mutex::new(NonceState::default()),
+        }
+    }
+
+    pub async fn sync<M: Middleware>(&self, provider: &M) -> Result<U256> {
+        let mut state = self.state.lock().await;
+        self._sync(provider, &mut state).await
+    }
+
+    async fn _sync<M: Middleware>(&self, provider: &M, state: &mut NonceState) -> Result<U256> {
+        let nonce = provider
+            .get_transaction_count(self.address, Some(BlockNumber::Pending.into()))
+            .await
+            .map_err(|e| anyhow!("Error syncing nonce: {:?}", e))?;
+        state.synced = true;
+        state.confirmed = nonce;
+        state.next = nonce;
+        Ok(nonce)
+    }
+
+    // 单独发送的交易: 一次分配 count 个连续的 nonce, 返回第一个
+    pub async fn reserve<M: Middleware>(&self, provider: &M, count: u64) -> Result<U256> {
+        let mut state = self.state.lock().await;
+        if !state.synced {
+            self._sync(provider, &mut state).await?;
+        }
+        let nonce = state.next;
+        state.next += U256::from(count);
+        Ok(nonce)
+    }
+
+    // 给一个 bundle 分配 count 个连续的 nonce, 返回第一个
+    // 同一个 bundle 里的多笔交易要一起分配; 不同的 bundle 从同一个 nonce 开始
+    pub async fn reserve_bundle<M: Middleware>(&self, provider: &M, count: u64) -> Result<U256> {
+        let mut state = self.state.lock().await;
+        if !state.synced {
+            self._sync(provider, &mut state).await?;
+        }
+        if count == 0 {
+            return Err(anyhow!("Cannot reserve 0 nonces"));
+        }
+        Ok(state.confirmed)
+    }
+
+    // 新区块: 上一个区块分配出去但没上链的 nonce 可以重新用
+    // 区块号往回走说明发生了重组, 下次分配前重新同步
+    pub async fn on_new_block(&self, block_number: U64) {
+        let mut state = self.state.lock().await;
+        if block_number < state.block {
+            state.synced = false;
+        } else if block_number > state.block {
+            state.next = state.confirmed;
+        }
+        state.block = block_number;
+    }
+
+    // 用了这个 nonce 的交易已经上链
+    pub async fn confirm(&self, nonce: U256) {
+        let mut state = self.state.lock().await;
+        state.confirmed = std::cmp::max(state.confirmed, nonce + 1);
+        state.next = std::cmp::max(state.next, state.confirmed);
+    }
+
+    // nonce 出错时调用, 下次分配前重新同步
+    pub async fn invalidate(&self) {
+        self.state.lock().await.synced = false;
+    }
+}
+
+pub fn is_nonce_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("nonce too low")
+        || message.contains("nonce too high")
+        || message.contains("invalid nonce")
+}
+
+#[cfg(test)]
+mod nonce_tests {
+    use super::*;
+    use crate::mock::{FixtureTransport, Fixtures};
+    use ethers_providers::Provider;
+    use serde_json::json;
+
+    fn provider(nonces: &[&str]) -> (Provider<FixtureTransport>, Fixtures) {
+        let fixtures = Fixtures::default();
+        for nonce in nonces {
+            fixtures.push("eth_getTransactionCount", json!(nonce));
+        }
+        (
+            Provider::new(FixtureTransport::new(fixtures.clone())),
+            fixtures,
+        )
+    }
+
+    #[tokio::test]
+    async fn competing_bundles_share_the_base_nonce() {
+        let (provider, fixtures) = provider(&["0x7"]);
+        let manager = NonceManager::new(Address::from_low_u64_be(0xb0));
+        manager.on_new_block(U64::from(100)).await;
+        // 三角套利的 bundle 两笔交易, 尾随的 bundle 一笔, 都从 7 开始
+        assert_eq!(
+            manager.reserve_bundle(&provider, 2).await.unwrap(),
+            U256::from(7)
+        );
+        assert_eq!(
+            manager.reserve_bundle(&provider, 1).await.unwrap(),
+            U256::from(7)
+        );
+        assert!(manager.reserve_bundle(&provider, 0).await.is_err());
+        // 只在第一次分配时同步
+        assert_eq!(fixtures.requests("eth_getTransactionCount").len(), 1);
+        let params = &fixtures.requests("eth_getTransactionCount")[0];
+        assert_eq!(params[1], json!("pending"));
+
+        // 单独发送的交易在区块内递增
+        assert_eq!(manager.reserve(&provider, 2).await.unwrap(), U256::from(7));
+        assert_eq!(manager.reserve(&provider, 1).await.unwrap(), U256::from(9));
+
+        // 没上链的交易不占用 nonce
+        manager.on_new_block(U64::from(101)).await;
+        assert_eq!(manager.reserve(&provider, 1).await.unwrap(), U256::from(7));
+        assert_eq!(
+            manager.reserve_bundle(&provider, 1).await.unwrap(),
+            U256::from(7)
+        );
+    }
+
+    #[tokio::test]
+    async fn confirm_advances_past_landed_nonces() {
+        let (provider, _) = provider(&["0x7"]);
+        let manager = NonceManager::new(Address::from_low_u64_be(0xb0));
+        manager.reserve_bundle(&provider, 2).await.unwrap();
+        // bundle 里的两笔都上链了
+        manager.confirm(U256::from(7)).await;
+        manager.confirm(U256::from(8)).await;
+        assert_eq!(
+            manager.reserve_bundle(&provider, 1).await.unwrap(),
+            U256::from(9)
+        );
+        assert_eq!(manager.reserve(&provider, 1).await.unwrap(), U256::from(9));
+        // 旧的确认不会往回退
+        manager.confirm(U256::from(3)).await;
+        assert_eq!(
+            manager.reserve_bundle(&provider, 1).await.unwrap(),
+            U256::from(9)
+        );
+    }
+
+    #[tokio::test]
+    async fn reorg_and_invalidate_resync() {
+        let (provider, fixtures) = provider(&["0x7", "0x9", "0x4"]);
+        let manager = NonceManager::new(Address::from_low_u64_be(0xb0));
+        manager.on_new_block(U64::from(100)).await;
+        assert_eq!(manager.reserve(&provider, 1).await.unwrap(), U256::from(7));
+
+        // nonce 出错: 重新同步
+        manager.invalidate().await;
+        assert_eq!(manager.reserve(&provider, 1).await.unwrap(), U256::from(9));
+
+        // 区块号变大不用同步, 变小 (重组) 要同步
+        manager.on_new_block(U64::from(101)).await;
+        assert_eq!(manager.reserve(&provider, 1).await.unwrap(), U256::from(9));
+        manager.on_new_block(U64::from(99)).await;
+        assert_eq!(manager.reserve(&provider, 1).await.unwrap(), U256::from(4));
+        assert_eq!(fixtures.requests("eth_getTransactionCount").len(), 3);
+    }
+
+    #[test]
+    fn recognizes_nonce_errors() {
+        assert!(is_nonce_error("Nonce too low: next nonce 8, tx nonce 7"));
+        assert!(is_nonce_error("invalid nonce"));
+        assert!(!is_nonce_error("execution reverted"));
+    }
+}
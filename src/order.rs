@@ -0,0 +1,331 @@
+use anyhow::{anyhow, Result};
+use ethers::{
+    abi::{self, ParamType, Token},
+    types::{Address, Bytes, U256},
+};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+use crate::bundler::Flashloan;
+use crate::paths::PathParam;
+
+// 紧凑编码的第一个字节, abi 编码的第一个字节是 amountIn 的最高位, 实际上总是 0
+pub const PACKED_ORDER_VERSION: u8 = 1;
+const PACKED_HEADER_LEN: usize = 39;
+const PACKED_HOP_LEN: usize = 41;
+
+// 下单 calldata 的编码方式, 见 config::OrderConfig
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderEncoding {
+    // 每个字段 32 字节
+    #[default]
+    Abi,
+    // 地址 20 字节, 枚举 1 字节, 金额 16 字节, router 用注册表里的下标
+    Packed,
+}
+
+// 合约里登记的 router 列表, 紧凑编码里只放下标, 顺序必须和合约一致
+#[derive(Debug, Clone, Default)]
+pub struct RouterRegistry {
+    pub routers: Vec<Address>,
+}
+
+impl RouterRegistry {
+    pub fn new(routers: Vec<Address>) -> Self {
+        Self { routers }
+    }
+
+    pub fn index_of(&self, router: Address) -> Option<u8> {
+        let index = self.routers.iter().position(|r| *r == router)?;
+        u8::try_from(index).ok()
+    }
+
+    pub fn get(&self, index: u8) -> Option<Address> {
+        self.routers.get(index as usize).cloned()
+    }
+}
+
+// 套利合约的下单参数
+// 合约走 fallback, calldata 没有函数选择器
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderParams {
+    pub amount_in: U256,
+    pub flashloan: Flashloan,
+    pub loan_from: Address,
+    pub paths: Vec<PathParam>,
+}
+
+impl OrderParams {
+    pub fn new(
+        amount_in: U256,
+        flashloan: Flashloan,
+        loan_from: Address,
+        paths: Vec<PathParam>,
+    ) -> Self {
+        Self {
+            amount_in,
+            flashloan,
+            loan_from,
+            paths,
+        }
+    }
+
+    pub fn to_tokens(&self) -> Vec<Token> {
+        let mut params = vec![
+            Token::Uint(self.amount_in),
+            Token::Uint(U256::from(self.flashloan.clone() as u64)),
+            Token::Address(self.loan_from),
+        ];
+        for path in &self.paths {
+            params.extend(path.make_params());
+        }
+        params
+    }
+
+    // abi 编码: amountIn, flashloan, loanFrom, 然后每一跳 router, tokenIn, tokenOut
+    pub fn encode(&self) -> Bytes {
+        Bytes::from(abi::encode(&self.to_tokens()))
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < 96 || (data.len() - 96) % 96 != 0 {
+            return Err(anyhow!("Invalid abi order length: {}", data.len()));
+        }
+        let nhop = (data.len() - 96) / 96;
+        let mut types = vec![
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Address,
+        ];
+        types.extend(vec![ParamType::Address; nhop * 3]);
+        let tokens = abi::decode(&types, data)?;
+
+        let flashloan = tokens[1].clone().into_uint().unwrap();
+        if flashloan > U256::from(u8::MAX) {
+            return Err(anyhow!("Unknown flashloan: {}", flashloan));
+        }
+        let paths = tokens[3..]
+            .chunks(3)
+            .map(|hop| PathParam {
+                router: hop[0].clone().into_address().unwrap(),
+                token_in: hop[1].clone().into_address().unwrap(),
+                token_out: hop[2].clone().into_address().unwrap(),
+            })
+            .collect();
+        Ok(Self {
+            amount_in: tokens[0].clone().into_uint().unwrap(),
+            flashloan: Flashloan::try_from(flashloan.as_u32() as u8)?,
+            loan_from: tokens[2].clone().into_address().unwrap(),
+            paths,
+        })
+    }
+
+    // 紧凑编码:
+    // [0] version, [1] flashloan, [2..18] amountIn (uint128), [18..38] loanFrom, [38] nhop
+    // 然后每一跳 41 字节: [0] router 下标, [1..21] tokenIn, [21..41] tokenOut
+    pub fn encode_packed(&self, registry: &RouterRegistry) -> Result<Bytes> {
+        if self.amount_in > U256::from(u128::MAX) {
+            return Err(anyhow!(
+                "amount_in does not fit in uint128: {}",
+                self.amount_in
+            ));
+        }
+        let nhop = u8::try_from(self.paths.len())
+            .map_err(|_| anyhow!("Too many hops: {}", self.paths.len()))?;
+
+        let mut data = Vec::with_capacity(PACKED_HEADER_LEN + PACKED_HOP_LEN * self.paths.len());
+        data.push(PACKED_ORDER_VERSION);
+        data.push(self.flashloan.clone() as u8);
+        let mut amount_in = [0u8; 32];
+        self.amount_in.to_big_endian(&mut amount_in);
+        data.extend_from_slice(&amount_in[16..]);
+        data.extend_from_slice(self.loan_from.as_bytes());
+        data.push(nhop);
+        for path in &self.paths {
+            let index = registry
+                .index_of(path.router)
+                .ok_or(anyhow!("Router not in registry: {:?}", path.router))?;
+            data.push(index);
+            data.extend_from_slice(path.token_in.as_bytes());
+            data.extend_from_slice(path.token_out.as_bytes());
+        }
+        Ok(Bytes::from(data))
+    }
+
+    pub fn decode_packed(data: &[u8], registry: &RouterRegistry) -> Result<Self> {
+        if data.len() < PACKED_HEADER_LEN {
+            return Err(anyhow!("Packed order too short: {}", data.len()));
+        }
+        if data[0] != PACKED_ORDER_VERSION {
+            return Err(anyhow!("Unknown packed order version: {}", data[0]));
+        }
+        let nhop = data[38] as usize;
+        if data.len() != PACKED_HEADER_LEN + PACKED_HOP_LEN * nhop {
+            return Err(anyhow!(
+                "Invalid packed order length: {} for {} hops",
+                data.len(),
+                nhop
+            ));
+        }
+        let mut paths = Vec::new();
+        for hop in data[PACKED_HEADER_LEN..].chunks(PACKED_HOP_LEN) {
+            let router = registry
+                .get(hop[0])
+                .ok_or(anyhow!("Unknown router index: {}", hop[0]))?;
+            paths.push(PathParam {
+                router,
+                token_in: Address::from_slice(&hop[1..21]),
+                token_out: Address::from_slice(&hop[21..41]),
+            });
+        }
+        Ok(Self {
+            amount_in: U256::from_big_endian(&data[2..18]),
+            flashloan: Flashloan::try_from(data[1])?,
+            loan_from: Address::from_slice(&data[18..38]),
+            paths,
+        })
+    }
+
+    pub fn encode_with(&self, encoding: OrderEncoding, registry: &RouterRegistry) -> Result<Bytes> {
+        match encoding {
+            OrderEncoding::Abi => Ok(self.encode()),
+            OrderEncoding::Packed => self.encode_packed(registry),
+        }
+    }
+
+    // 按第一个字节区分两种编码
+    pub fn decode_any(data: &[u8], registry: &RouterRegistry) -> Result<Self> {
+        match data.first() {
+            Some(&PACKED_ORDER_VERSION) => Self::decode_packed(data, registry),
+            _ => Self::decode(data),
+        }
+    }
+
+    // 排查 revert 的 bundle 时用, 打印实际发出去的参数
+    pub fn explain(&self) -> String {
+        let mut lines = vec![
+            format!("amount_in: {}", self.amount_in),
+            format!("flashloan: {:?}", self.flashloan),
+            format!("loan_from: {:?}", self.loan_from),
+            format!("hops: {}", self.paths.len()),
+        ];
+        for (i, path) in self.paths.iter().enumerate() {
+            lines.push(format!(
+                "  #{} router {:?}: {:?} -> {:?}",
+                i + 1,
+                path.router,
+                path.token_in,
+                path.token_out
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+// 16 进制的 calldata, 可以带 0x
+pub fn parse_calldata(calldata: &str) -> Result<Vec<u8>> {
+    let calldata = calldata.trim();
+    let calldata = calldata.strip_prefix("0x").unwrap_or(calldata);
+    Ok(hex::decode(calldata)?)
+}
+
+#[cfg(test)]
+mod order_tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn address(addr: &str) -> Address {
+        Address::from_str(addr).unwrap()
+    }
+
+    fn sample_order() -> (OrderParams, RouterRegistry) {
+        let sushiswap = address("0xd9e1cE17f2641f24aE83637ab66a2cca9C378B9F");
+        let uniswap = address("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D");
+        let usdc = address("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+        let weth = address("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+        let order = OrderParams::new(
+            U256::from(1_000_000_000u64),
+            Flashloan::Balancer,
+            address("0xBA12222222228d8Ba445958a75a0704d566BF2C8"),
+            vec![
+                PathParam {
+                    router: uniswap,
+                    token_in: usdc,
+                    token_out: weth,
+                },
+                PathParam {
+                    router: sushiswap,
+                    token_in: weth,
+                    token_out: usdc,
+                },
+            ],
+        );
+        (order, RouterRegistry::new(vec![sushiswap, uniswap]))
+    }
+
+    #[test]
+    fn packed_layout() {
+        let (order, registry) = sample_order();
+        let data = order.encode_packed(&registry).unwrap();
+        let expected = concat!(
+            "01",                                       // version
+            "01",                                       // Balancer
+            "0000000000000000000000003b9aca00",         // amountIn
+            "ba12222222228d8ba445958a75a0704d566bf2c8", // loanFrom
+            "02",                                       // nhop
+            "01",                                       // uniswap
+            "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+            "00", // sushiswap
+            "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+            "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+        );
+        assert_eq!(hex::encode(&data), expected);
+        assert!(data.len() < order.encode().len() / 2);
+    }
+
+    #[test]
+    fn round_trip() {
+        let (order, registry) = sample_order();
+        let abi_data = order.encode();
+        assert_eq!(abi_data.len(), 32 * (3 + 3 * 2));
+        assert_eq!(OrderParams::decode(&abi_data).unwrap(), order);
+        assert_eq!(
+            OrderParams::decode_any(&abi_data, &registry).unwrap(),
+            order
+        );
+
+        let packed = order.encode_packed(&registry).unwrap();
+        assert_eq!(
+            OrderParams::decode_packed(&packed, &registry).unwrap(),
+            order
+        );
+        assert_eq!(OrderParams::decode_any(&packed, &registry).unwrap(), order);
+    }
+
+    #[test]
+    fn explain_decoded_calldata() {
+        let (order, registry) = sample_order();
+        let calldata = format!("0x{}", hex::encode(order.encode()));
+        let decoded = OrderParams::decode_any(&parse_calldata(&calldata).unwrap(), &registry);
+        let explained = decoded.unwrap().explain();
+        assert!(explained.contains("amount_in: 1000000000"));
+        assert!(explained.contains("flashloan: Balancer"));
+        assert!(explained.contains("hops: 2"));
+        assert!(explained.contains(
+            "#2 router 0xd9e1ce17f2641f24ae83637ab66a2cca9c378b9f: \
+             0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2 -> 0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+        ));
+    }
+
+    #[test]
+    fn packed_rejects_unknown_router_and_large_amount() {
+        let (mut order, _) = sample_order();
+        assert!(order.encode_packed(&RouterRegistry::default()).is_err());
+
+        let (_, registry) = sample_order();
+        order.amount_in = U256::from(u128::MAX) + 1;
+        assert!(order.encode_packed(&registry).is_err());
+    }
+}
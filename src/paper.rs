@@ -0,0 +1,442 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ethers::types::{BlockId, Bytes, Filter, TxHash, H160, H256, U256, U64};
+use ethers::utils::keccak256;
+use ethers_providers::Middleware;
+use log::info;
+use serde::Serialize;
+use std::{
+    fs::OpenOptions,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::bundler::{decode_signed_tx, Bundler, SimulationError};
+use crate::engine::{Action, ExecutionReport, Executor};
+use crate::selector::Opportunity;
+use crate::tracker::BundleTracker;
+
+// 模拟盘里的一笔交易: 策略照常构建、签名, 交给 PaperExecutor 模拟和记账, 不提交
+#[derive(Debug, Clone)]
+pub struct PaperTrade {
+    pub strategy: String,
+    pub block: U64,     // 发现机会时的最新区块
+    pub max_block: U64, // 最后一个目标区块
+    pub opportunities: Vec<Opportunity>,
+    pub txs: Vec<Bytes>, // 我们自己签好名的交易, 按提交顺序
+    pub tx_hashes: Vec<TxHash>,
+    // 尾随 pending 交易时排在前面的用户交易
+    pub victim: Option<Bytes>,
+    // MEV-Share 的用户交易只有哈希, 没法模拟
+    pub hint: Option<H256>,
+}
+
+impl PaperTrade {
+    pub fn new(
+        strategy: &str,
+        block: U64,
+        max_block: U64,
+        opportunities: Vec<Opportunity>,
+        txs: Vec<Bytes>,
+    ) -> Self {
+        Self {
+            strategy: strategy.to_string(),
+            block,
+            max_block,
+            opportunities,
+            tx_hashes: txs.iter().map(|tx| TxHash::from(keccak256(tx))).collect(),
+            txs,
+            victim: None,
+            hint: None,
+        }
+    }
+
+    pub fn pools(&self) -> Vec<H160> {
+        let mut pools: Vec<H160> = Vec::new();
+        for opp in &self.opportunities {
+            for pool in &opp.pools {
+                if !pools.contains(pool) {
+                    pools.push(*pool);
+                }
+            }
+        }
+        pools
+    }
+
+    // 扣除 gas 后的预期利润, 和 Opportunity.profit 同一个代币单位
+    pub fn expected_profit(&self) -> i128 {
+        self.opportunities.iter().map(|opp| opp.profit).sum()
+    }
+
+    pub fn gas_cost(&self) -> i128 {
+        self.opportunities.iter().map(|opp| opp.gas_cost).sum()
+    }
+}
+
+// 对账: 发现机会之后的 reconcile_blocks 个区块里链上实际发生了什么
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reconciliation {
+    // 没有别的交易动过这些池子, 出价够的话我们能上链
+    Untouched,
+    // 池子被 first_tx 动过, 但我们的交易在窗口结束时仍然能执行
+    StillValid { first_tx: TxHash },
+    // 池子被 first_tx 动过, 之后我们的交易会 revert: 机会被别人拿走了
+    Taken { first_tx: TxHash },
+}
+
+impl Reconciliation {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Reconciliation::Untouched => "untouched",
+            Reconciliation::StillValid { .. } => "still_valid",
+            Reconciliation::Taken { .. } => "taken",
+        }
+    }
+
+    pub fn first_tx(&self) -> Option<TxHash> {
+        match self {
+            Reconciliation::Untouched => None,
+            Reconciliation::StillValid { first_tx } | Reconciliation::Taken { first_tx } => {
+                Some(*first_tx)
+            }
+        }
+    }
+}
+
+// ledger 里的一行, 每笔模拟盘交易对账之后写一次
+#[derive(Debug, Clone, Serialize)]
+pub struct LedgerRow {
+    pub timestamp: u64,
+    pub strategy: String,
+    pub block: u64,
+    pub max_block: u64,
+    pub tx_hash: String, // 我们的第一笔交易
+    pub paths: String,   // 每条路径的池子用 > 连接, 多条路径用 ; 分隔
+    pub expected_profit: i128,
+    pub gas_cost: i128,
+    pub simulated_gas: Option<u64>, // 我们自己的交易模拟出来的 gas, 模拟失败时为空
+    pub simulation: String,
+    pub reconciliation: String,
+    pub first_tx: String, // 窗口内第一个动了这些池子的交易
+}
+
+impl LedgerRow {
+    pub fn new(
+        trade: &PaperTrade,
+        simulation: &Result<U256>,
+        reconciliation: &Result<Reconciliation>,
+    ) -> Self {
+        let paths = trade
+            .opportunities
+            .iter()
+            .map(|opp| {
+                opp.pools
+                    .iter()
+                    .map(|pool| format!("{:?}", pool))
+                    .collect::<Vec<_>>()
+                    .join(">")
+            })
+            .collect::<Vec<_>>()
+            .join(";");
+        let (reconciliation, first_tx) = match reconciliation {
+            Ok(reconciliation) => (
+                reconciliation.label().to_string(),
+                reconciliation
+                    .first_tx()
+                    .map(|tx| format!("{:?}", tx))
+                    .unwrap_or_default(),
+            ),
+            Err(e) => (format!("error: {}", e), String::new()),
+        };
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            strategy: trade.strategy.clone(),
+            block: trade.block.as_u64(),
+            max_block: trade.max_block.as_u64(),
+            tx_hash: trade
+                .tx_hashes
+                .first()
+                .map(|tx| format!("{:?}", tx))
+                .unwrap_or_default(),
+            paths,
+            expected_profit: trade.expected_profit(),
+            gas_cost: trade.gas_cost(),
+            simulated_gas: simulation.as_ref().ok().map(|gas| gas.as_u64()),
+            simulation: match simulation {
+                Ok(_) => "ok".to_string(),
+                Err(e) => format!("error: {}", e),
+            },
+            reconciliation,
+            first_tx,
+        }
+    }
+}
+
+// 只追加的 CSV 文件, 新文件先写表头
+pub struct PaperLedger {
+    pub path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl PaperLedger {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn append(&self, row: &LedgerRow) -> Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        let has_headers = std::fs::metadata(&self.path).map_or(true, |meta| meta.len() == 0);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(has_headers)
+            .from_writer(file);
+        writer.serialize(row)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+// 模拟盘的执行器: 和 BundleExecutor 一样模拟, 但不提交
+// 等 reconcile_blocks 个区块之后对账, 结果写进 ledger
+pub struct PaperExecutor {
+    pub bundler: Arc<Bundler>,
+    pub tracker: BundleTracker,
+    pub ledger: PaperLedger,
+    pub reconcile_blocks: u64,
+}
+
+impl PaperExecutor {
+    pub fn new(bundler: Arc<Bundler>, tracker: BundleTracker) -> Self {
+        let dry_run = bundler.env.config.dry_run.clone();
+        Self {
+            bundler,
+            tracker,
+            ledger: PaperLedger::new(dry_run.ledger_path),
+            reconcile_blocks: dry_run.reconcile_blocks,
+        }
+    }
+
+    // 按 submission.mode 走和实盘一样的模拟: bundle 用 eth_callBundle, 单独提交用 eth_estimateGas
+    // 返回我们自己的交易用掉的 gas
+    async fn simulate(&self, trade: &PaperTrade) -> Result<U256> {
+        if trade.hint.is_some() {
+            return Err(anyhow!("MEV-Share user tx is hidden, not simulated"));
+        }
+        if self.bundler.env.config.submission.uses_bundles() {
+            let mut txs: Vec<Bytes> = trade.victim.iter().cloned().collect();
+            txs.extend(trade.txs.iter().cloned());
+            let bundle = self.bundler.to_bundle(txs, trade.block);
+            let simulated = self.bundler.simulate_bundle(&bundle).await?;
+            return Ok(simulated
+                .transactions
+                .iter()
+                .filter(|tx| trade.tx_hashes.contains(&tx.hash))
+                .fold(U256::zero(), |acc, tx| acc + tx.gas_used));
+        }
+        let mut gas_used = U256::zero();
+        for signed_tx in &trade.txs {
+            let typed = decode_signed_tx(signed_tx)?;
+            gas_used += self
+                .bundler
+                .provider
+                .estimate_gas(&typed, None)
+                .await
+                .map_err(|e| SimulationError::Revert(format!("{:?}", e)))?;
+        }
+        Ok(gas_used)
+    }
+
+    // 窗口内第一个发出 Sync 的交易就是第一个动了这些池子的交易
+    // 动过的话在窗口结束时的状态上重新执行我们的交易, revert 说明机会没了
+    async fn reconcile(&self, trade: &PaperTrade) -> Result<Reconciliation> {
+        let last_block = trade.block + self.reconcile_blocks;
+        self.tracker.wait_for_block(last_block).await?;
+        let provider = &self.bundler.provider;
+        let filter = Filter::new()
+            .address(trade.pools())
+            .topic0(H256::from(keccak256("Sync(uint112,uint112)")))
+            .from_block(trade.block + 1)
+            .to_block(last_block);
+        let logs = provider.get_logs(&filter).await?;
+        let first_tx = match logs.iter().find_map(|log| log.transaction_hash) {
+            Some(first_tx) => first_tx,
+            None => return Ok(Reconciliation::Untouched),
+        };
+        for signed_tx in &trade.txs {
+            let typed = decode_signed_tx(signed_tx)?;
+            if provider
+                .call(&typed, Some(BlockId::from(last_block)))
+                .await
+                .is_err()
+            {
+                return Ok(Reconciliation::Taken { first_tx });
+            }
+        }
+        Ok(Reconciliation::StillValid { first_tx })
+    }
+
+    async fn record(&self, action: &Action, trade: &PaperTrade) -> ExecutionReport {
+        let simulation = self.simulate(trade).await;
+        info!(
+            "Paper trade {} at block {:?}: expected profit {:?}, simulation {:?}",
+            trade.strategy,
+            trade.block,
+            trade.expected_profit(),
+            simulation
+        );
+        let reconciliation = self.reconcile(trade).await;
+        let row = LedgerRow::new(trade, &simulation, &reconciliation);
+        info!("Paper trade reconciled: {:?}", row);
+        if let Err(e) = self.ledger.append(&row) {
+            info!("Error writing paper ledger: {:?}", e);
+        }
+        let mut report = ExecutionReport::new(
+            action,
+            Err(anyhow!("Dry run, not submitted ({})", row.reconciliation)),
+        );
+        report.simulation_failed = simulation.is_err();
+        report
+    }
+}
+
+#[async_trait]
+impl Executor for PaperExecutor {
+    fn handles(&self, action: &Action) -> bool {
+        matches!(action, Action::PaperTrade(_))
+    }
+
+    async fn execute(&self, action: Action) -> ExecutionReport {
+        match &action {
+            Action::PaperTrade(trade) => self.record(&action, trade).await,
+            _ => {
+                info!("Dry run, skipping {:?}", action);
+                ExecutionReport::new(&action, Err(anyhow!("Dry run, not submitted")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod paper_tests {
+    use super::*;
+    use crate::flashloan::FlashloanQuote;
+    use crate::paths::ArbPath;
+    use crate::pools::{DexVariant, Pool};
+
+    fn pool(address: u64) -> Pool {
+        Pool {
+            address: H160::from_low_u64_be(address),
+            version: DexVariant::UniswapV2,
+            token0: H160::from_low_u64_be(100 + address),
+            token1: H160::from_low_u64_be(200 + address),
+            decimals0: 6,
+            decimals1: 18,
+            fee: 300,
+            router: H160::zero(),
+        }
+    }
+
+    fn trade() -> PaperTrade {
+        let path = ArbPath {
+            nhop: 3,
+            pool_1: pool(1),
+            pool_2: pool(2),
+            pool_3: pool(3),
+            zero_for_one_1: true,
+            zero_for_one_2: true,
+            zero_for_one_3: false,
+        };
+        let opportunities = vec![
+            Opportunity::new(
+                0,
+                &path,
+                U256::from(1000),
+                50,
+                20,
+                FlashloanQuote::not_used(),
+            ),
+            Opportunity::new(
+                1,
+                &path,
+                U256::from(500),
+                30,
+                10,
+                FlashloanQuote::not_used(),
+            ),
+        ];
+        PaperTrade::new(
+            "triangular",
+            U64::from(100),
+            U64::from(102),
+            opportunities,
+            vec![Bytes::from(vec![1]), Bytes::from(vec![2])],
+        )
+    }
+
+    #[test]
+    fn ledger_row_from_trade() {
+        let trade = trade();
+        assert_eq!(trade.pools().len(), 3);
+        let first_tx = TxHash::from_low_u64_be(7);
+        let row = LedgerRow::new(
+            &trade,
+            &Ok(U256::from(210_000)),
+            &Ok(Reconciliation::Taken { first_tx }),
+        );
+        assert_eq!(row.expected_profit, 80);
+        assert_eq!(row.gas_cost, 30);
+        assert_eq!(row.simulated_gas, Some(210_000));
+        assert_eq!(row.reconciliation, "taken");
+        assert_eq!(row.first_tx, format!("{:?}", first_tx));
+        assert_eq!(row.tx_hash, format!("{:?}", trade.tx_hashes[0]));
+        assert_eq!(row.paths.split(';').count(), 2);
+        assert_eq!(row.paths.split(';').next().unwrap().split('>').count(), 3);
+
+        let row = LedgerRow::new(&trade, &Err(anyhow!("revert")), &Err(anyhow!("timeout")));
+        assert_eq!(row.simulated_gas, None);
+        assert_eq!(row.simulation, "error: revert");
+        assert_eq!(row.reconciliation, "error: timeout");
+    }
+
+    #[test]
+    fn ledger_appends_with_one_header() {
+        let path = std::env::temp_dir().join(format!(
+            "paper_trades_{}_{}.csv",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let ledger = PaperLedger::new(&path);
+        let trade = trade();
+        let row = LedgerRow::new(
+            &trade,
+            &Ok(U256::from(100_000)),
+            &Ok(Reconciliation::Untouched),
+        );
+        ledger.append(&row).unwrap();
+        ledger.append(&row).unwrap();
+
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        let headers = reader.headers().unwrap().clone();
+        assert_eq!(&headers[0], "timestamp");
+        assert_eq!(&headers[10], "reconciliation");
+        let records: Vec<_> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(&records[1][2], "100");
+        assert_eq!(&records[1][8], "100000");
+        assert_eq!(&records[1][10], "untouched");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
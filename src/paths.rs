@@ -12,7 +12,7 @@ use crate::{
     simulator::UniswapV2Simulator,
     utils::Reserve,
 };
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PathParam {
     pub router: Address,
     pub token_in: Address,
@@ -40,6 +40,10 @@ pub struct ArbPath {
     pub zero_for_one_3: bool, // 第三个池子的交易方向
 }
 impl ArbPath {
+    // 按路径顺序的池子
+    pub fn pools(&self) -> Vec<Pool> {
+        (0..self.nhop).map(|i| self._get_pool(i).clone()).collect()
+    }
     pub fn has_pool(&self, pool: &H160) -> bool {
         let is_pool_1 = self.pool_1.address == *pool;
         let is_pool_2 = self.pool_2.address == *pool;
@@ -64,7 +68,7 @@ impl ArbPath {
         }
         .unwrap()
     }
-    pub fn should_blacklist(&self, blacklist_tokens: &Vec<H160>) -> bool {
+    pub fn should_blacklist(&self, blacklist_tokens: &[H160]) -> bool {
         for i in 0..self.nhop {
             let pool = self._get_pool(i);
             return blacklist_tokens.contains(&pool.token0)
@@ -105,6 +109,53 @@ impl ArbPath {
         }
         Some(amount_out)
     }
+    // 按 amount_in 执行一遍路径, 并把每一跳的结果写回储备量
+    // 用于同一个区块里依次模拟多笔套利; 先在副本上算完, 中途失败时 reserves 不变
+    pub fn apply_v2_path(
+        &self,
+        amount_in: U256,
+        reserves: &mut HashMap<H160, Reserve>,
+    ) -> Option<U256> {
+        let token_in_decimals = if self.zero_for_one_1 {
+            self.pool_1.decimals0
+        } else {
+            self.pool_1.decimals1
+        };
+        let uint = U256::from(10).pow(U256::from(token_in_decimals));
+        let mut amount_out = amount_in * uint;
+        let mut updated: HashMap<H160, Reserve> = HashMap::new();
+        for i in 0..self.nhop {
+            let pool = self._get_pool(i);
+            let zero_for_one = self._get_zero_for_one(i);
+            let mut reserve = match updated.get(&pool.address) {
+                Some(reserve) => reserve.clone(),
+                None => reserves.get(&pool.address)?.clone(),
+            };
+            let amount_in = amount_out;
+            if zero_for_one {
+                amount_out = UniswapV2Simulator::get_amount_out(
+                    amount_in,
+                    reserve.reserve0,
+                    reserve.reserve1,
+                    U256::from(pool.fee),
+                )?;
+                reserve.reserve0 = reserve.reserve0.checked_add(amount_in)?;
+                reserve.reserve1 = reserve.reserve1.checked_sub(amount_out)?;
+            } else {
+                amount_out = UniswapV2Simulator::get_amount_out(
+                    amount_in,
+                    reserve.reserve1,
+                    reserve.reserve0,
+                    U256::from(pool.fee),
+                )?;
+                reserve.reserve1 = reserve.reserve1.checked_add(amount_in)?;
+                reserve.reserve0 = reserve.reserve0.checked_sub(amount_out)?;
+            }
+            updated.insert(pool.address, reserve);
+        }
+        reserves.extend(updated);
+        Some(amount_out)
+    }
     // 优化输入金额，找到最佳套利数量
     // 交易量越大，滑点越大
     // 滑点会降低实际获得的代币数量
@@ -147,8 +198,8 @@ impl ArbPath {
 
         (optimized_in, U256::from(profit))
     }
-    // 将交易路径转换为路由参数
-    pub fn to_path_params(&self, routers: &Vec<H160>) -> Vec<PathParam> {
+    // 将交易路径转换为路由参数, 每一跳走池子所在 DEX 的 router
+    pub fn to_path_params(&self) -> Vec<PathParam> {
         let mut path_params = Vec::new();
         // 遍历路径中的每一跳
         for i in 0..self.nhop {
@@ -167,9 +218,9 @@ impl ArbPath {
             }
             // 创建路径参数
             let param = PathParam {
-                router: routers[i as usize], // 使用对应的路由合约
-                token_in: token_in,          // 输入代币
-                token_out: token_out,        // 输出代币
+                router: pool.router,  // 使用对应的路由合约
+                token_in: token_in,   // 输入代币
+                token_out: token_out, // 输出代币
             };
             path_params.push(param);
         }
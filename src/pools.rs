@@ -1,14 +1,24 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use cfmms::{
     dex::{Dex, DexVariant as CfmmsDexVariant},
     pool::Pool as CfmmsPool,
     sync::sync_pairs,
 };
 use csv::StringRecord;
-use ethers::types::{Address, H160};
-use ethers_providers::{Provider, Ws};
+use ethers::{
+    types::{Address, H160},
+    utils::keccak256,
+};
+use ethers_providers::{JsonRpcClient, Provider};
 use log::info;
-use std::{path::Path, str::FromStr, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use crate::config::DexConfig;
+
 #[derive(Debug, Clone)]
 pub enum DexVariant {
     UniswapV2,
@@ -22,7 +32,8 @@ pub struct Pool {
     pub token1: Address,
     pub decimals0: u8,
     pub decimals1: u8,
-    pub fee: u32,
+    pub fee: u32,        // V2 是 cfmms 的单位 (300 = 0.3%), 见 simulator
+    pub router: Address, // 池子所在 DEX 的 router, 下单时这一跳走它
 }
 impl From<StringRecord> for Pool {
     fn from(record: StringRecord) -> Self {
@@ -39,11 +50,12 @@ impl From<StringRecord> for Pool {
             decimals0: record.get(4).unwrap().parse().unwrap(),
             decimals1: record.get(5).unwrap().parse().unwrap(),
             fee: record.get(6).unwrap().parse().unwrap(),
+            router: H160::from_str(record.get(7).unwrap()).unwrap(),
         }
     }
 }
 impl Pool {
-    pub fn cache_row(&self) -> (String, i32, String, String, u8, u8, u32) {
+    pub fn cache_row(&self) -> (String, i32, String, String, u8, u8, u32, String) {
         (
             format!("{:?}", self.address),
             match self.version {
@@ -55,72 +67,110 @@ impl Pool {
             self.decimals0,
             self.decimals1,
             self.fee,
+            format!("{:?}", self.router),
         )
     }
 }
-// 根据工厂地址和区块 取出所有的池子 并创建表格
-pub async fn load_all_pools_from_v2(
-    wss_url: String,
-    factory_addresses: Vec<&str>,
-    from_blocks: Vec<u64>,
+// 池子缓存, 有的话直接读, 不连节点
+pub const POOL_CACHE_PREFIX: &str = "src/.cached-pools";
+
+// 缓存按链和 DEX 列表分开: src/.cached-pools-<chain_id>-<DEX 列表的哈希>.csv
+// 换了 CHAIN_ID 或者改了 DEX (factory / router / fee) 就是另一个文件, 会重新同步
+pub fn pool_cache_path(chain_id: u64, dexes: &[DexConfig]) -> PathBuf {
+    let key: Vec<String> = dexes
+        .iter()
+        .map(|dex| format!("{:?}:{:?}:{}", dex.factory, dex.router, dex.fee))
+        .collect();
+    let hash = keccak256(key.join(","));
+    PathBuf::from(format!(
+        "{}-{}-{}.csv",
+        POOL_CACHE_PREFIX,
+        chain_id,
+        hex::encode(&hash[..4])
+    ))
+}
+
+// 按配置里的 DEX 取出所有的池子 并创建表格
+pub async fn load_all_pools_from_v2<P: JsonRpcClient + 'static>(
+    provider: Arc<Provider<P>>,
+    chain_id: u64,
+    dexes: &[DexConfig],
+) -> Result<Vec<Pool>> {
+    let file_path = pool_cache_path(chain_id, dexes);
+    load_all_pools_with_cache(&file_path, provider, dexes).await
+}
+
+// 缓存文件存在时直接读, 否则用 provider 同步后写入缓存 (测试里是 FixtureTransport)
+// 每个 DEX 单独同步, 池子带上这个 DEX 的 fee 和 router
+pub async fn load_all_pools_with_cache<P: JsonRpcClient + 'static>(
+    file_path: &Path,
+    provider: Arc<Provider<P>>,
+    dexes: &[DexConfig],
 ) -> Result<Vec<Pool>> {
-    let file_path = Path::new("src/.cached-pools.csv");
     if file_path.exists() {
-        let mut reader = csv::Reader::from_path(file_path)?;
-        let mut pools_vec = Vec::new();
-        for row in reader.records() {
-            let row = row.unwrap();
-            let pool = Pool::from(row);
-            pools_vec.push(pool);
-        }
-        return Ok(pools_vec);
+        return read_pool_cache(file_path);
     }
-    let ws = Ws::connect(wss_url).await?;
-    let provider = Arc::new(Provider::new(ws));
-    let mut dexes_data = Vec::new();
-    for i in 0..factory_addresses.len() {
-        dexes_data.push((
-            factory_addresses[i],
+    let mut pools_vec = Vec::new();
+    for dex in dexes {
+        let cfmms_dex = Dex::new(
+            dex.factory,
             CfmmsDexVariant::UniswapV2,
-            from_blocks[i],
-        ))
+            dex.start_block,
+            Some(dex.fee as u64),
+        );
+        let synced = sync_pairs(vec![cfmms_dex], provider.clone(), None).await?;
+        info!("{}: synced {} pools", dex.name, synced.len());
+        for pool in synced {
+            let pool = match pool {
+                CfmmsPool::UniswapV2(pool) => Pool {
+                    address: pool.address,
+                    version: DexVariant::UniswapV2,
+                    token0: pool.token_a,
+                    token1: pool.token_b,
+                    decimals0: pool.token_a_decimals,
+                    decimals1: pool.token_b_decimals,
+                    fee: dex.fee,
+                    router: dex.router,
+                },
+                CfmmsPool::UniswapV3(pool) => Pool {
+                    address: pool.address,
+                    version: DexVariant::UniswapV3,
+                    token0: pool.token_a,
+                    token1: pool.token_b,
+                    decimals0: pool.token_a_decimals,
+                    decimals1: pool.token_b_decimals,
+                    fee: pool.fee,
+                    router: dex.router,
+                },
+            };
+            pools_vec.push(pool);
+        }
     }
-    let dexes: Vec<Dex> = dexes_data
-        .into_iter()
-        .map(|(address, variant, number)| {
-            Dex::new(
-                H160::from_str(&address).unwrap(),
-                variant,
-                number,
-                Some(3000),
-            )
-        })
-        .collect();
-    let pools_vec = sync_pairs(dexes.clone(), provider.clone(), None).await?;
-    let pools_vec: Vec<Pool> = pools_vec
-        .into_iter()
-        .map(|pool| match pool {
-            CfmmsPool::UniswapV2(pool) => Pool {
-                address: pool.address,
-                version: DexVariant::UniswapV2,
-                token0: pool.token_a,
-                token1: pool.token_b,
-                decimals0: pool.token_a_decimals,
-                decimals1: pool.token_b_decimals,
-                fee: pool.fee,
-            },
-            CfmmsPool::UniswapV3(pool) => Pool {
-                address: pool.address,
-                version: DexVariant::UniswapV3,
-                token0: pool.token_a,
-                token1: pool.token_b,
-                decimals0: pool.token_a_decimals,
-                decimals1: pool.token_b_decimals,
-                fee: pool.fee,
-            },
-        })
-        .collect();
     info!("Synced to {} pools", pools_vec.len());
+    write_pool_cache(file_path, &pools_vec)?;
+
+    Ok(pools_vec)
+}
+
+pub fn read_pool_cache(file_path: &Path) -> Result<Vec<Pool>> {
+    let mut reader = csv::Reader::from_path(file_path)?;
+    let mut pools_vec = Vec::new();
+    for row in reader.records() {
+        let row = row.unwrap();
+        // 旧版本的缓存没有 router, fee 的单位也不一样, 只能重新同步
+        if row.len() < 8 {
+            return Err(anyhow!(
+                "Pool cache {} is outdated, delete it to resync",
+                file_path.display()
+            ));
+        }
+        let pool = Pool::from(row);
+        pools_vec.push(pool);
+    }
+    Ok(pools_vec)
+}
+
+pub fn write_pool_cache(file_path: &Path, pools_vec: &[Pool]) -> Result<()> {
     let mut writer = csv::Writer::from_path(file_path)?;
     writer.write_record(&[
         "address",
@@ -130,11 +180,125 @@ pub async fn load_all_pools_from_v2(
         "decimals0",
         "decimals1",
         "fee",
+        "router",
     ])?;
-    for pool in &pools_vec {
+    for pool in pools_vec {
         writer.serialize(pool.cache_row())?
     }
     writer.flush()?;
+    Ok(())
+}
 
-    Ok(pools_vec)
+#[cfg(test)]
+mod pools_tests {
+    use super::*;
+    use crate::mock::{FixtureTransport, Fixtures};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn load_pools_from_cache() {
+        let pools_vec = vec![
+            Pool {
+                address: H160::from_low_u64_be(0xa01),
+                version: DexVariant::UniswapV2,
+                token0: H160::from_low_u64_be(1),
+                token1: H160::from_low_u64_be(2),
+                decimals0: 6,
+                decimals1: 18,
+                fee: 250,
+                router: H160::from_low_u64_be(0xf2),
+            },
+            Pool {
+                address: H160::from_low_u64_be(0xa02),
+                version: DexVariant::UniswapV3,
+                token0: H160::from_low_u64_be(2),
+                token1: H160::from_low_u64_be(3),
+                decimals0: 18,
+                decimals1: 18,
+                fee: 500,
+                router: H160::zero(),
+            },
+        ];
+        let path = std::env::temp_dir().join(format!("cached_pools_{}.csv", std::process::id()));
+        write_pool_cache(&path, &pools_vec).unwrap();
+        // 有缓存时不连节点
+        let fixtures = Fixtures::default();
+        let provider = Arc::new(Provider::new(FixtureTransport::new(fixtures.clone())));
+        let read = load_all_pools_with_cache(&path, provider, &[])
+            .await
+            .unwrap();
+        assert_eq!(fixtures.request_count(), 0);
+
+        assert_eq!(read.len(), 2);
+        for (pool, cached) in pools_vec.iter().zip(&read) {
+            assert_eq!(pool.cache_row(), cached.cache_row());
+        }
+
+        // 没有 router 列的旧缓存要重新同步
+        std::fs::write(
+            &path,
+            "address,version,token0,token1,decimals0,decimals1,fee\n\
+             0x0000000000000000000000000000000000000a01,2,\
+             0x0000000000000000000000000000000000000001,\
+             0x0000000000000000000000000000000000000002,6,18,3000\n",
+        )
+        .unwrap();
+        let error = read_pool_cache(&path).unwrap_err().to_string();
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.contains("outdated"));
+    }
+
+    #[tokio::test]
+    async fn sync_pools_through_provider() {
+        let path = std::env::temp_dir().join(format!("synced_pools_{}.csv", std::process::id()));
+        let dex = DexConfig {
+            name: "mock".to_string(),
+            factory: H160::from_low_u64_be(0xf1),
+            router: H160::from_low_u64_be(0xf2),
+            start_block: 0,
+            fee: 300,
+        };
+        // 没有缓存时用传进来的 provider 同步, 节点出错时不写缓存
+        let fixtures = Fixtures::default();
+        fixtures.push(
+            "eth_blockNumber",
+            json!({"error": {"code": -32000, "message": "node down"}}),
+        );
+        let provider = Arc::new(Provider::new(FixtureTransport::new(fixtures.clone())));
+        assert!(load_all_pools_with_cache(&path, provider, &[dex])
+            .await
+            .is_err());
+        assert_eq!(fixtures.requests("eth_blockNumber").len(), 1);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn cache_path_depends_on_chain_and_dexes() {
+        let dex = DexConfig {
+            name: "uniswap_v2".to_string(),
+            factory: H160::from_low_u64_be(0xf1),
+            router: H160::from_low_u64_be(0xf2),
+            start_block: 0,
+            fee: 300,
+        };
+        let mainnet = pool_cache_path(1, &[dex.clone()]);
+        assert!(mainnet
+            .to_str()
+            .unwrap()
+            .starts_with("src/.cached-pools-1-"));
+        assert_eq!(mainnet, pool_cache_path(1, &[dex.clone()]));
+        // 换链、换 factory、改 fee 或者多一个 DEX 都不用旧的缓存
+        assert_ne!(mainnet, pool_cache_path(137, &[dex.clone()]));
+        let other = DexConfig {
+            factory: H160::from_low_u64_be(0xf3),
+            ..dex.clone()
+        };
+        assert_ne!(mainnet, pool_cache_path(1, &[other.clone()]));
+        let pancake = DexConfig {
+            fee: 250,
+            ..dex.clone()
+        };
+        assert_ne!(mainnet, pool_cache_path(1, &[pancake]));
+        assert_ne!(mainnet, pool_cache_path(1, &[dex, other]));
+    }
 }
@@ -0,0 +1,274 @@
+use ethers::types::{H160, U256};
+use std::collections::HashMap;
+
+use crate::pools::Pool;
+use crate::utils::Reserve;
+
+// 原生代币 -> 基础代币的兑换路线, 直接一跳或者经过一个中间代币两跳
+#[derive(Debug, Clone)]
+pub struct PriceRoute {
+    pub pools: Vec<Pool>,
+    pub zero_for_one: Vec<bool>,
+    pub depth: U256, // 按原生代币计的流动性深度, 取路线上最浅的一跳
+}
+
+// 用追踪中的池子给原生代币定价, 把 gas 成本换算成任意基础代币
+#[derive(Debug, Clone)]
+pub struct PriceService {
+    pub native_token: H160,
+    pub base_token: H160,
+    pub route: Option<PriceRoute>,
+}
+
+fn reserves_in_out(pool: &Pool, token_in: H160, reserve: &Reserve) -> (bool, U256, U256) {
+    if pool.token0 == token_in {
+        (true, reserve.reserve0, reserve.reserve1)
+    } else {
+        (false, reserve.reserve1, reserve.reserve0)
+    }
+}
+
+fn other_token(pool: &Pool, token: H160) -> H160 {
+    if pool.token0 == token {
+        pool.token1
+    } else {
+        pool.token0
+    }
+}
+
+impl PriceService {
+    pub fn new(native_token: H160, base_token: H160) -> Self {
+        Self {
+            native_token,
+            base_token,
+            route: None,
+        }
+    }
+
+    // 在追踪的池子里找流动性最深的路线, 储备量变化后可以重新调用
+    pub fn update_route(
+        &mut self,
+        pools: &HashMap<H160, Pool>,
+        reserves: &HashMap<H160, Reserve>,
+    ) -> Option<&PriceRoute> {
+        self.route = None;
+        if self.native_token == self.base_token {
+            return None;
+        }
+        // 按代币索引池子
+        let mut pools_by_token: HashMap<H160, Vec<&Pool>> = HashMap::new();
+        for pool in pools.values() {
+            pools_by_token.entry(pool.token0).or_default().push(pool);
+            pools_by_token.entry(pool.token1).or_default().push(pool);
+        }
+        let native_pools = match pools_by_token.get(&self.native_token) {
+            Some(native_pools) => native_pools,
+            None => return None,
+        };
+
+        let mut best: Option<PriceRoute> = None;
+        for pool_1 in native_pools {
+            let reserve_1 = match reserves.get(&pool_1.address) {
+                Some(reserve) => reserve,
+                None => continue,
+            };
+            let (zero_for_one_1, native_reserve, mid_reserve) =
+                reserves_in_out(pool_1, self.native_token, reserve_1);
+            if native_reserve.is_zero() || mid_reserve.is_zero() {
+                continue;
+            }
+            let mid_token = other_token(pool_1, self.native_token);
+            let mut candidates = Vec::new();
+            if mid_token == self.base_token {
+                // 直接一跳
+                candidates.push(PriceRoute {
+                    pools: vec![(*pool_1).clone()],
+                    zero_for_one: vec![zero_for_one_1],
+                    depth: native_reserve,
+                });
+            } else {
+                // 两跳: native -> mid -> base
+                for pool_2 in pools_by_token.get(&mid_token).into_iter().flatten() {
+                    if other_token(pool_2, mid_token) != self.base_token {
+                        continue;
+                    }
+                    let reserve_2 = match reserves.get(&pool_2.address) {
+                        Some(reserve) => reserve,
+                        None => continue,
+                    };
+                    let (zero_for_one_2, mid_reserve_2, base_reserve) =
+                        reserves_in_out(pool_2, mid_token, reserve_2);
+                    if mid_reserve_2.is_zero() || base_reserve.is_zero() {
+                        continue;
+                    }
+                    // 第二跳的深度按第一跳的价格折算成原生代币
+                    let depth_2 = mid_reserve_2 * native_reserve / mid_reserve;
+                    candidates.push(PriceRoute {
+                        pools: vec![(*pool_1).clone(), (*pool_2).clone()],
+                        zero_for_one: vec![zero_for_one_1, zero_for_one_2],
+                        depth: std::cmp::min(native_reserve, depth_2),
+                    });
+                }
+            }
+            for route in candidates {
+                if best.as_ref().map_or(true, |b| route.depth > b.depth) {
+                    best = Some(route);
+                }
+            }
+        }
+        self.route = best;
+        self.route.as_ref()
+    }
+
+    // 按中间价(不算手续费和滑点)把原生代币数量换算成基础代币
+    pub fn native_to_base(&self, amount: U256, reserves: &HashMap<H160, Reserve>) -> Option<U256> {
+        if self.native_token == self.base_token {
+            return Some(amount);
+        }
+        let route = self.route.as_ref()?;
+        let mut amount = amount;
+        for (pool, zero_for_one) in route.pools.iter().zip(route.zero_for_one.iter()) {
+            let reserve = reserves.get(&pool.address)?;
+            let (reserve_in, reserve_out) = if *zero_for_one {
+                (reserve.reserve0, reserve.reserve1)
+            } else {
+                (reserve.reserve1, reserve.reserve0)
+            };
+            amount = (amount * reserve_out).checked_div(reserve_in)?;
+        }
+        Some(amount)
+    }
+
+    // native_to_base 的反方向
+    pub fn base_to_native(&self, amount: U256, reserves: &HashMap<H160, Reserve>) -> Option<U256> {
+        if self.native_token == self.base_token {
+            return Some(amount);
+        }
+        let route = self.route.as_ref()?;
+        let mut amount = amount;
+        for (pool, zero_for_one) in route.pools.iter().zip(route.zero_for_one.iter()).rev() {
+            let reserve = reserves.get(&pool.address)?;
+            let (reserve_in, reserve_out) = if *zero_for_one {
+                (reserve.reserve1, reserve.reserve0)
+            } else {
+                (reserve.reserve0, reserve.reserve1)
+            };
+            amount = (amount * reserve_out).checked_div(reserve_in)?;
+        }
+        Some(amount)
+    }
+
+    // 下一个区块的 gas 成本(next_base_fee × gas), 换算成基础代币的最小单位
+    pub fn gas_cost_in_base(
+        &self,
+        next_base_fee: U256,
+        gas_usage: U256,
+        reserves: &HashMap<H160, Reserve>,
+    ) -> Option<U256> {
+        let gas_cost_in_native = next_base_fee * gas_usage;
+        self.native_to_base(gas_cost_in_native, reserves)
+    }
+}
+
+#[cfg(test)]
+mod pricing_tests {
+    use super::*;
+    use crate::pools::DexVariant;
+
+    fn token(i: u64) -> H160 {
+        H160::from_low_u64_be(i)
+    }
+
+    fn pool(address: u64, token0: u64, token1: u64) -> Pool {
+        Pool {
+            address: token(address),
+            version: DexVariant::UniswapV2,
+            token0: token(token0),
+            token1: token(token1),
+            decimals0: 18,
+            decimals1: 18,
+            fee: 300,
+            router: H160::zero(),
+        }
+    }
+
+    fn reserve(reserve0: u64, reserve1: u64) -> Reserve {
+        Reserve {
+            reserve0: U256::from(reserve0),
+            reserve1: U256::from(reserve1),
+        }
+    }
+
+    // 原生代币 1, 基础代币 2, 中间代币 3
+    fn market(direct_depth: u64) -> (HashMap<H160, Pool>, HashMap<H160, Reserve>) {
+        let pools = HashMap::from([
+            (token(10), pool(10, 2, 1)),
+            (token(11), pool(11, 1, 3)),
+            (token(12), pool(12, 3, 2)),
+            // 和基础代币无关的池子
+            (token(13), pool(13, 1, 4)),
+        ]);
+        let reserves = HashMap::from([
+            // 1 原生代币 = 2000 基础代币
+            (token(10), reserve(direct_depth * 2000, direct_depth)),
+            // 1 原生代币 = 4 中间代币, 1 中间代币 = 500 基础代币
+            (token(11), reserve(1_000, 4_000)),
+            (token(12), reserve(4_000, 2_000_000)),
+            (token(13), reserve(1_000_000, 1_000_000)),
+        ]);
+        (pools, reserves)
+    }
+
+    #[test]
+    fn picks_the_deepest_route() {
+        let mut pricing = PriceService::new(token(1), token(2));
+
+        // 直接一跳更深
+        let (pools, reserves) = market(5_000);
+        let route = pricing.update_route(&pools, &reserves).unwrap();
+        assert_eq!(route.pools.len(), 1);
+        assert_eq!(route.pools[0].address, token(10));
+        assert_eq!(route.zero_for_one, vec![false]);
+        assert_eq!(route.depth, U256::from(5_000));
+        assert_eq!(
+            pricing.native_to_base(U256::from(3), &reserves),
+            Some(U256::from(6_000))
+        );
+        assert_eq!(
+            pricing.base_to_native(U256::from(6_000), &reserves),
+            Some(U256::from(3))
+        );
+
+        // 直接一跳变浅后换成两跳, 深度取最浅的一跳
+        let (pools, reserves) = market(100);
+        let route = pricing.update_route(&pools, &reserves).unwrap();
+        assert_eq!(
+            route.pools.iter().map(|p| p.address).collect::<Vec<_>>(),
+            vec![token(11), token(12)]
+        );
+        assert_eq!(route.zero_for_one, vec![true, true]);
+        assert_eq!(route.depth, U256::from(1_000));
+        assert_eq!(
+            pricing.gas_cost_in_base(U256::from(2), U256::from(3), &reserves),
+            Some(U256::from(12_000))
+        );
+    }
+
+    #[test]
+    fn no_route_without_reserves() {
+        let (pools, mut reserves) = market(5_000);
+        reserves.remove(&token(10));
+        reserves.insert(token(12), reserve(0, 0));
+        let mut pricing = PriceService::new(token(1), token(2));
+        assert!(pricing.update_route(&pools, &reserves).is_none());
+        assert_eq!(pricing.native_to_base(U256::one(), &reserves), None);
+
+        // 基础代币就是原生代币时不用换算
+        let mut pricing = PriceService::new(token(2), token(2));
+        assert!(pricing.update_route(&pools, &reserves).is_none());
+        assert_eq!(
+            pricing.native_to_base(U256::from(7), &reserves),
+            Some(U256::from(7))
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use anyhow::{anyhow, Result};
+use ethers::types::{Log, Transaction};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast::{error::RecvError, Sender};
+
+use crate::config::RecorderConfig;
+use crate::streams::{Event, NewBlock};
+
+const FILE_PREFIX: &str = "events-";
+const FILE_SUFFIX: &str = ".jsonl";
+
+// 录下来的一个事件, 文件里一行一个 JSON (JSONL)
+// received_at_us 是 bot 收到事件的时间 (unix 微秒), 回测时当作时钟
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub received_at_us: i64,
+    pub event: Recorded,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Recorded {
+    Block(NewBlock),
+    Log(Log),
+    PendingTx(Transaction),
+}
+
+impl Recorded {
+    // 只录链上的数据, MEV-Share hint 和执行结果不录
+    pub fn from_event(event: &Event) -> Option<Self> {
+        match event {
+            Event::Block(block) => Some(Recorded::Block(block.clone())),
+            Event::Log(log) => Some(Recorded::Log(log.clone())),
+            Event::PendingTx(tx) => Some(Recorded::PendingTx(tx.clone())),
+            Event::Hint(_) | Event::Execution(_) => None,
+        }
+    }
+
+    pub fn into_event(self) -> Event {
+        match self {
+            Recorded::Block(block) => Event::Block(block),
+            Recorded::Log(log) => Event::Log(log),
+            Recorded::PendingTx(tx) => Event::PendingTx(tx),
+        }
+    }
+}
+
+// 读一个录下来的文件; 最后一行没有换行说明录制时进程被杀, 解析不了就跳过
+pub fn read_recorded_events(path: &Path) -> Result<Vec<RecordedEvent>> {
+    let content = fs::read_to_string(path)?;
+    let mut lines: Vec<&str> = content.split('\n').collect();
+    let partial = lines.pop().unwrap_or_default();
+    let mut events = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(line)
+            .map_err(|e| anyhow!("{}:{}: {}", path.display(), i + 1, e))?;
+        events.push(event);
+    }
+    if !partial.trim().is_empty() {
+        match serde_json::from_str(partial) {
+            Ok(event) => events.push(event),
+            Err(_) => info!(
+                "{}:{}: skipping partial line",
+                path.display(),
+                lines.len() + 1
+            ),
+        }
+    }
+    Ok(events)
+}
+
+// 文件或者 recorder 的目录 (按顺序读所有轮转出来的文件)
+pub fn read_recordings(path: &Path) -> Result<Vec<RecordedEvent>> {
+    if !path.is_dir() {
+        return read_recorded_events(path);
+    }
+    let mut events = Vec::new();
+    for file in recorded_files(path)? {
+        events.extend(read_recorded_events(&file)?);
+    }
+    Ok(events)
+}
+
+// 目录里录下来的文件, 文件名带第一个事件的时间, 排序后就是录制顺序
+pub fn recorded_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if name.starts_with(FILE_PREFIX) && name.ends_with(FILE_SUFFIX) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// 只追加的 JSONL 文件, 一行一个 RecordedEvent
+// 文件超过 max_file_bytes 换新文件, 最多留 max_files 个 (0 不删)
+pub struct EventRecorder {
+    pub dir: PathBuf,
+    pub max_file_bytes: u64,
+    pub max_files: usize,
+    writer: Option<BufWriter<File>>,
+    written: u64,
+}
+
+impl EventRecorder {
+    pub fn new(config: &RecorderConfig) -> Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        Ok(Self {
+            dir: PathBuf::from(&config.dir),
+            max_file_bytes: config.max_file_mb * 1024 * 1024,
+            max_files: config.max_files,
+            writer: None,
+            written: 0,
+        })
+    }
+
+    // 区块头写完就 flush, 进程被杀最多丢掉最后一个区块之后的事件
+    // 没写完的最后一行 replay 读的时候会跳过
+    pub fn record(&mut self, event: &RecordedEvent) -> Result<()> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+        if self.writer.is_none()
+            || (self.written > 0 && self.written + line.len() as u64 > self.max_file_bytes)
+        {
+            self.rotate(event.received_at_us)?;
+        }
+        if let Some(writer) = self.writer.as_mut() {
+            writer.write_all(&line)?;
+            self.written += line.len() as u64;
+            if let Recorded::Block(_) = event.event {
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    // 关掉当前文件, 开一个新文件, 再删掉超出 max_files 的旧文件
+    fn rotate(&mut self, received_at_us: i64) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        // 时间补齐位数, 文件名按字符串排序就是时间顺序
+        let path = self.dir.join(format!(
+            "{}{:020}{}",
+            FILE_PREFIX, received_at_us, FILE_SUFFIX
+        ));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        self.written = file.metadata()?.len();
+        self.writer = Some(BufWriter::new(file));
+
+        if self.max_files > 0 {
+            let files = recorded_files(&self.dir)?;
+            if files.len() > self.max_files {
+                for old in &files[..files.len() - self.max_files] {
+                    fs::remove_file(old)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// 订阅 Event 广播, 把区块、日志和 pending 交易录到磁盘
+// 收到的时间是这里从广播里拿到事件的时间
+pub async fn run_recorder(config: RecorderConfig, event_sender: Sender<Event>) {
+    let mut recorder = match EventRecorder::new(&config) {
+        Ok(recorder) => recorder,
+        Err(e) => {
+            info!("Error starting recorder: {:?}", e);
+            return;
+        }
+    };
+    let mut event_receiver = event_sender.subscribe();
+    loop {
+        match event_receiver.recv().await {
+            Ok(event) => {
+                if let Some(recorded) = Recorded::from_event(&event) {
+                    let event = RecordedEvent {
+                        received_at_us: SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_micros() as i64,
+                        event: recorded,
+                    };
+                    if let Err(e) = recorder.record(&event) {
+                        info!("Error recording event: {:?}", e);
+                    }
+                }
+            }
+            Err(RecvError::Lagged(n)) => info!("Recorder lagged {} events", n),
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod recorder_tests {
+    use super::*;
+    use ethers::types::H160;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn events() -> Vec<RecordedEvent> {
+        (0..10)
+            .map(|i| RecordedEvent {
+                received_at_us: 1_000_000 + i,
+                event: if i % 2 == 0 {
+                    Recorded::Block(NewBlock {
+                        block_number: (i as u64).into(),
+                        ..Default::default()
+                    })
+                } else {
+                    Recorded::Log(Log {
+                        address: H160::from_low_u64_be(i as u64),
+                        block_number: Some((i as u64 - 1).into()),
+                        ..Default::default()
+                    })
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rotates_and_reads_back_in_order() {
+        let dir = temp_dir("recorder_rotate");
+        let config = RecorderConfig {
+            enabled: true,
+            dir: dir.to_string_lossy().to_string(),
+            max_file_mb: 1,
+            max_files: 0,
+        };
+        let mut recorder = EventRecorder::new(&config).unwrap();
+        // 每个文件只放得下一两行
+        recorder.max_file_bytes = 200;
+        for event in &events() {
+            recorder.record(event).unwrap();
+        }
+        drop(recorder);
+
+        let files = recorded_files(&dir).unwrap();
+        assert!(files.len() > 1);
+        let read = read_recordings(&dir).unwrap();
+        let times: Vec<i64> = read.iter().map(|event| event.received_at_us).collect();
+        assert_eq!(times, (1_000_000..1_000_010).collect::<Vec<i64>>());
+
+        // 只留最新的两个文件
+        let mut recorder = EventRecorder::new(&RecorderConfig {
+            max_files: 2,
+            ..config
+        })
+        .unwrap();
+        recorder.max_file_bytes = 200;
+        recorder.record(&events()[9]).unwrap();
+        drop(recorder);
+        assert_eq!(recorded_files(&dir).unwrap().len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skips_partial_last_line() {
+        let dir = temp_dir("recorder_partial");
+        let config = RecorderConfig {
+            enabled: true,
+            dir: dir.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        let mut recorder = EventRecorder::new(&config).unwrap();
+        for event in &events()[..3] {
+            recorder.record(event).unwrap();
+        }
+        drop(recorder);
+        // 进程在写一行的中途被杀
+        let file = recorded_files(&dir).unwrap().remove(0);
+        let mut writer = OpenOptions::new().append(true).open(&file).unwrap();
+        writer
+            .write_all(b"{\"received_at_us\":1000003,\"ev")
+            .unwrap();
+        drop(writer);
+
+        let read = read_recordings(&file).unwrap();
+        assert_eq!(read.len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
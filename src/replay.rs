@@ -0,0 +1,398 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use ethers::types::{Log, TxHash, H160, U64};
+use ethers_providers::Provider;
+use log::info;
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::bundler::Bundler;
+use crate::config::Config;
+use crate::engine::{Action, ExecutionReport, Executor, Strategy};
+use crate::mock::{offline_env, serve_fixtures, FixtureTransport, Fixtures};
+use crate::paper::PaperTrade;
+use crate::paths::ArbPath;
+use crate::pools::Pool;
+use crate::recorder::{Recorded, RecordedEvent};
+use crate::selector::Opportunity;
+use crate::strategy::TriangularStrategy;
+use crate::streams::{Event, NewBlock};
+
+// 回测的时钟: 跟着录下来的时间走, 计算耗时按真实时间加上去
+#[derive(Debug, Default, Clone)]
+pub struct MockClock {
+    now_us: i64,
+}
+
+impl MockClock {
+    pub fn now_us(&self) -> i64 {
+        self.now_us
+    }
+
+    // 时钟只往前走
+    pub fn advance_to(&mut self, us: i64) {
+        self.now_us = self.now_us.max(us);
+    }
+
+    pub fn advance(&mut self, elapsed: Duration) {
+        self.now_us += elapsed.as_micros() as i64;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockReport {
+    pub block_number: U64,
+    pub opportunities: Vec<Opportunity>,
+    // 数据齐了、算完之后, 离下一个区块到达还剩的时间, 用来签名和提交
+    pub latency_budget_ms: i64,
+}
+
+impl BlockReport {
+    pub fn estimated_profit(&self) -> i128 {
+        self.opportunities.iter().map(|opp| opp.profit).sum()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OpportunityReport {
+    pub blocks: Vec<BlockReport>,
+}
+
+impl OpportunityReport {
+    pub fn opportunity_count(&self) -> usize {
+        self.blocks
+            .iter()
+            .map(|block| block.opportunities.len())
+            .sum()
+    }
+
+    // 扣除 gas 后的利润总和, 基础代币的最小单位
+    pub fn estimated_profit(&self) -> i128 {
+        self.blocks
+            .iter()
+            .map(|block| block.estimated_profit())
+            .sum()
+    }
+
+    // 有机会的区块的 latency budget, 从小到大
+    pub fn latency_budgets_ms(&self) -> Vec<i64> {
+        let mut budgets: Vec<i64> = self
+            .blocks
+            .iter()
+            .filter(|block| !block.opportunities.is_empty())
+            .map(|block| block.latency_budget_ms)
+            .collect();
+        budgets.sort();
+        budgets
+    }
+
+    pub fn summary(&self) -> String {
+        let budgets = self.latency_budgets_ms();
+        let blocks_with_opportunities = budgets.len();
+        let mut summary = format!(
+            "blocks: {}, blocks with opportunities: {}, opportunities: {}, estimated profit: {}",
+            self.blocks.len(),
+            blocks_with_opportunities,
+            self.opportunity_count(),
+            self.estimated_profit()
+        );
+        if !budgets.is_empty() {
+            summary += &format!(
+                ", latency budget ms (min / median): {} / {}",
+                budgets[0],
+                budgets[budgets.len() / 2]
+            );
+        }
+        summary
+    }
+}
+
+// 回测的执行器: 不提交, 只记下策略交出来的 PaperTrade
+#[derive(Debug, Default)]
+pub struct BacktestExecutor {
+    pub trades: Mutex<Vec<PaperTrade>>,
+}
+
+impl BacktestExecutor {
+    pub fn take_trades(&self) -> Vec<PaperTrade> {
+        std::mem::take(&mut *self.trades.lock().unwrap())
+    }
+}
+
+#[async_trait]
+impl Executor for BacktestExecutor {
+    fn handles(&self, action: &Action) -> bool {
+        matches!(action, Action::PaperTrade(_))
+    }
+
+    async fn execute(&self, action: Action) -> ExecutionReport {
+        if let Action::PaperTrade(trade) = &action {
+            self.trades.lock().unwrap().push(trade.clone());
+        }
+        ExecutionReport::new(&action, Ok(TxHash::zero()))
+    }
+}
+
+// 用录下来的区块头和 Sync 日志回放 TriangularStrategy, 不需要节点
+// 策略的 provider 是 FixtureTransport, 每个区块的 eth_getLogs 返回录下来的日志
+// 策略按模拟盘 (dry_run) 跑, 用测试私钥签名, 交易交给 BacktestExecutor 记下来, 不提交
+// 储备量从空开始, 池子出现过 Sync 之后才参与; 闪电贷来源的余额查不到, 只能用不需要查余额的来源
+// 不加 access list (要 eth_estimateGas)
+pub struct Backtest {
+    pub strategy: TriangularStrategy<FixtureTransport>,
+    pub fixtures: Fixtures,
+    pub executor: Arc<BacktestExecutor>,
+    pub block_time: Duration,
+    pub clock: MockClock,
+}
+
+impl Backtest {
+    pub async fn new(
+        config: &Config,
+        paths: Vec<ArbPath>,
+        pools: HashMap<H160, Pool>,
+    ) -> Result<Self> {
+        let fixtures = Fixtures::default();
+        fixtures.push("eth_chainId", json!(U64::from(config.chain.chain_id)));
+        fixtures.push("eth_getTransactionCount", json!("0x0"));
+        fixtures.push("eth_getLogs", json!([]));
+        // Bundler 只收 URL, 取 nonce 走本地的 HTTP 服务
+        let url = serve_fixtures(fixtures.clone()).await?;
+        let env = offline_env(config.clone(), &url);
+        let provider = Arc::new(Provider::new(FixtureTransport::new(fixtures.clone())));
+        let bundler = Arc::new(Bundler::from_env(env)?);
+        let mut strategy = TriangularStrategy::new(config, provider, bundler)?;
+        strategy.paths = paths;
+        strategy.pools = pools;
+        strategy.dry_run = true;
+        strategy.use_access_list = false;
+        Ok(Self {
+            strategy,
+            fixtures,
+            executor: Arc::new(BacktestExecutor::default()),
+            block_time: Duration::from_millis(config.chain.block_time_ms),
+            clock: MockClock::default(),
+        })
+    }
+
+    // 日志按所在区块分组, 区块头按区块号排序; pending 交易不影响三角套利
+    pub async fn run(&mut self, events: Vec<RecordedEvent>) -> OpportunityReport {
+        let mut blocks: Vec<(i64, NewBlock)> = Vec::new();
+        let mut logs: BTreeMap<U64, Vec<(i64, Log)>> = BTreeMap::new();
+        for event in events {
+            match event.event {
+                Recorded::Block(block) => blocks.push((event.received_at_us, block)),
+                // 重组时节点会再推一遍被移除的日志
+                Recorded::Log(log) if log.removed == Some(true) => {}
+                Recorded::Log(log) => {
+                    if let Some(block_number) = log.block_number {
+                        logs.entry(block_number)
+                            .or_default()
+                            .push((event.received_at_us, log));
+                    }
+                }
+                Recorded::PendingTx(_) => {}
+            }
+        }
+        blocks.sort_by_key(|(_, block)| block.block_number);
+        blocks.dedup_by_key(|(_, block)| block.block_number);
+
+        let mut report = OpportunityReport::default();
+        for (i, (received_at, block)) in blocks.iter().enumerate() {
+            let block_logs = logs.remove(&block.block_number).unwrap_or_default();
+            // 区块头和这个区块的日志都到了才能开始算
+            let ready_at = block_logs
+                .iter()
+                .map(|(received_at, _)| *received_at)
+                .fold(*received_at, i64::max);
+            self.clock.advance_to(ready_at);
+            let block_logs: Vec<Log> = block_logs.into_iter().map(|(_, log)| log).collect();
+            let started = Instant::now();
+            let opportunities = self.process_block(block, block_logs).await;
+            self.clock.advance(started.elapsed());
+            // 最后一个区块按出块时间估计下一个区块什么时候到
+            let deadline = match blocks.get(i + 1) {
+                Some((next_received_at, _)) => *next_received_at,
+                None => received_at + self.block_time.as_micros() as i64,
+            };
+            report.blocks.push(BlockReport {
+                block_number: block.block_number,
+                opportunities,
+                latency_budget_ms: (deadline - self.clock.now_us()) / 1000,
+            });
+        }
+        report
+    }
+
+    // 把区块交给策略, 动作交给执行器, 执行结果再发回策略, 和 Engine 一样
+    pub async fn process_block(&mut self, block: &NewBlock, logs: Vec<Log>) -> Vec<Opportunity> {
+        self.fixtures.set("eth_getLogs", json!(logs));
+        let actions = self
+            .strategy
+            .process_event(Event::Block(block.clone()))
+            .await;
+        for action in actions {
+            if !self.executor.handles(&action) {
+                info!("Backtest: skipping {:?}", action);
+                continue;
+            }
+            let report = self.executor.execute(action).await;
+            self.strategy.process_event(Event::Execution(report)).await;
+        }
+        self.executor
+            .take_trades()
+            .into_iter()
+            .flat_map(|trade| trade.opportunities)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod replay_tests {
+    use super::*;
+    use crate::config::BaseTokenConfig;
+    use crate::mock::mock_env;
+    use crate::paths::generate_triangular_paths;
+    use crate::pools::DexVariant;
+    use crate::recorder::read_recorded_events;
+    use ethers::{
+        abi::{encode, Token},
+        types::{Transaction, H256, U256},
+        utils::keccak256,
+    };
+    use std::{fs, io::Write};
+
+    fn token(i: u64) -> H160 {
+        H160::from_low_u64_be(i)
+    }
+
+    fn pool(address: u64, token0: u64, token1: u64, decimals0: u8, decimals1: u8) -> Pool {
+        Pool {
+            address: H160::from_low_u64_be(address),
+            version: DexVariant::UniswapV2,
+            token0: token(token0),
+            token1: token(token1),
+            decimals0,
+            decimals1,
+            fee: 300,
+            router: H160::zero(),
+        }
+    }
+
+    fn sync_log(block: u64, pool: u64, reserve0: U256, reserve1: U256) -> Log {
+        Log {
+            address: H160::from_low_u64_be(pool),
+            topics: vec![H256::from(keccak256("Sync(uint112,uint112)"))],
+            data: encode(&[Token::Uint(reserve0), Token::Uint(reserve1)]).into(),
+            block_number: Some(block.into()),
+            transaction_index: Some(pool.into()),
+            ..Default::default()
+        }
+    }
+
+    fn block(number: u64) -> NewBlock {
+        NewBlock {
+            block_number: number.into(),
+            base_fee: U256::exp10(10),
+            next_base_fee: U256::exp10(10),
+        }
+    }
+
+    fn event(received_at_us: i64, event: Recorded) -> RecordedEvent {
+        RecordedEvent {
+            received_at_us,
+            event,
+        }
+    }
+
+    #[test]
+    fn recorded_events_round_trip() {
+        let events = vec![
+            event(1, Recorded::Block(block(1))),
+            event(2, Recorded::Log(sync_log(1, 10, U256::one(), U256::one()))),
+            event(3, Recorded::PendingTx(Transaction::default())),
+        ];
+        let path = std::env::temp_dir().join(format!("replay_{}.jsonl", std::process::id()));
+        let mut file = fs::File::create(&path).unwrap();
+        for event in &events {
+            writeln!(file, "{}", serde_json::to_string(event).unwrap()).unwrap();
+        }
+        writeln!(file).unwrap();
+        drop(file);
+
+        let read = read_recorded_events(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.len(), 3);
+        assert_eq!(read[1].received_at_us, 2);
+        match read[1].event.clone().into_event() {
+            Event::Log(log) => assert_eq!(log.address, H160::from_low_u64_be(10)),
+            _ => panic!("expected a log"),
+        }
+        assert!(matches!(read[2].event, Recorded::PendingTx(_)));
+    }
+
+    #[tokio::test]
+    async fn backtest_reports_opportunities() {
+        // 代币 1, 基础代币 2 (6 位小数), 原生代币 3; 地址顺序决定 token0 / token1
+        // 原生代币 = 2000, 池子 11 里代币 1 = 4, 池子 12 里代币 1 = 50
+        let pools_vec = vec![
+            pool(10, 2, 3, 6, 18),
+            pool(11, 1, 3, 18, 18),
+            pool(12, 1, 2, 18, 6),
+        ];
+        let paths = generate_triangular_paths(&pools_vec, token(2));
+        let pools: HashMap<H160, Pool> = pools_vec.into_iter().map(|p| (p.address, p)).collect();
+        let mut config = mock_env("http://127.0.0.1:1").unwrap().config;
+        config.base_token = BaseTokenConfig {
+            address: token(2),
+            symbol: "USDC".to_string(),
+            decimals: 6,
+        };
+        config.chain.wrapped_native = token(3);
+        // 利润很大, 出价不设上限时 max_fee_per_gas 会超过风控, 和实盘一样不提交
+        config.thresholds.bid_ceiling_gwei = Some(10_000_000);
+        let mut backtest = Backtest::new(&config, paths, pools).await.unwrap();
+        let e6 = U256::exp10(6);
+        let e18 = U256::exp10(18);
+        let events = vec![
+            event(1_000_000, Recorded::Block(block(1))),
+            event(
+                1_100_000,
+                Recorded::Log(sync_log(1, 10, e6 * 2_000_000, e18 * 1_000)),
+            ),
+            event(
+                1_100_000,
+                Recorded::Log(sync_log(1, 11, e18 * 500_000, e18 * 1_000)),
+            ),
+            event(
+                1_200_000,
+                Recorded::Log(sync_log(1, 12, e18 * 100_000, e6 * 5_000_000)),
+            ),
+            event(13_000_000, Recorded::Block(block(2))),
+        ];
+        let report = backtest.run(events).await;
+        assert_eq!(report.blocks.len(), 2);
+        // 策略每个区块查一次 eth_getLogs, 拿到的是录下来的日志
+        let requests = backtest.fixtures.requests("eth_getLogs");
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0][0]["fromBlock"], json!("0x1"));
+        let first = &report.blocks[0];
+        assert_eq!(first.opportunities.len(), 1);
+        assert!(first.estimated_profit() > 0);
+        // 日志 1.2s 到齐, 下一个区块 13s 到
+        assert!(first.latency_budget_ms <= 11_800);
+        assert!(first.latency_budget_ms > 11_000);
+        // 没有日志的区块只重新检查已经提交过的路径, 和实盘一样
+        assert_eq!(report.blocks[1].opportunities.len(), 1);
+        assert_eq!(
+            report.blocks[1].opportunities[0].path_idx,
+            first.opportunities[0].path_idx
+        );
+        assert_eq!(report.opportunity_count(), 2);
+        assert!(report.summary().contains("blocks with opportunities: 2"));
+    }
+}
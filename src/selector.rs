@@ -0,0 +1,394 @@
+use ethers::types::{H160, U256};
+use std::collections::{HashMap, HashSet};
+
+use crate::flashloan::FlashloanQuote;
+use crate::paths::ArbPath;
+use crate::utils::Reserve;
+
+// 不超过这个数量时穷举求最优组合, 否则按利润贪心
+pub const EXACT_SELECTION_LIMIT: usize = 20;
+
+// 一个区块里找到的套利机会
+#[derive(Debug, Clone)]
+pub struct Opportunity {
+    pub path_idx: usize,
+    pub amount_in: U256,      // optimize_amount_in 的结果, 不含小数位
+    pub profit: i128,         // 扣除 gas 后的净利润
+    pub gas_cost: i128,       // 这条路径的 gas 成本, 和利润同一个代币单位
+    pub flashloan_fee: i128,  // 闪电贷手续费, 和利润同一个代币单位
+    pub loan: FlashloanQuote, // 算利润时选的闪电贷来源, 下单时按这个借
+    pub pools: Vec<H160>,
+}
+
+impl Opportunity {
+    pub fn new(
+        path_idx: usize,
+        path: &ArbPath,
+        amount_in: U256,
+        profit: i128,
+        gas_cost: i128,
+        loan: FlashloanQuote,
+    ) -> Self {
+        Self {
+            path_idx,
+            amount_in,
+            profit,
+            gas_cost,
+            flashloan_fee: loan.fee.as_u128() as i128,
+            loan,
+            pools: (0..path.nhop).map(|i| path._get_pool(i).address).collect(),
+        }
+    }
+
+    pub fn overlaps(&self, pools: &HashSet<H160>) -> bool {
+        self.pools.iter().any(|pool| pools.contains(pool))
+    }
+}
+
+// 选出利润总和最大、且互相之间没有共用池子的一组套利
+// 共用池子的套利按同一份储备量算出的金额放进同一个 bundle 会互相让对方失效
+pub fn select_opportunities(opportunities: &[Opportunity]) -> Vec<Opportunity> {
+    let mut sorted: Vec<Opportunity> = opportunities
+        .iter()
+        .filter(|opp| opp.profit > 0)
+        .cloned()
+        .collect();
+    sorted.sort_by(|a, b| b.profit.cmp(&a.profit));
+    if sorted.len() <= EXACT_SELECTION_LIMIT {
+        select_exact(&sorted)
+    } else {
+        select_greedy(&sorted)
+    }
+}
+
+// 按利润从高到低, 能放就放
+pub fn select_greedy(sorted: &[Opportunity]) -> Vec<Opportunity> {
+    let mut used_pools = HashSet::new();
+    let mut selected = Vec::new();
+    for opp in sorted {
+        if !opp.overlaps(&used_pools) {
+            used_pools.extend(opp.pools.iter().cloned());
+            selected.push(opp.clone());
+        }
+    }
+    selected
+}
+
+// 分支定界穷举, sorted 需要按利润从高到低排好
+pub fn select_exact(sorted: &[Opportunity]) -> Vec<Opportunity> {
+    // suffix[i] = sorted[i..] 的利润总和, 用来剪枝
+    let mut suffix = vec![0i128; sorted.len() + 1];
+    for i in (0..sorted.len()).rev() {
+        suffix[i] = suffix[i + 1] + sorted[i].profit;
+    }
+    let mut best = (0i128, Vec::new());
+    let mut current = Vec::new();
+    let mut used_pools = HashSet::new();
+    search(
+        sorted,
+        &suffix,
+        0,
+        0,
+        &mut current,
+        &mut used_pools,
+        &mut best,
+    );
+    best.1.into_iter().map(|i| sorted[i].clone()).collect()
+}
+
+fn search(
+    sorted: &[Opportunity],
+    suffix: &[i128],
+    idx: usize,
+    profit: i128,
+    current: &mut Vec<usize>,
+    used_pools: &mut HashSet<H160>,
+    best: &mut (i128, Vec<usize>),
+) {
+    if profit > best.0 {
+        *best = (profit, current.clone());
+    }
+    if idx == sorted.len() || profit + suffix[idx] <= best.0 {
+        return;
+    }
+    let opp = &sorted[idx];
+    if !opp.overlaps(used_pools) {
+        current.push(idx);
+        used_pools.extend(opp.pools.iter().cloned());
+        search(
+            sorted,
+            suffix,
+            idx + 1,
+            profit + opp.profit,
+            current,
+            used_pools,
+            best,
+        );
+        for pool in &opp.pools {
+            used_pools.remove(pool);
+        }
+        current.pop();
+    }
+    search(sorted, suffix, idx + 1, profit, current, used_pools, best);
+}
+
+// bundle 里的提交顺序: 先放不共用池子的最优组合, 再按利润放其余共用池子的套利
+// 后者要交给 simulate_sequentially 在前面几笔执行后的储备量上重新优化
+pub fn order_opportunities(opportunities: &[Opportunity]) -> Vec<Opportunity> {
+    let mut ordered = select_opportunities(opportunities);
+    let mut rest: Vec<Opportunity> = opportunities
+        .iter()
+        .filter(|opp| opp.profit > 0)
+        .filter(|opp| !ordered.iter().any(|s| s.path_idx == opp.path_idx))
+        .cloned()
+        .collect();
+    rest.sort_by(|a, b| b.profit.cmp(&a.profit));
+    ordered.extend(rest);
+    ordered
+}
+
+// 按顺序依次模拟选中的套利, 每一笔都在前面几笔执行后的储备量上重新优化, 金额不超过 max_amount_in
+// 金额变了要重新选闪电贷来源 (可借数量、手续费) 和算 gas: quote(path, amount_in) 返回 (gas 成本, 闪电贷来源)
+// 利润不超过 min_profit 的会被去掉, 返回的金额和利润是按顺序执行时的结果
+pub fn simulate_sequentially(
+    paths: &[ArbPath],
+    selected: &[Opportunity],
+    reserves: &HashMap<H160, Reserve>,
+    min_profit: i128,
+    max_amount_in: u64,
+    quote: impl Fn(&ArbPath, U256) -> Option<(i128, FlashloanQuote)>,
+) -> Vec<Opportunity> {
+    // optimize_amount_in 不含上限本身
+    let amount_cap = U256::from(max_amount_in.saturating_add(1).min(1000));
+    let mut local_reserves = HashMap::new();
+    for opp in selected {
+        for pool in &opp.pools {
+            if let Some(reserve) = reserves.get(pool) {
+                local_reserves.insert(*pool, reserve.clone());
+            }
+        }
+    }
+    let mut simulated = Vec::new();
+    for opp in selected {
+        let path = &paths[opp.path_idx];
+        let opt = path.optimize_amount_in(amount_cap, 10, &local_reserves);
+        let (gas_cost, loan) = match quote(path, opt.0) {
+            Some(quote) => quote,
+            None => continue,
+        };
+        let profit = (opt.1.as_u128() as i128) - gas_cost - (loan.fee.as_u128() as i128);
+        if profit <= min_profit {
+            continue;
+        }
+        if path.apply_v2_path(opt.0, &mut local_reserves).is_none() {
+            continue;
+        }
+        simulated.push(Opportunity::new(
+            opp.path_idx,
+            path,
+            opt.0,
+            profit,
+            gas_cost,
+            loan,
+        ));
+    }
+    simulated
+}
+
+#[cfg(test)]
+mod selector_tests {
+    use super::*;
+    use crate::pools::{DexVariant, Pool};
+
+    fn opportunity(path_idx: usize, pools: Vec<u64>, profit: i128) -> Opportunity {
+        Opportunity {
+            path_idx,
+            amount_in: U256::one(),
+            profit,
+            gas_cost: 0,
+            flashloan_fee: 0,
+            loan: FlashloanQuote::not_used(),
+            pools: pools.into_iter().map(H160::from_low_u64_be).collect(),
+        }
+    }
+
+    #[test]
+    fn exact_beats_greedy() {
+        // 贪心会先拿 10, 之后 6 + 6 都和它冲突
+        let opportunities = vec![
+            opportunity(0, vec![1, 2, 3], 10),
+            opportunity(1, vec![1, 4, 5], 6),
+            opportunity(2, vec![2, 6, 7], 6),
+        ];
+        let greedy = select_greedy(&opportunities);
+        assert_eq!(greedy.iter().map(|o| o.profit).sum::<i128>(), 10);
+
+        let selected = select_opportunities(&opportunities);
+        let mut idxs: Vec<usize> = selected.iter().map(|o| o.path_idx).collect();
+        idxs.sort();
+        assert_eq!(idxs, vec![1, 2]);
+    }
+
+    #[test]
+    fn selected_pools_do_not_overlap() {
+        let opportunities: Vec<Opportunity> = (0..30)
+            .map(|i| opportunity(i, vec![i as u64 % 7, 100 + i as u64], 30 - i as i128))
+            .collect();
+        let selected = select_opportunities(&opportunities);
+        let mut seen = HashSet::new();
+        for opp in &selected {
+            assert!(!opp.overlaps(&seen));
+            seen.extend(opp.pools.iter().cloned());
+        }
+        assert_eq!(selected.len(), 7);
+    }
+
+    fn pool(address: u64, token0: u64, token1: u64) -> Pool {
+        Pool {
+            address: H160::from_low_u64_be(address),
+            version: DexVariant::UniswapV2,
+            token0: H160::from_low_u64_be(token0),
+            token1: H160::from_low_u64_be(token1),
+            decimals0: 0,
+            decimals1: 0,
+            fee: 300,
+            router: H160::zero(),
+        }
+    }
+
+    fn reserve(reserve0: u64, reserve1: u64) -> Reserve {
+        Reserve {
+            reserve0: U256::from(reserve0),
+            reserve1: U256::from(reserve1),
+        }
+    }
+
+    // 1 -> 2 -> 3 -> 1, 两条路径共用第一个池子
+    fn overlapping_paths() -> (Vec<ArbPath>, HashMap<H160, Reserve>) {
+        let path = |pool_2: u64, pool_3: u64| ArbPath {
+            nhop: 3,
+            pool_1: pool(10, 1, 2),
+            pool_2: pool(pool_2, 2, 3),
+            pool_3: pool(pool_3, 3, 1),
+            zero_for_one_1: true,
+            zero_for_one_2: true,
+            zero_for_one_3: true,
+        };
+        let reserves = HashMap::from([
+            (H160::from_low_u64_be(10), reserve(100_000, 200_000)),
+            (H160::from_low_u64_be(11), reserve(100_000, 200_000)),
+            (H160::from_low_u64_be(12), reserve(100_000, 200_000)),
+            (H160::from_low_u64_be(21), reserve(100_000, 150_000)),
+            (H160::from_low_u64_be(22), reserve(100_000, 150_000)),
+        ]);
+        (vec![path(11, 12), path(21, 22)], reserves)
+    }
+
+    #[test]
+    fn overlapping_paths_are_resimulated() {
+        let (paths, reserves) = overlapping_paths();
+        let opportunities: Vec<Opportunity> = paths
+            .iter()
+            .enumerate()
+            .map(|(idx, path)| {
+                let (amount_in, profit) = path.optimize_amount_in(U256::from(1000), 10, &reserves);
+                Opportunity::new(
+                    idx,
+                    path,
+                    amount_in,
+                    profit.as_u128() as i128,
+                    0,
+                    FlashloanQuote::not_used(),
+                )
+            })
+            .collect();
+        assert!(opportunities.iter().all(|opp| opp.profit > 0));
+
+        // 共用池子, 只能选一条, 另一条排在后面
+        let ordered = order_opportunities(&opportunities);
+        assert_eq!(select_opportunities(&opportunities).len(), 1);
+        assert_eq!(ordered.len(), 2);
+        assert_eq!(ordered[0].path_idx, 0);
+
+        let no_loan = |_: &ArbPath, _: U256| Some((0, FlashloanQuote::not_used()));
+        let simulated = simulate_sequentially(&paths, &ordered, &reserves, 0, 1000, no_loan);
+        assert_eq!(simulated[0].profit, ordered[0].profit);
+        // 第二条在第一笔之后的储备量上重新优化, 利润变少
+        assert_eq!(simulated.len(), 2);
+        assert_eq!(simulated[1].path_idx, 1);
+        assert!(simulated[1].profit < ordered[1].profit);
+        let high = simulate_sequentially(
+            &paths,
+            &ordered,
+            &reserves,
+            ordered[0].profit,
+            1000,
+            no_loan,
+        );
+        assert!(high.is_empty());
+    }
+
+    #[test]
+    fn resimulated_amounts_are_capped_and_requoted() {
+        let (paths, reserves) = overlapping_paths();
+        let (amount_in, profit) = paths[0].optimize_amount_in(U256::from(1000), 10, &reserves);
+        assert!(amount_in > U256::from(20));
+        let ordered = vec![Opportunity::new(
+            0,
+            &paths[0],
+            amount_in,
+            profit.as_u128() as i128,
+            0,
+            FlashloanQuote::not_used(),
+        )];
+
+        // 闪电贷按新金额重新报价, 手续费是金额的 10%
+        let quoted = std::cell::RefCell::new(Vec::new());
+        let quote = |_: &ArbPath, amount_in: U256| {
+            quoted.borrow_mut().push(amount_in);
+            Some((
+                5,
+                FlashloanQuote {
+                    flashloan: crate::bundler::Flashloan::Balancer,
+                    loan_from: H160::from_low_u64_be(0xba),
+                    fee: amount_in / 10,
+                },
+            ))
+        };
+        let simulated = simulate_sequentially(&paths, &ordered, &reserves, 0, 20, quote);
+        assert_eq!(simulated.len(), 1);
+        // optimize_amount_in 的步长是 10, 上限 20 含在内
+        assert_eq!(simulated[0].amount_in, U256::from(20));
+        assert_eq!(*quoted.borrow(), vec![U256::from(20)]);
+        assert_eq!(
+            simulated[0].loan.flashloan,
+            crate::bundler::Flashloan::Balancer
+        );
+        assert_eq!(simulated[0].flashloan_fee, 2);
+        assert_eq!(simulated[0].gas_cost, 5);
+
+        // 新金额借不到钱就去掉
+        let none = simulate_sequentially(&paths, &ordered, &reserves, 0, 20, |_, _| None);
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn failed_apply_leaves_reserves_unchanged() {
+        let (paths, mut reserves) = overlapping_paths();
+        // 第三跳没有储备量
+        reserves.remove(&H160::from_low_u64_be(12));
+        assert!(paths[0]
+            .apply_v2_path(U256::from(100), &mut reserves)
+            .is_none());
+        let first = &reserves[&H160::from_low_u64_be(10)];
+        assert_eq!(first.reserve0, U256::from(100_000));
+        assert_eq!(first.reserve1, U256::from(200_000));
+
+        let amount_out = paths[1]
+            .apply_v2_path(U256::from(100), &mut reserves)
+            .unwrap();
+        let first = &reserves[&H160::from_low_u64_be(10)];
+        assert_eq!(first.reserve0, U256::from(100_100));
+        assert!(amount_out > U256::from(100));
+    }
+}
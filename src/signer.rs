@@ -0,0 +1,441 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ethers::{
+    signers::{LocalWallet, Signer},
+    types::{
+        transaction::{eip2718::TypedTransaction, eip712::Eip712},
+        Address, Signature,
+    },
+    utils::rlp,
+};
+use serde_json::{json, Value};
+use std::{fmt, io::Write, str::FromStr, sync::Arc, time::Duration};
+
+use crate::config::{KeyConfig, SignersConfig};
+use crate::utils::http_client;
+
+// 签名接口, LocalWallet 之外还可以换成远程签名服务
+#[async_trait]
+pub trait BotSigner: fmt::Debug + Send + Sync {
+    fn address(&self) -> Address;
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature>;
+    // EIP-191 前缀的消息签名, 和 eth_sign 一样
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature>;
+}
+
+#[async_trait]
+impl BotSigner for LocalWallet {
+    fn address(&self) -> Address {
+        Signer::address(self)
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature> {
+        Ok(Signer::sign_transaction(self, tx).await?)
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        Ok(Signer::sign_message(self, message).await?)
+    }
+}
+
+// 远程签名服务 (web3signer / clef 一类), JSON-RPC:
+// eth_signTransaction(tx) 返回签好名的 raw tx, eth_sign(address, data) 返回签名
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    pub url: String,
+    pub address: Address,
+    pub http: reqwest::Client,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str, address: Address, timeout: Duration) -> Self {
+        Self {
+            url: url.to_string(),
+            address,
+            http: http_client(timeout),
+        }
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+        let response = self
+            .http
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await?
+            .text()
+            .await?;
+        let response: Value = serde_json::from_str(&response)?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow!("Remote signer {}: {}", method, error));
+        }
+        response
+            .get("result")
+            .cloned()
+            .ok_or(anyhow!("Remote signer {}: empty result", method))
+    }
+}
+
+fn decode_hex(value: &Value) -> Result<Vec<u8>> {
+    let value = value.as_str().ok_or(anyhow!("Expected hex string"))?;
+    Ok(hex::decode(value.strip_prefix("0x").unwrap_or(value))?)
+}
+
+#[async_trait]
+impl BotSigner for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature> {
+        let mut tx = tx.clone();
+        tx.set_from(self.address);
+        // ethers 序列化交易时不带 chainId, 手动加上
+        let mut params = serde_json::to_value(&tx)?;
+        if let Some(chain_id) = tx.chain_id() {
+            params["chainId"] = json!(chain_id);
+        }
+        let raw = decode_hex(&self.call("eth_signTransaction", json!([params])).await?)?;
+        let (signed, signature) = TypedTransaction::decode_signed(&rlp::Rlp::new(&raw))?;
+        // 签名服务返回的必须是同一笔交易, 并且是这个地址签的
+        if signed.sighash() != tx.sighash() {
+            return Err(anyhow!("Remote signer returned a different transaction"));
+        }
+        if signature.recover(tx.sighash())? != self.address {
+            return Err(anyhow!("Remote signer signed with a different key"));
+        }
+        Ok(signature)
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        let params = json!([self.address, format!("0x{}", hex::encode(message))]);
+        let signature = decode_hex(&self.call("eth_sign", params).await?)?;
+        let signature = Signature::try_from(signature.as_slice())?;
+        if signature.recover(message)? != self.address {
+            return Err(anyhow!("Remote signer signed with a different key"));
+        }
+        Ok(signature)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct BotSignerError(pub String);
+
+// 实现 ethers 的 Signer, 可以直接放进 SignerMiddleware / FlashbotsMiddleware
+#[derive(Debug, Clone)]
+pub struct BotWallet {
+    pub signer: Arc<dyn BotSigner>,
+    pub chain_id: u64,
+}
+
+impl BotWallet {
+    pub fn new(signer: Arc<dyn BotSigner>, chain_id: u64) -> Self {
+        Self { signer, chain_id }
+    }
+}
+
+#[async_trait]
+impl Signer for BotWallet {
+    type Error = BotSignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        self.signer
+            .sign_message(message.as_ref())
+            .await
+            .map_err(|e| BotSignerError(e.to_string()))
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        let mut tx = tx.clone();
+        if tx.chain_id().is_none() {
+            tx.set_chain_id(self.chain_id);
+        }
+        self.signer
+            .sign_transaction(&tx)
+            .await
+            .map_err(|e| BotSignerError(e.to_string()))
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        _payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        Err(BotSignerError(
+            "EIP-712 signing is not supported".to_string(),
+        ))
+    }
+
+    fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    fn with_chain_id<T: Into<u64>>(mut self, chain_id: T) -> Self {
+        self.chain_id = chain_id.into();
+        self
+    }
+}
+
+// 私钥的来源
+#[derive(Clone)]
+pub enum KeySource {
+    PrivateKey(String),
+    // 加密的 JSON keystore, 没有密码文件时在终端输入
+    Keystore {
+        path: String,
+        password_file: Option<String>,
+    },
+    Remote {
+        url: String,
+        address: Address,
+    },
+}
+
+// 不打印私钥
+impl fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeySource::PrivateKey(_) => write!(f, "PrivateKey(<redacted>)"),
+            KeySource::Keystore {
+                path,
+                password_file,
+            } => write!(f, "Keystore({}, password_file: {:?})", path, password_file),
+            KeySource::Remote { url, address } => write!(f, "Remote({}, {:?})", url, address),
+        }
+    }
+}
+
+impl KeySource {
+    // <PREFIX>_REMOTE_SIGNER_URL + <PREFIX>_ADDRESS, <PREFIX>_KEYSTORE (+ <PREFIX>_KEYSTORE_PASSWORD_FILE),
+    // <PREFIX>_PRIVATE_KEY, 按这个顺序找, 都没有时用 fallback 这个变量里的私钥
+    pub fn from_env(prefix: &str, fallback: Option<&str>) -> Result<Option<Self>> {
+        let var = |name: &str| std::env::var(format!("{}_{}", prefix, name)).ok();
+        if let Some(url) = var("REMOTE_SIGNER_URL") {
+            let address = var("ADDRESS").ok_or(anyhow!(
+                "{}_REMOTE_SIGNER_URL requires {}_ADDRESS",
+                prefix,
+                prefix
+            ))?;
+            return Ok(Some(KeySource::Remote {
+                url,
+                address: Address::from_str(&address)?,
+            }));
+        }
+        if let Some(path) = var("KEYSTORE") {
+            return Ok(Some(KeySource::Keystore {
+                path,
+                password_file: var("KEYSTORE_PASSWORD_FILE"),
+            }));
+        }
+        if let Some(key) = var("PRIVATE_KEY") {
+            return Ok(Some(KeySource::PrivateKey(key)));
+        }
+        match fallback.and_then(|name| std::env::var(name).ok()) {
+            Some(key) => Ok(Some(KeySource::PrivateKey(key))),
+            None => Ok(None),
+        }
+    }
+
+    // timeout 只用于远程签名服务的请求
+    pub fn load(&self, chain_id: u64, timeout: Duration) -> Result<BotWallet> {
+        let signer: Arc<dyn BotSigner> = match self {
+            KeySource::PrivateKey(key) => Arc::new(key.parse::<LocalWallet>()?),
+            KeySource::Keystore {
+                path,
+                password_file,
+            } => {
+                let password = match password_file {
+                    Some(file) => std::fs::read_to_string(file)?.trim_end().to_string(),
+                    None => prompt_password(&format!("Password for {}: ", path))?,
+                };
+                Arc::new(LocalWallet::decrypt_keystore(path, password)?)
+            }
+            KeySource::Remote { url, address } => {
+                Arc::new(RemoteSigner::new(url, *address, timeout))
+            }
+        };
+        Ok(BotWallet::new(signer, chain_id))
+    }
+}
+
+// 终端输入密码, 输入时关掉回显
+fn prompt_password(prompt: &str) -> Result<String> {
+    eprint!("{}", prompt);
+    std::io::stderr().flush()?;
+    let stty = |arg: &str| {
+        std::process::Command::new("stty")
+            .arg(arg)
+            .stdin(std::process::Stdio::inherit())
+            .status()
+    };
+    let _ = stty("-echo");
+    let mut password = String::new();
+    let result = std::io::stdin().read_line(&mut password);
+    let _ = stty("echo");
+    eprintln!();
+    result?;
+    Ok(password.trim_end().to_string())
+}
+
+// 三把钥匙分开:
+// executor 发套利交易, flashbots 是 builder 那边的信誉身份 (只签请求, 不需要资金),
+// admin 是合约 owner, 用来 approve 和取回资金, 不配置时和 executor 相同
+#[derive(Debug, Clone)]
+pub struct SignerConfig {
+    pub executor: KeySource,
+    pub flashbots: KeySource,
+    pub admin: Option<KeySource>,
+}
+
+impl From<&KeyConfig> for KeySource {
+    fn from(key: &KeyConfig) -> Self {
+        match key {
+            KeyConfig::Keystore {
+                path,
+                password_file,
+            } => KeySource::Keystore {
+                path: path.clone(),
+                password_file: password_file.clone(),
+            },
+            KeyConfig::Remote { url, address } => KeySource::Remote {
+                url: url.clone(),
+                address: *address,
+            },
+        }
+    }
+}
+
+// 按 <PREFIX>_* 环境变量, 配置文件 [signers], 原来的 PRIVATE_KEY / SIGNING_KEY 的顺序找
+fn resolve_key(
+    prefix: &str,
+    key: &Option<KeyConfig>,
+    fallback: Option<&str>,
+) -> Result<Option<KeySource>> {
+    if let Some(source) = KeySource::from_env(prefix, None)? {
+        return Ok(Some(source));
+    }
+    if let Some(key) = key {
+        return Ok(Some(KeySource::from(key)));
+    }
+    KeySource::from_env(prefix, fallback)
+}
+
+pub fn load_signer_config(signers: &SignersConfig) -> Result<SignerConfig> {
+    let executor = resolve_key("EXECUTOR", &signers.executor, Some("PRIVATE_KEY"))?.ok_or(
+        anyhow!("Missing executor key: set signers.executor, EXECUTOR_* or PRIVATE_KEY"),
+    )?;
+    let flashbots = resolve_key("FLASHBOTS", &signers.flashbots, Some("SIGNING_KEY"))?.ok_or(
+        anyhow!("Missing flashbots key: set signers.flashbots, FLASHBOTS_* or SIGNING_KEY"),
+    )?;
+    let admin = resolve_key("ADMIN", &signers.admin, None)?;
+    Ok(SignerConfig {
+        executor,
+        flashbots,
+        admin,
+    })
+}
+
+#[cfg(test)]
+mod signer_tests {
+    use super::*;
+    use crate::mock::{serve_http, HttpResponse};
+    use ethers::types::{Eip1559TransactionRequest, U256};
+
+    const KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    // 本地的假签名服务, 用 LocalWallet 处理 eth_signTransaction / eth_sign
+    async fn mock_remote_signer(wallet: LocalWallet) -> String {
+        serve_http(move |request| {
+            let wallet = wallet.clone();
+            async move {
+                let body: Value = serde_json::from_str(&request.body).unwrap();
+                let params = &body["params"];
+                let result = match request.method.as_str() {
+                    "eth_signTransaction" => {
+                        let tx: TypedTransaction =
+                            serde_json::from_value(params[0].clone()).unwrap();
+                        let signature = Signer::sign_transaction(&wallet, &tx).await.unwrap();
+                        format!("0x{}", hex::encode(tx.rlp_signed(&signature)))
+                    }
+                    "eth_sign" => {
+                        let data = decode_hex(&params[1]).unwrap();
+                        let signature = Signer::sign_message(&wallet, data).await.unwrap();
+                        format!("0x{}", signature)
+                    }
+                    _ => unreachable!(),
+                };
+                HttpResponse::Json(json!({"jsonrpc": "2.0", "id": body["id"], "result": result}))
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn remote_signer_matches_local_wallet() {
+        let wallet = KEY.parse::<LocalWallet>().unwrap().with_chain_id(1u64);
+        let url = mock_remote_signer(wallet.clone()).await;
+        let remote = KeySource::Remote {
+            url,
+            address: Signer::address(&wallet),
+        }
+        .load(1, Duration::from_secs(12))
+        .unwrap();
+
+        let tx = TypedTransaction::Eip1559(
+            Eip1559TransactionRequest::new()
+                .to(Address::zero())
+                .value(U256::from(1))
+                .nonce(7)
+                .gas(21000)
+                .max_fee_per_gas(100)
+                .max_priority_fee_per_gas(1)
+                .chain_id(1),
+        );
+        // 类型化交易的 v 有 y parity 和 EIP-155 两种写法, 比较编码后的交易
+        let remote_signature = remote.sign_transaction(&tx).await.unwrap();
+        let local_signature = Signer::sign_transaction(&wallet, &tx).await.unwrap();
+        assert_eq!(
+            tx.rlp_signed(&remote_signature),
+            tx.rlp_signed(&local_signature)
+        );
+        assert_eq!(
+            remote.sign_message("0xabcd").await.unwrap(),
+            Signer::sign_message(&wallet, "0xabcd").await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn keystore_with_password_file() {
+        let dir = std::env::temp_dir().join(format!("keystore-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (wallet, name) =
+            LocalWallet::new_keystore(&dir, &mut rand::thread_rng(), "hunter2", None).unwrap();
+        let password_file = dir.join("password");
+        std::fs::write(&password_file, "hunter2\n").unwrap();
+
+        let source = KeySource::Keystore {
+            path: dir.join(name).to_string_lossy().to_string(),
+            password_file: Some(password_file.to_string_lossy().to_string()),
+        };
+        let loaded = source.load(1, Duration::from_secs(12)).unwrap();
+        assert_eq!(Signer::address(&loaded), Signer::address(&wallet));
+        assert!(!format!("{:?}", KeySource::PrivateKey(KEY.to_string())).contains(KEY));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
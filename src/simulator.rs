@@ -1,5 +1,8 @@
 use ethers::types::U256;
 
+// V2 池子 fee 的分母
+const FEE_DENOMINATOR: U256 = U256([100_000, 0, 0, 0]);
+
 pub struct UniswapV2Simulator;
 
 impl UniswapV2Simulator {
@@ -34,19 +37,74 @@ impl UniswapV2Simulator {
         reserve_out: U256,
         fee: U256,
     ) -> Option<U256> {
-        // Claude
-        // Uniswap V2 公式：
-        // amount_out = (amount_in * fee * reserve_out) / (reserve_in * 1000 + amount_in * fee)
-        let fee = U256::from(1000) - fee; // fee 应该是 997 (0.3%)
+        // Uniswap V2 公式, fee 和 cfmms 的单位一样 (300 = 0.3%, PancakeSwap 是 250):
+        // amount_out = (amount_in * (100000 - fee) * reserve_out) / (reserve_in * 100000 + amount_in * (100000 - fee))
+        let fee = FEE_DENOMINATOR.checked_sub(fee)?;
         let amount_in_with_fee = amount_in * fee;
         let numerator = amount_in_with_fee * reserve_out;
-        let denominator = (reserve_in * U256::from(1000)) + amount_in_with_fee;
+        let denominator = (reserve_in * FEE_DENOMINATOR) + amount_in_with_fee;
         numerator.checked_div(denominator)
-        // 文章计算公式
-        // let fee = fee / U256::from(100);
-        // let amount_in_with_fee = amount_in * (U256::from(1000) - fee);
-        // let numerator = amount_in_with_fee * reserve_out;
-        // let denominator = (reserve_in * 1000) + amount_in_with_fee;
-        // numerator.checked_div(denominator)
+    }
+
+    // get_amount_out 的逆运算: 要拿到 amount_out 需要输入多少
+    // amount_in = (reserve_in * amount_out * 100000) / ((reserve_out - amount_out) * (100000 - fee)) + 1
+    pub fn get_amount_in(
+        amount_out: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+        fee: U256,
+    ) -> Option<U256> {
+        if amount_out >= reserve_out {
+            return None;
+        }
+        let fee = FEE_DENOMINATOR.checked_sub(fee)?;
+        let numerator = reserve_in * amount_out * FEE_DENOMINATOR;
+        let denominator = (reserve_out - amount_out) * fee;
+        Some(numerator.checked_div(denominator)? + U256::one())
+    }
+}
+
+#[cfg(test)]
+mod simulator_tests {
+    use super::*;
+
+    #[test]
+    fn amount_in_round_trips_amount_out() {
+        let fee = U256::from(300);
+        let reserve_in = U256::from(2_000_000) * U256::exp10(6);
+        let reserve_out = U256::from(1_000) * U256::exp10(18);
+        for amount_out in [U256::exp10(15), U256::exp10(18), U256::exp10(18) * 100] {
+            let amount_in =
+                UniswapV2Simulator::get_amount_in(amount_out, reserve_in, reserve_out, fee)
+                    .unwrap();
+            // 向上取整: 刚好够, 少 1 就不够
+            let out = UniswapV2Simulator::get_amount_out(amount_in, reserve_in, reserve_out, fee)
+                .unwrap();
+            assert!(out >= amount_out);
+            let out =
+                UniswapV2Simulator::get_amount_out(amount_in - 1, reserve_in, reserve_out, fee)
+                    .unwrap();
+            assert!(out < amount_out);
+        }
+        // 不可能换出整个储备量
+        assert!(
+            UniswapV2Simulator::get_amount_in(reserve_out, reserve_in, reserve_out, fee).is_none()
+        );
+
+        // 300 和合约里的 997 / 1000 一样; PancakeSwap 的 250 换出来更多
+        let amount_in = U256::exp10(9);
+        let out = UniswapV2Simulator::get_amount_out(amount_in, reserve_in, reserve_out, fee);
+        let v2 = amount_in * 997 * reserve_out / (reserve_in * 1000 + amount_in * 997);
+        assert_eq!(out, Some(v2));
+        let pancake =
+            UniswapV2Simulator::get_amount_out(amount_in, reserve_in, reserve_out, U256::from(250));
+        assert!(pancake.unwrap() > v2);
+        assert!(UniswapV2Simulator::get_amount_out(
+            amount_in,
+            reserve_in,
+            reserve_out,
+            U256::from(100_001)
+        )
+        .is_none());
     }
 }
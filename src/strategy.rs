@@ -1,171 +1,908 @@
-use ethers::types::{Address, H160, U256};
-use ethers_providers::{Provider, Ws};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ethers::{
+    types::{Bytes, TxHash, H160, U256, U64},
+    utils::keccak256,
+};
+use ethers_providers::{JsonRpcClient, Provider, Ws};
+use futures::future::join_all;
 use log::info;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use std::{collections::HashMap, str::FromStr};
-use tokio::sync::broadcast::Sender;
 
-use crate::constants::WEI;
+use crate::bidding::{BidMode, Bidder};
+use crate::builders::new_replacement_uuid;
+use crate::bundler::{Bundler, Flashloan, PrivateTx};
+use crate::engine::{Action, ExecutionReport, Strategy};
+use crate::flashloan::{FlashloanQuote, FlashloanSelector};
+use crate::gas::GasModel;
+use crate::paper::PaperTrade;
+use crate::paths::ArbPath;
 use crate::pools::Pool;
-use crate::simulator::UniswapV2Simulator;
-use crate::utils::{batch_get_uniswap_v2_reserves, get_touched_pool_reserves};
+use crate::pricing::PriceService;
+use crate::selector::{order_opportunities, simulate_sequentially, Opportunity};
+use crate::streams::NewBlock;
+use crate::utils::{batch_get_uniswap_v2_reserves, get_touched_pool_reserves, Reserve};
 use crate::{
-    constants::{get_blacklist_tokens, Env},
+    config::{Config, DexConfig, RiskLimits, SubmissionConfig},
     paths::generate_triangular_paths,
     pools::load_all_pools_from_v2,
     streams::Event,
 };
 
-pub async fn event_handler(provider: Arc<Provider<Ws>>, event_sender: Sender<Event>) {
-    let env = Env::new();
-    // address
-    let factory_addresses = vec!["0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac"];
-    let router_addresses = vec!["0xd9e1cE17f2641f24aE83637ab66a2cca9C378B9F"];
-    let factory_blocks = vec![10794229u64];
-    let pools_vec = load_all_pools_from_v2(env.wss_url, factory_addresses, factory_blocks)
-        .await
-        .unwrap();
+// 加载所有池子, 生成以 token_in 开头的三角路径
+// 返回路径和路径上用到的池子(已去掉黑名单代币)
+pub async fn load_triangular_paths<P: JsonRpcClient + 'static>(
+    provider: Arc<Provider<P>>,
+    chain_id: u64,
+    token_in: H160,
+    dexes: &[DexConfig],
+    blacklist_tokens: &[H160],
+) -> Result<(Vec<ArbPath>, HashMap<H160, Pool>)> {
+    let pools_vec = load_all_pools_from_v2(provider, chain_id, dexes).await?;
     info!("Initial pool count: {}", pools_vec.len());
-    // Performing USDC triangular arbitrage
-    let usdc_address = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap();
-    let usdc_decimals = 6;
     // 生成所有的usdc_address交换路径 多跳为3
-    let paths = generate_triangular_paths(&pools_vec, usdc_address);
-    let blacklist_tokens = get_blacklist_tokens();
+    let paths = generate_triangular_paths(&pools_vec, token_in);
     // 三角路径池map
     let mut pools = HashMap::new();
     for path in &paths {
-        if !path.should_blacklist(&blacklist_tokens) {
+        if !path.should_blacklist(blacklist_tokens) {
             pools.insert(path.pool_1.address.clone(), path.pool_1.clone());
             pools.insert(path.pool_2.address.clone(), path.pool_2.clone());
             pools.insert(path.pool_3.address.clone(), path.pool_3.clone());
         }
     }
     info!("New pool count: {:?}", pools.len());
-    // pools_vec从所有池子转换成三角路径池 且是有关usdc_address的
-    // pools.values() - 获取 HashMap 中所有的值（Pool）的引用
-    // cloned() - 克隆每个 Pool
-    // collect() - 收集到一个新的 Vec 中
-    let pools_vec: Vec<Pool> = pools.values().cloned().collect();
-    let mut reserves =
-        batch_get_uniswap_v2_reserves(env.https_url.clone(), pools_vec.clone()).await;
-
-    // 订阅事件
-    let mut event_receiver = event_sender.subscribe();
-    //
-    loop {
-        match event_receiver.recv().await {
-            Ok(event) => match event {
-                Event::Block(block) => {
-                    info!("{:?}", block);
-                    let touched_reserves =
-                        match get_touched_pool_reserves(provider.clone(), block.block_number).await
-                        {
-                            Ok(res) => res,
-                            Err(e) => {
-                                info!("Error from get_touched_pool_reserves: {:?}", e);
-                                HashMap::new()
-                            }
-                        };
-                    // 涉及储备量变化的池子
-                    let mut touched_pools = Vec::new();
-                    for (address, reserve) in touched_reserves {
-                        if reserves.contains_key(&address) {
-                            reserves.insert(address, reserve);
-                            touched_pools.push(address);
-                        }
-                    }
-                    info!("{:?}", touched_pools);
-                    // 1. 创建存储价差的HashMap
-                    let mut spreads = HashMap::new();
-
-                    // 2. 遍历所有可能的套利路径 有价差就加入spreads
-                    // enumerate() 会给迭代器的每个元素加上一个索引
-                    for (idx, path) in (&paths).iter().enumerate() {
-                        // 3. 检查路径是否包含发生变化的池子
-                        // 只有当路径中的某个池子储备量发生变化时，才有可能出现套利机会
-                        let touched_path = touched_pools
-                            .iter()
-                            .map(|pool| path.has_pool(&pool) as i32)
-                            .sum::<i32>()
-                            >= 1;
-                        if touched_path {
-                            // 4. 模拟交易
-                            let one_token_in = U256::from(1); // 用1个代币测试
-                            let simulated = path.simulate_v2_path(one_token_in, &reserves);
-                            match simulated {
-                                Some(price_quote) => {
-                                    let one_usdc_in = one_token_in * U256::from(usdc_decimals);
-                                    let _out = price_quote.as_u128() as i128;
-                                    let _in = one_usdc_in.as_u128() as i128;
-                                    let spread = _out - _in;
-                                    if spread > 0 {
-                                        spreads.insert(idx, spread);
-                                    }
-                                }
-                                None => {}
-                            }
-                        }
-                    }
-                    // 获取 USDC-WETH 池子信息
-                    let usdc_weth_address =
-                        Address::from_str("0x397FF1542f962076d0BFE58eA045FfA2d347ACa0").unwrap();
-                    let pool = pools.get(&usdc_weth_address).unwrap();
-                    let reserve = reserves.get(&usdc_weth_address).unwrap();
-                    // 根据公式 计算 WETH 价格
-                    let weth_price = UniswapV2Simulator::reserves_to_price(
-                        reserve.reserve0,
-                        reserve.reserve1,
-                        pool.decimals0,
-                        pool.decimals1,
-                        false,
-                    );
-                    // 获取下一个区块的基础 gas 费
-                    let base_fee = block.next_base_fee;
-                    // 预估 gas 使用量
-                    let estimated_gas_usage = U256::from(550000);
-                    // 计算总 gas 成本（以 wei 为单位）
-                    let gas_cost_in_wei = base_fee * estimated_gas_usage;
-                    // 转换为 WMATIC
-                    let gas_cost_in_wmatic =
-                        (gas_cost_in_wei.as_u64() as f64) / ((*WEI).as_u64() as f64);
-                    // 转换为 USDC
-                    let gas_cost_in_usdc = weth_price * gas_cost_in_wmatic;
-                    // 调整精度
-                    let gas_cost_in_usdc =
-                        U256::from((gas_cost_in_usdc * ((10 as f64).powi(usdc_decimals))) as u64);
-                    //
-                    let mut sorted_spreads: Vec<_> = spreads.iter().collect();
-                    sorted_spreads.sort_by_key(|x| x.1);
-                    sorted_spreads.reverse();
-                    // 遍历排序后的套利机会
-                    for spread in sorted_spreads {
-                        let path_idx = spread.0;
-                        let path = &paths[*path_idx];
-                        // 优化输入金额
-                        let opt = path.optimize_amount_in(U256::from(1000), 10, &reserves);
-                        // 计算扣除 gas 后的净利润
-                        let excess_profit =
-                            (opt.1.as_u128() as i128) - (gas_cost_in_usdc.as_u128() as i128);
-
-                        // TODO
-                        if excess_profit > 0 {
-                            // 构建套利交易
-                            // 签名交易
-                            // 发送交易
-                            // 监控交易状态
-                            // 处理交易结果
+    Ok((paths, pools))
+}
+
+// 一个区块里找三角套利机会, 只用本地的储备量, 不需要节点
+pub struct OpportunitySearch<'a> {
+    pub paths: &'a [ArbPath],
+    pub reserves: &'a HashMap<H160, Reserve>,
+    pub pricing: &'a PriceService,
+    pub gas_model: &'a GasModel,
+    pub base_decimals: i32,
+    pub min_profit: i128,
+    pub max_amount_in: u64,
+}
+
+impl OpportunitySearch<'_> {
+    // 只检查 candidate 返回 true 的路径
+    // 优化输入金额后扣除 gas 和闪电贷手续费, 利润超过 min_profit 的按价差从大到小返回
+    pub fn find(
+        &self,
+        next_base_fee: U256,
+        candidate: impl Fn(usize, &ArbPath) -> bool,
+        select_loan: impl Fn(&ArbPath, U256) -> FlashloanQuote,
+    ) -> Vec<Opportunity> {
+        let reserves = self.reserves;
+        // 1. 创建存储价差的HashMap
+        let mut spreads = HashMap::new();
+
+        // 2. 遍历所有可能的套利路径 有价差就加入spreads
+        // enumerate() 会给迭代器的每个元素加上一个索引
+        for (idx, path) in self.paths.iter().enumerate() {
+            // 3. 检查路径是否需要模拟
+            if candidate(idx, path) {
+                // 4. 模拟交易
+                let one_token_in = U256::from(1); // 用1个代币测试
+                let simulated = path.simulate_v2_path(one_token_in, &reserves);
+                match simulated {
+                    Some(price_quote) => {
+                        let one_usdc_in = one_token_in * U256::exp10(self.base_decimals as usize);
+                        let _out = price_quote.as_u128() as i128;
+                        let _in = one_usdc_in.as_u128() as i128;
+                        let spread = _out - _in;
+                        if spread > 0 {
+                            spreads.insert(idx, spread);
                         }
                     }
+                    None => {}
                 }
-                Event::PendingTx(_) => {
-                    // not using pending tx
-                }
-                Event::Log(_) => {
-                    // not using logs
+            }
+        }
+        //
+        let mut sorted_spreads: Vec<_> = spreads.iter().collect();
+        sorted_spreads.sort_by_key(|x| x.1);
+        sorted_spreads.reverse();
+        // 遍历排序后的套利机会
+        let mut opportunities = Vec::new();
+        for spread in sorted_spreads {
+            let path_idx = spread.0;
+            let path = &self.paths[*path_idx];
+            // 优化输入金额
+            let opt = path.optimize_amount_in(U256::from(1000), 10, &reserves);
+            let (gas_cost_in_usdc, loan) =
+                match self.quote(next_base_fee, path, opt.0, &select_loan) {
+                    Some(quote) => quote,
+                    None => continue,
+                };
+            // 计算扣除 gas 和闪电贷手续费后的净利润
+            let flashloan_fee = loan.fee.as_u128() as i128;
+            let excess_profit = (opt.1.as_u128() as i128) - gas_cost_in_usdc - flashloan_fee;
+
+            if opt.0 > U256::from(self.max_amount_in) {
+                info!(
+                    "Path #{:?}: amount in {:?} over risk limit",
+                    path_idx, opt.0
+                );
+                continue;
+            }
+            if excess_profit > self.min_profit {
+                opportunities.push(Opportunity::new(
+                    *path_idx,
+                    path,
+                    opt.0,
+                    excess_profit,
+                    gas_cost_in_usdc,
+                    loan,
+                ));
+            }
+        }
+        opportunities
+    }
+
+    // amount_in 对应的闪电贷来源, 以及预估 gas 换算成 USDC 的成本
+    fn quote(
+        &self,
+        next_base_fee: U256,
+        path: &ArbPath,
+        amount_in: U256,
+        select_loan: impl Fn(&ArbPath, U256) -> FlashloanQuote,
+    ) -> Option<(i128, FlashloanQuote)> {
+        let loan = select_loan(path, amount_in);
+        let estimated_gas_usage = self.gas_model.estimate(path, &loan.flashloan);
+        let gas_cost =
+            self.pricing
+                .gas_cost_in_base(next_base_fee, estimated_gas_usage, self.reserves)?;
+        Some((gas_cost.as_u128() as i128, loan))
+    }
+
+    // 先放不共用池子的一组套利, 再放共用池子的, 按顺序重新模拟一遍, 最多 max_orders 个
+    // 重新模拟后金额会变, 闪电贷来源和 gas 成本跟着重新算
+    pub fn select(
+        &self,
+        opportunities: &[Opportunity],
+        max_orders: usize,
+        next_base_fee: U256,
+        select_loan: impl Fn(&ArbPath, U256) -> FlashloanQuote,
+    ) -> Vec<Opportunity> {
+        let ordered = order_opportunities(opportunities);
+        let mut selected = simulate_sequentially(
+            self.paths,
+            &ordered,
+            self.reserves,
+            self.min_profit,
+            self.max_amount_in,
+            |path, amount_in| self.quote(next_base_fee, path, amount_in, &select_loan),
+        );
+        selected.truncate(max_orders);
+        selected
+    }
+}
+
+// USDC 三角套利, 测试里 provider 换成 FixtureTransport
+pub struct TriangularStrategy<P: JsonRpcClient = Ws> {
+    pub provider: Arc<Provider<P>>,
+    pub config: Config,
+    pub bundler: Arc<Bundler>,
+    pub usdc_address: H160,
+    pub usdc_decimals: i32,
+    pub paths: Vec<ArbPath>,
+    pub pools: HashMap<H160, Pool>,
+    pub reserves: HashMap<H160, Reserve>,
+    // 把 gas 成本换算成 USDC
+    pub pricing: PriceService,
+    pub gas_model: GasModel,
+    // 按预期利润给 builder 出价
+    pub bidder: Bidder,
+    // 闪电贷来源, use_flashloan 为 false 时用合约自己的资金
+    pub flashloans: FlashloanSelector,
+    pub use_flashloan: bool,
+    // 套利交易附加 access list (每笔交易多几次 eth_estimateGas)
+    pub use_access_list: bool,
+    // 已提交还没有结果的套利交易: 交易哈希 -> (路径下标, 最后一个目标区块, 闪电贷来源)
+    pub pending_orders: HashMap<TxHash, (usize, U64, Flashloan)>,
+    // 每次提交覆盖几个区块
+    pub target_blocks: u64,
+    // 扣除成本后的最低利润, 以及单笔金额、gas 价格、每个 bundle 交易数的上限
+    pub min_profit: i128,
+    pub risk: RiskLimits,
+    // bundle 还是单独提交, 以及单独提交时的 fee 策略
+    pub submission: SubmissionConfig,
+    // 当前 bundle 里的路径, 之后每个区块都重新模拟, 还有利润就重新提交
+    pub active_paths: HashSet<usize>,
+    // 目标区块 -> replacementUuid, 重新提交时替换同一个目标区块的 bundle
+    pub replacement_uuids: HashMap<U64, String>,
+    // replacementUuid -> 最近一次提交的第一笔交易, 用来区分被替换掉的 bundle
+    pub latest_submissions: HashMap<String, TxHash>,
+    // 模拟盘, 不提交
+    pub dry_run: bool,
+}
+
+impl<P: JsonRpcClient + 'static> TriangularStrategy<P> {
+    pub fn new(config: &Config, provider: Arc<Provider<P>>, bundler: Arc<Bundler>) -> Result<Self> {
+        let config = config.clone();
+        // Performing USDC triangular arbitrage
+        let usdc_address = config.base_token.address;
+        let native_token = config.chain.wrapped_native;
+        if native_token.is_zero() {
+            return Err(anyhow!("chain.wrapped_native is not set"));
+        }
+        Ok(Self {
+            provider,
+            config: config.clone(),
+            bundler,
+            usdc_address,
+            usdc_decimals: config.base_token.decimals as i32,
+            paths: Vec::new(),
+            pools: HashMap::new(),
+            reserves: HashMap::new(),
+            pricing: PriceService::new(native_token, usdc_address),
+            gas_model: GasModel::default(),
+            bidder: Bidder::new(config.thresholds.bid_policy()),
+            flashloans: FlashloanSelector::for_chain(config.chain.chain_id),
+            use_flashloan: config.thresholds.use_flashloan,
+            use_access_list: config.thresholds.use_access_list,
+            pending_orders: HashMap::new(),
+            target_blocks: config.thresholds.target_blocks,
+            min_profit: config.thresholds.min_profit as i128,
+            risk: config.risk,
+            submission: config.submission,
+            active_paths: HashSet::new(),
+            replacement_uuids: HashMap::new(),
+            latest_submissions: HashMap::new(),
+            dry_run: config.dry_run.enabled,
+        })
+    }
+
+    // 新区块: 重新找套利并提交到接下来的 target_blocks 个区块
+    // 模拟盘只把这个区块的交易交给 PaperExecutor, 没有替换和取消
+    // 之前提交的 bundle 用同一个 replacementUuid 替换, 机会消失时取消
+    // 不走 bundle 时每笔交易单独提交, 没有替换和取消
+    async fn process_block(&mut self, block: NewBlock) -> Vec<Action> {
+        let (selected, orders) = self.build_orders(&block).await;
+        if self.dry_run {
+            if orders.is_empty() {
+                return Vec::new();
+            }
+            let signed_txs = orders.into_iter().map(|order| order.signed_tx).collect();
+            let trade = PaperTrade::new(
+                "triangular",
+                block.block_number,
+                block.block_number + self.target_blocks,
+                selected,
+                signed_txs,
+            );
+            return vec![Action::PaperTrade(trade)];
+        }
+        if !self.submission.uses_bundles() {
+            return orders.into_iter().map(Action::SendPrivateTx).collect();
+        }
+        let signed_txs: Vec<Bytes> = orders.into_iter().map(|order| order.signed_tx).collect();
+        // 已经过了目标区块的不用再管
+        self.replacement_uuids
+            .retain(|target_block, _| *target_block > block.block_number);
+
+        let mut actions = Vec::new();
+        if signed_txs.is_empty() {
+            self.active_paths.clear();
+            for (_, uuid) in self.replacement_uuids.drain() {
+                self.latest_submissions.remove(&uuid);
+                actions.push(Action::CancelBundle(uuid));
+            }
+            return actions;
+        }
+        // 执行器负责模拟和发送, 结果通过 Event::Execution 回来
+        let bundles = self
+            .bundler
+            .to_bundles(signed_txs, block.block_number, self.target_blocks);
+        for bundle in bundles {
+            let target_block = bundle.block().unwrap_or_default();
+            let uuid = self
+                .replacement_uuids
+                .entry(target_block)
+                .or_insert_with(new_replacement_uuid)
+                .clone();
+            if let Some(tx_hash) = bundle.transaction_hashes().first() {
+                self.latest_submissions.insert(uuid.clone(), *tx_hash);
+            }
+            actions.push(Action::ReplaceBundle(bundle, uuid));
+        }
+        actions
+    }
+
+    // 找出这个区块要提交的套利, 返回选中的机会和签好名的交易 (bundle 模式下所有套利打包进同一个 bundle)
+    async fn build_orders(&mut self, block: &NewBlock) -> (Vec<Opportunity>, Vec<PrivateTx>) {
+        self.bundler.nonces.on_new_block(block.block_number).await;
+        let usdc_decimals = self.usdc_decimals;
+        let paths = &self.paths;
+        let reserves = &mut self.reserves;
+        info!("{:?}", block);
+        let touched_reserves =
+            match get_touched_pool_reserves(self.provider.clone(), block.block_number).await {
+                Ok(res) => res,
+                Err(e) => {
+                    info!("Error from get_touched_pool_reserves: {:?}", e);
+                    HashMap::new()
                 }
+            };
+        // 涉及储备量变化的池子, 启动时没取到储备量的池子 (回测时全部) 出现 Sync 之后也开始跟踪
+        let mut touched_pools = Vec::new();
+        for (address, reserve) in touched_reserves {
+            if self.pools.contains_key(&address) {
+                reserves.insert(address, reserve);
+                touched_pools.push(address);
+            }
+        }
+        info!("{:?}", touched_pools);
+        // 找流动性最深的原生代币/USDC 路线, 把 gas 成本换算成 USDC
+        if self.pricing.update_route(&self.pools, reserves).is_none() {
+            info!("No native token price route in tracked pools");
+            return Default::default();
+        }
+        let reserves = &*reserves;
+        if block.block_number.as_u64() % 10 == 0 {
+            self.flashloans
+                .refresh(self.provider.clone(), &[self.usdc_address])
+                .await;
+        }
+        // 闪电贷来源和手续费, 没有可用的来源时用合约自己的资金
+        let unit = U256::from(10).pow(U256::from(usdc_decimals));
+        let select_loan = |path: &ArbPath, amount_in: U256, reserves: &HashMap<H160, Reserve>| {
+            if !self.use_flashloan {
+                return FlashloanQuote::not_used();
+            }
+            let path_pools: Vec<H160> = (0..path.nhop).map(|i| path._get_pool(i).address).collect();
+            self.flashloans
+                .select(
+                    self.usdc_address,
+                    amount_in * unit,
+                    &path_pools,
+                    &self.pools,
+                    reserves,
+                )
+                .unwrap_or_else(FlashloanQuote::not_used)
+        };
+        let search = OpportunitySearch {
+            paths,
+            reserves,
+            pricing: &self.pricing,
+            gas_model: &self.gas_model,
+            base_decimals: usdc_decimals,
+            min_profit: self.min_profit,
+            max_amount_in: self.risk.max_amount_in,
+        };
+        // 只有当路径中的某个池子储备量发生变化时，才有可能出现套利机会
+        // 已经提交过的路径每个区块都重新检查, 利润没了就取消
+        let opportunities = search.find(
+            block.next_base_fee,
+            |idx, path| {
+                touched_pools.iter().any(|pool| path.has_pool(pool))
+                    || self.active_paths.contains(&idx)
             },
-            Err(_) => {}
+            |path, amount_in| select_loan(path, amount_in, reserves),
+        );
+        if opportunities.is_empty() {
+            return Default::default();
+        }
+        let mut selected = search.select(
+            &opportunities,
+            self.risk.max_orders_per_bundle,
+            block.next_base_fee,
+            |path, amount_in| select_loan(path, amount_in, reserves),
+        );
+        // 单独提交时前一笔被拒绝后面的 nonce 就接不上了, 只提交最好的一个
+        if !self.submission.uses_bundles() {
+            selected = selected
+                .into_iter()
+                .max_by_key(|opp| opp.profit)
+                .into_iter()
+                .collect();
+        }
+        if selected.is_empty() {
+            return Default::default();
+        }
+        for opp in &selected {
+            info!(
+                "Path #{:?}: excess profit {:?} (amount in {:?})",
+                opp.path_idx, opp.profit, opp.amount_in
+            );
+        }
+
+        // 用按顺序模拟时选的闪电贷来源, 编码套利合约的 calldata
+        // 编码失败 (紧凑编码时 router 没登记、金额超过 uint128) 的套利不提交, 也不计入出价
+        let mut kept = Vec::new();
+        let mut loans: Vec<FlashloanQuote> = Vec::new();
+        let mut orders = Vec::new();
+        for opp in selected {
+            let path = &paths[opp.path_idx];
+            let loan = opp.loan.clone();
+            let path_params = path.to_path_params();
+            let calldata = match self.bundler.order_calldata(
+                path_params,
+                opp.amount_in * unit,
+                loan.flashloan.clone(),
+                loan.loan_from,
+            ) {
+                Ok(calldata) => calldata,
+                Err(e) => {
+                    info!("Path #{:?}: error encoding order: {:?}", opp.path_idx, e);
+                    continue;
+                }
+            };
+            orders.push((calldata, self.gas_model.gas_limit(path, &loan.flashloan)));
+            loans.push(loan);
+            kept.push(opp);
+        }
+        let selected = kept;
+        if selected.is_empty() {
+            return Default::default();
+        }
+
+        // 按整个 bundle 的预期利润出价, 利润先换算成原生代币
+        let total_profit: i128 = selected.iter().map(|opp| opp.profit).sum();
+        let total_gas_usage =
+            selected
+                .iter()
+                .zip(loans.iter())
+                .fold(U256::zero(), |acc, (opp, loan)| {
+                    acc + self
+                        .gas_model
+                        .estimate(&paths[opp.path_idx], &loan.flashloan)
+                });
+        let expected_profit = match self
+            .pricing
+            .base_to_native(U256::from(total_profit as u128), reserves)
+        {
+            Some(expected_profit) => expected_profit,
+            None => return Default::default(),
+        };
+        let bid = match self
+            .bidder
+            .bid(expected_profit, block.next_base_fee, total_gas_usage)
+        {
+            Some(bid) => bid,
+            None => {
+                info!("No profitable bid for {:?} wei", expected_profit);
+                return Default::default();
+            }
+        };
+        let (max_priority_fee_per_gas, max_fee_per_gas) =
+            self.submission.fees(&bid, block.next_base_fee);
+        if max_fee_per_gas > self.risk.max_fee_per_gas() {
+            info!("Max fee per gas {:?} over risk limit", max_fee_per_gas);
+            return Default::default();
+        }
+        info!("{:?}", bid);
+
+        // coinbase 模式: 最后附加一笔转给 builder 的交易, nonce 一起分配
+        let tip_recipient = match self.bidder.policy.mode {
+            BidMode::CoinbaseTransfer { recipient } => Some(recipient),
+            BidMode::PriorityFee => None,
+        };
+        let order_count = orders.len() as u64;
+        let nonce_count = order_count + tip_recipient.is_some() as u64;
+        let first_nonce = match self.bundler.reserve_bundle_nonces(nonce_count).await {
+            Ok(nonce) => nonce,
+            Err(e) => {
+                info!("Error reserving nonces: {:?}", e);
+                return Default::default();
+            }
+        };
+        let mut order_txs = self.bundler.order_txs(
+            orders,
+            first_nonce,
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+        );
+        if self.use_access_list {
+            let pools: Vec<Vec<Pool>> = selected
+                .iter()
+                .map(|opp| paths[opp.path_idx].pools())
+                .collect();
+            order_txs = join_all(
+                order_txs
+                    .into_iter()
+                    .zip(pools.iter())
+                    .map(|(tx, pools)| self.bundler.with_access_list(tx, pools)),
+            )
+            .await;
+        }
+        let tip_tx = tip_recipient.map(|recipient| {
+            self.bundler.coinbase_tip_tx(
+                recipient,
+                bid.coinbase_transfer,
+                first_nonce + U256::from(order_count),
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+            )
+        });
+        // 签名交易
+        let last_target_block = block.block_number + self.target_blocks;
+        let mut signed_txs = Vec::new();
+        for ((order_tx, opp), loan) in order_txs.into_iter().zip(selected.iter()).zip(loans) {
+            let signed_tx = match self.bundler.sign_tx(order_tx).await {
+                Ok(signed_tx) => signed_tx,
+                Err(e) => {
+                    info!("Error signing order tx: {:?}", e);
+                    return Default::default();
+                }
+            };
+            let tx_hash = TxHash::from(keccak256(&signed_tx));
+            self.pending_orders
+                .insert(tx_hash, (opp.path_idx, last_target_block, loan.flashloan));
+            // 单独提交时用来检查利润: 扣 gas 之前的利润, 换算成原生代币
+            let expected_profit = self
+                .pricing
+                .base_to_native(U256::from((opp.profit + opp.gas_cost) as u128), reserves)
+                .unwrap_or_default();
+            signed_txs.push(PrivateTx {
+                signed_tx,
+                max_block_number: last_target_block,
+                expected_profit,
+                pools: opp.pools.clone(),
+            });
+        }
+        if let Some(tip_tx) = tip_tx {
+            match self.bundler.sign_tx(tip_tx).await {
+                Ok(signed_tx) => signed_txs.push(PrivateTx {
+                    signed_tx,
+                    max_block_number: last_target_block,
+                    expected_profit: U256::zero(),
+                    pools: Vec::new(),
+                }),
+                Err(e) => {
+                    info!("Error signing coinbase tip tx: {:?}", e);
+                    return Default::default();
+                }
+            }
+        }
+        self.active_paths = selected.iter().map(|opp| opp.path_idx).collect();
+        (selected, signed_txs)
+    }
+
+    // 处理交易结果
+    // 用收据的 gas_used 校准 gas 模型, 没上链时用 eth_callBundle 模拟的 gas_used, 并更新出价用的上链率
+    // 模拟阶段就失败、已经被替换/取消的 bundle, 以及模拟盘的交易不计入上链率
+    fn process_execution(&mut self, report: ExecutionReport) {
+        let ours = report
+            .tx_hashes
+            .iter()
+            .any(|tx_hash| self.pending_orders.contains_key(tx_hash));
+        let replaced = match &report.replacement_uuid {
+            Some(uuid) => {
+                let latest = self.latest_submissions.get(uuid);
+                if latest.is_some() && latest == report.tx_hashes.first() {
+                    self.latest_submissions.remove(uuid);
+                    false
+                } else {
+                    true
+                }
+            }
+            None => false,
+        };
+        if ours && !replaced && !report.simulation_failed && !self.dry_run {
+            self.bidder.record_inclusion(report.landed());
+        }
+        for tx_hash in &report.tx_hashes {
+            let (path_idx, _, flashloan) = match self.pending_orders.get(tx_hash) {
+                Some(order) => order,
+                None => continue,
+            };
+            let gas_used = report
+                .outcome
+                .as_ref()
+                .and_then(|record| record.gas_used.get(tx_hash))
+                .or_else(|| report.simulated_gas.get(tx_hash));
+            if let Some(gas_used) = gas_used {
+                self.gas_model
+                    .calibrate(&self.paths[*path_idx], flashloan, *gas_used);
+            }
+        }
+        let target_block = report.target_block.unwrap_or_default();
+        for tx_hash in &report.tx_hashes {
+            // 同样的交易还在等后面的目标区块, 上链或者最后一个目标区块过了才算结束
+            match self.pending_orders.get(tx_hash) {
+                Some((_, last_target_block, _)) => {
+                    if !report.landed() && target_block < *last_target_block {
+                        continue;
+                    }
+                }
+                None => continue,
+            }
+            let (path_idx, _, _) = self.pending_orders.remove(tx_hash).unwrap();
+            match &report.result {
+                Ok(bundle_hash) => info!(
+                    "Path #{:?} bundle {:?} included in block {:?}",
+                    path_idx, bundle_hash, report.target_block
+                ),
+                Err(e) => info!("Path #{:?} bundle failed: {}", path_idx, e),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<P: JsonRpcClient + 'static> Strategy for TriangularStrategy<P> {
+    async fn sync_state(&mut self) -> Result<()> {
+        let (paths, pools) = load_triangular_paths(
+            self.provider.clone(),
+            self.config.chain.chain_id,
+            self.usdc_address,
+            &self.config.dexes,
+            &self.config.risk.blacklist_tokens,
+        )
+        .await?;
+        // pools_vec从所有池子转换成三角路径池 且是有关usdc_address的
+        // pools.values() - 获取 HashMap 中所有的值（Pool）的引用
+        // cloned() - 克隆每个 Pool
+        // collect() - 收集到一个新的 Vec 中
+        let pools_vec: Vec<Pool> = pools.values().cloned().collect();
+        self.reserves = batch_get_uniswap_v2_reserves(self.provider.clone(), pools_vec).await?;
+        self.paths = paths;
+        self.pools = pools;
+        self.flashloans
+            .refresh(self.provider.clone(), &[self.usdc_address])
+            .await;
+        Ok(())
+    }
+
+    async fn process_event(&mut self, event: Event) -> Vec<Action> {
+        match event {
+            Event::Block(block) => self.process_block(block).await,
+            Event::PendingTx(_) => {
+                // not using pending tx
+                Vec::new()
+            }
+            Event::Log(_) | Event::Hint(_) => {
+                // not using logs
+                Vec::new()
+            }
+            Event::Execution(report) => {
+                self.process_execution(report);
+                Vec::new()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod strategy_tests {
+    use super::*;
+    use crate::chains::ChainProfile;
+    use crate::mock::{mock_env, serve_fixtures, FixtureTransport, Fixtures};
+    use crate::pools::DexVariant;
+    use ethers::abi::{encode, Token};
+    use ethers::types::{Log, H256};
+    use serde_json::{json, Value};
+
+    fn pool(address: u64, token0: H160, token1: H160, decimals0: u8, decimals1: u8) -> Pool {
+        Pool {
+            address: H160::from_low_u64_be(address),
+            version: DexVariant::UniswapV2,
+            token0,
+            token1,
+            decimals0,
+            decimals1,
+            fee: 300,
+            router: H160::zero(),
+        }
+    }
+
+    fn reserve(reserve0: U256, reserve1: U256) -> Reserve {
+        Reserve { reserve0, reserve1 }
+    }
+
+    fn sync_log(pool: u64, reserve: &Reserve) -> Value {
+        let log = Log {
+            address: H160::from_low_u64_be(pool),
+            topics: vec![H256::from(keccak256("Sync(uint112,uint112)"))],
+            data: encode(&[Token::Uint(reserve.reserve0), Token::Uint(reserve.reserve1)]).into(),
+            transaction_index: Some(U64::from(1)),
+            ..Default::default()
+        };
+        serde_json::to_value(log).unwrap()
+    }
+
+    fn new_block(block_number: u64) -> NewBlock {
+        NewBlock {
+            block_number: U64::from(block_number),
+            base_fee: U256::exp10(9),
+            next_base_fee: U256::exp10(9),
+        }
+    }
+
+    // USDC -> WETH -> TKN -> USDC, 每个区块 eth_getLogs 依次返回 sync_logs 里的一项
+    async fn strategy(sync_logs: Vec<Value>) -> TriangularStrategy<FixtureTransport> {
+        let ethereum = ChainProfile::ethereum();
+        let usdc = ethereum.stables[0].address;
+        let weth = ethereum.wrapped_native.address;
+        let tkn = H160::from_low_u64_be(0x70);
+        let fixtures = Fixtures::default();
+        fixtures.push("eth_chainId", json!("0x1"));
+        fixtures.push("eth_blockNumber", json!("0x64"));
+        fixtures.push("eth_getTransactionCount", json!("0x7"));
+        for logs in sync_logs {
+            fixtures.push("eth_getLogs", json!(logs));
+        }
+        let url = serve_fixtures(fixtures.clone()).await.unwrap();
+        let env = mock_env(&url).unwrap();
+        let config = env.config.clone();
+        let provider = Arc::new(Provider::new(FixtureTransport::new(fixtures)));
+        let bundler = Arc::new(Bundler::from_env(env).unwrap());
+        let mut strategy = TriangularStrategy::new(&config, provider, bundler).unwrap();
+        strategy.use_flashloan = false;
+        strategy.use_access_list = false;
+
+        let path = ArbPath {
+            nhop: 3,
+            pool_1: pool(0xa01, usdc, weth, 6, 18),
+            pool_2: pool(0xa02, tkn, weth, 18, 18),
+            pool_3: pool(0xa03, tkn, usdc, 18, 6),
+            zero_for_one_1: true,
+            zero_for_one_2: false,
+            zero_for_one_3: true,
+        };
+        for pool in path.pools() {
+            strategy.pools.insert(pool.address, pool);
         }
+        strategy.paths = vec![path];
+        // 价格: 1 WETH = 2000 USDC = 1000 TKN, 1 TKN = 2 USDC
+        strategy.reserves = HashMap::from([
+            (
+                H160::from_low_u64_be(0xa01),
+                reserve(U256::exp10(12) * 2, U256::exp10(21)),
+            ),
+            (
+                H160::from_low_u64_be(0xa02),
+                reserve(U256::exp10(24), U256::exp10(21)),
+            ),
+            (
+                H160::from_low_u64_be(0xa03),
+                reserve(U256::exp10(24), U256::exp10(12) * 2),
+            ),
+        ]);
+        strategy
+    }
+
+    fn fair() -> Reserve {
+        reserve(U256::exp10(24), U256::exp10(12) * 2)
+    }
+
+    // TKN 在 0xa03 里涨到 2.2 USDC
+    fn mispriced() -> Reserve {
+        reserve(U256::exp10(24), U256::exp10(11) * 22)
+    }
+
+    fn replacements(actions: &[Action]) -> Vec<(U64, String)> {
+        actions
+            .iter()
+            .map(|action| match action {
+                Action::ReplaceBundle(bundle, uuid) => {
+                    (bundle.block().unwrap_or_default(), uuid.clone())
+                }
+                _ => panic!("expected ReplaceBundle, got {:?}", action),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn replaces_bundles_per_target_block() {
+        let mut strategy = strategy(vec![json!([sync_log(0xa03, &mispriced())]), json!([])]).await;
+
+        let first = replacements(&strategy.process_block(new_block(101)).await);
+        assert_eq!(
+            first.iter().map(|(block, _)| *block).collect::<Vec<_>>(),
+            vec![U64::from(102), U64::from(103)]
+        );
+        assert_ne!(first[0].1, first[1].1);
+        assert_eq!(strategy.active_paths, HashSet::from([0]));
+
+        // 没有新的 Sync, 提交过的路径还有利润: 103 用同一个 uuid 替换, 104 换新的
+        let second = replacements(&strategy.process_block(new_block(102)).await);
+        assert_eq!(
+            second.iter().map(|(block, _)| *block).collect::<Vec<_>>(),
+            vec![U64::from(103), U64::from(104)]
+        );
+        assert_eq!(second[0].1, first[1].1);
+        assert!(second[1].1 != first[0].1 && second[1].1 != first[1].1);
+        // 过期的目标区块 102 不再记录
+        assert_eq!(strategy.replacement_uuids.len(), 2);
+        assert!(!strategy.replacement_uuids.contains_key(&U64::from(102)));
+        assert_eq!(strategy.latest_submissions.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn cancels_all_bundles_without_orders() {
+        let mut strategy = strategy(vec![
+            json!([sync_log(0xa03, &mispriced())]),
+            json!([sync_log(0xa03, &fair())]),
+        ])
+        .await;
+        let submitted = replacements(&strategy.process_block(new_block(101)).await);
+        assert_eq!(submitted.len(), 2);
+
+        // 价格回到正常, 还没过期的目标区块全部取消
+        let actions = strategy.process_block(new_block(102)).await;
+        let cancelled: Vec<String> = actions
+            .iter()
+            .map(|action| match action {
+                Action::CancelBundle(uuid) => uuid.clone(),
+                _ => panic!("expected CancelBundle, got {:?}", action),
+            })
+            .collect();
+        assert_eq!(cancelled, vec![submitted[1].1.clone()]);
+        assert!(strategy.replacement_uuids.is_empty());
+        assert!(strategy.active_paths.is_empty());
+        assert!(!strategy.latest_submissions.contains_key(&submitted[1].1));
+
+        // 之后没有机会的区块不再发取消
+        assert!(strategy.process_block(new_block(103)).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn calibrates_gas_from_simulation() {
+        let mut strategy = strategy(vec![json!([sync_log(0xa03, &mispriced())])]).await;
+        let actions = strategy.process_block(new_block(101)).await;
+        let (bundle, uuid) = match &actions[0] {
+            Action::ReplaceBundle(bundle, uuid) => (bundle.clone(), uuid.clone()),
+            action => panic!("expected ReplaceBundle, got {:?}", action),
+        };
+        let tx_hash = bundle.transaction_hashes()[0];
+        let raw = strategy
+            .gas_model
+            .raw_estimate(&strategy.paths[0], &Flashloan::NotUsed);
+
+        // 模拟通过但没上链, 也用模拟出来的 gas_used 校准
+        let action = Action::ReplaceBundle(bundle, uuid);
+        let mut report = ExecutionReport::new(&action, Err(anyhow!("Bundle not included")));
+        report.simulated_gas = HashMap::from([(tx_hash, U256::from(raw + 15000))]);
+        strategy.process_execution(report);
+        assert_eq!(strategy.gas_model.correction(3, &Flashloan::NotUsed), 15000);
+        assert_eq!(strategy.gas_model.correction(3, &Flashloan::Balancer), 0);
+        // 还要等下一个目标区块
+        assert!(strategy.pending_orders.contains_key(&tx_hash));
+    }
+
+    #[tokio::test]
+    async fn spread_filter_rejects_break_even_paths() {
+        let mut strategy = strategy(vec![]).await;
+        strategy
+            .pricing
+            .update_route(&strategy.pools, &strategy.reserves);
+        let optimized = std::cell::Cell::new(0);
+        let find = |reserves: &HashMap<H160, Reserve>| {
+            let search = OpportunitySearch {
+                paths: &strategy.paths,
+                reserves,
+                pricing: &strategy.pricing,
+                gas_model: &strategy.gas_model,
+                base_decimals: strategy.usdc_decimals,
+                min_profit: strategy.min_profit,
+                max_amount_in: strategy.risk.max_amount_in,
+            };
+            optimized.set(0);
+            search.find(
+                U256::exp10(9),
+                |_, _| true,
+                |_, _| {
+                    optimized.set(optimized.get() + 1);
+                    FlashloanQuote::not_used()
+                },
+            )
+        };
+
+        // 价格一致: 1 USDC 换一圈扣完手续费不到 1 USDC, 不用优化金额
+        let fair_reserves = strategy.reserves.clone();
+        assert!(find(&fair_reserves).is_empty());
+        assert_eq!(optimized.get(), 0);
+
+        // 有价差的才优化
+        let mut mispriced_reserves = fair_reserves.clone();
+        mispriced_reserves.insert(H160::from_low_u64_be(0xa03), mispriced());
+        assert_eq!(find(&mispriced_reserves).len(), 1);
+        assert_eq!(optimized.get(), 1);
     }
 }
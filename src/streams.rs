@@ -1,11 +1,17 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use crate::utils::calculate_next_block_base_fee;
-use ethers::types::{Log, Transaction, U256, U64};
+use crate::{
+    engine::ExecutionReport,
+    mev_share::{Hint, SseParser},
+    utils::calculate_next_block_base_fee,
+};
+use ethers::types::{Filter, Log, Transaction, U256, U64};
 use ethers_providers::{Middleware, Provider, Ws};
+use log::info;
+use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast::Sender;
 use tokio_stream::StreamExt;
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct NewBlock {
     pub block_number: U64,
     pub base_fee: U256,
@@ -18,6 +24,10 @@ pub enum Event {
     Block(NewBlock),
     PendingTx(Transaction),
     Log(Log),
+    // MEV-Share 事件流里的 hint
+    Hint(Hint),
+    // 执行器提交动作的结果, 回传给策略
+    Execution(ExecutionReport),
 }
 // 处理区块信息流
 pub async fn stream_new_block(provider: Arc<Provider<Ws>>, event_sender: Sender<Event>) {
@@ -55,3 +65,58 @@ pub async fn stream_pending_transactions(provider: Arc<Provider<Ws>>, event_send
         };
     }
 }
+// 所有 V2 池子的 Sync 日志, 给 recorder 录回测数据
+pub async fn stream_sync_logs(provider: Arc<Provider<Ws>>, event_sender: Sender<Event>) {
+    let filter = Filter::new().event("Sync(uint112,uint112)");
+    let mut stream = provider.subscribe_logs(&filter).await.unwrap();
+
+    while let Some(log) = stream.next().await {
+        match event_sender.send(Event::Log(log)) {
+            Ok(_) => {}
+            Err(_) => {}
+        }
+    }
+}
+// SSE 连接一直开着, 不能给整个请求设超时; 这么多个区块都没收到数据就当连接断了
+pub const HINT_STREAM_IDLE_BLOCKS: u32 = 10;
+
+// MEV-Share 的 hint 事件流 (SSE), 断开或者卡住后重连
+pub async fn stream_mev_share_hints(
+    url: String,
+    block_time: Duration,
+    event_sender: Sender<Event>,
+) {
+    let client = reqwest::Client::builder()
+        .connect_timeout(block_time)
+        .build()
+        .expect("Error building HTTP client");
+    let idle_timeout = block_time * HINT_STREAM_IDLE_BLOCKS;
+    loop {
+        let response = client
+            .get(&url)
+            .header("Accept", "text/event-stream")
+            .send()
+            .await;
+        match response {
+            Ok(mut response) => {
+                let mut parser = SseParser::new();
+                while let Ok(Ok(Some(chunk))) =
+                    tokio::time::timeout(idle_timeout, response.chunk()).await
+                {
+                    for data in parser.feed(&chunk) {
+                        match serde_json::from_str::<Hint>(&data) {
+                            Ok(hint) => match event_sender.send(Event::Hint(hint)) {
+                                Ok(_) => {}
+                                Err(_) => {}
+                            },
+                            Err(e) => info!("Error parsing hint: {:?}", e),
+                        }
+                    }
+                }
+                info!("MEV-Share stream closed, reconnecting");
+            }
+            Err(e) => info!("Error from MEV-Share stream: {:?}", e),
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
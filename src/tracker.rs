@@ -0,0 +1,357 @@
+use anyhow::Result;
+use ethers::{
+    signers::Signer,
+    types::{transaction::eip2718::TypedTransaction, BlockId, TxHash, H160, H256, I256, U256, U64},
+};
+use ethers_flashbots::BundleRequest;
+use ethers_providers::Middleware;
+use log::info;
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use crate::abi::ERC20;
+use crate::builders::BuilderResult;
+use crate::bundler::Bundler;
+
+#[derive(Debug, Clone)]
+pub enum BundleOutcome {
+    Included { block: U64 },
+    // 目标区块里没有我们的交易: 被出价更高的 bundle 挤掉, 或者 builder 没有出块
+    NotIncluded,
+    // 上链了但是我们的交易 revert
+    Reverted { tx_hash: TxHash, reason: String },
+}
+
+// 每次提交 bundle 的最终结果
+#[derive(Debug, Clone)]
+pub struct OutcomeRecord {
+    pub bundle_hash: Option<H256>,
+    pub target_block: U64,
+    pub tx_hashes: Vec<TxHash>,
+    pub builders: Vec<BuilderResult>,
+    pub outcome: BundleOutcome,
+    pub gas_used: HashMap<TxHash, U256>, // 我们自己的交易
+    pub gas_cost: U256,                  // 我们自己的交易花掉的 gas (wei)
+    // 目标区块前后合约里利润代币的余额变化, 没有配置利润代币时为 None
+    pub realized_profit: Option<I256>,
+    // 支持 flashbots_getBundleStatsV2 的 builder 返回的统计
+    pub stats: Vec<(String, Value)>,
+}
+
+impl OutcomeRecord {
+    pub fn is_included(&self) -> bool {
+        matches!(self.outcome, BundleOutcome::Included { .. })
+    }
+}
+
+// 盯着目标区块, 判断 bundle 是否上链
+pub struct BundleTracker {
+    pub bundler: Arc<Bundler>,
+    pub profit_token: Option<H160>,
+    pub poll_interval: Duration,
+}
+
+impl BundleTracker {
+    pub fn new(bundler: Arc<Bundler>, profit_token: Option<H160>) -> Self {
+        Self {
+            bundler,
+            profit_token,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+
+    pub async fn wait_for_block(&self, block: U64) -> Result<()> {
+        loop {
+            if self.bundler.provider.get_block_number().await? >= block {
+                return Ok(());
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    pub async fn track(
+        &self,
+        bundle: &BundleRequest,
+        builders: Vec<BuilderResult>,
+    ) -> Result<OutcomeRecord> {
+        let target_block = bundle.block().unwrap_or_default();
+        let tx_hashes = bundle.transaction_hashes();
+        let bundle_hash = builders
+            .iter()
+            .find_map(|result| result.result.clone().ok().flatten());
+        self.wait_for_block(target_block).await?;
+
+        let provider = &self.bundler.provider;
+        let block_txs = match provider.get_block(target_block).await? {
+            Some(block) => block.transactions,
+            None => Vec::new(),
+        };
+        let mut record = OutcomeRecord {
+            bundle_hash,
+            target_block,
+            tx_hashes: tx_hashes.clone(),
+            builders,
+            outcome: BundleOutcome::NotIncluded,
+            gas_used: HashMap::new(),
+            gas_cost: U256::zero(),
+            realized_profit: None,
+            stats: Vec::new(),
+        };
+
+        // bundle 是原子的, 所有交易都在目标区块里才算上链
+        if !tx_hashes.is_empty() && tx_hashes.iter().all(|tx| block_txs.contains(tx)) {
+            record.outcome = BundleOutcome::Included {
+                block: target_block,
+            };
+            let sender = self.bundler.sender.address();
+            for tx_hash in &tx_hashes {
+                let receipt = match provider.get_transaction_receipt(*tx_hash).await? {
+                    Some(receipt) => receipt,
+                    None => continue,
+                };
+                // 受害者的交易不算
+                if receipt.from != sender {
+                    continue;
+                }
+                if let Ok(Some(tx)) = provider.get_transaction(*tx_hash).await {
+                    self.bundler.nonces.confirm(tx.nonce).await;
+                }
+                let gas_used = receipt.gas_used.unwrap_or_default();
+                let gas_price = receipt.effective_gas_price.unwrap_or_default();
+                record.gas_used.insert(*tx_hash, gas_used);
+                record.gas_cost += gas_used * gas_price;
+                if receipt.status == Some(U64::zero()) {
+                    if let BundleOutcome::Included { .. } = record.outcome {
+                        record.outcome = BundleOutcome::Reverted {
+                            tx_hash: *tx_hash,
+                            reason: self.revert_reason(*tx_hash, target_block).await,
+                        };
+                    }
+                }
+            }
+            record.realized_profit = self.realized_profit(target_block).await;
+        }
+        if let Some(bundle_hash) = bundle_hash {
+            record.stats = self.bundle_stats(bundle_hash, target_block).await;
+        }
+        info!("Bundle outcome: {:?}", record);
+        Ok(record)
+    }
+
+    // 单独提交的交易: 等到上链, 或者过了 max_block 还没上链
+    // 公开 mempool 里的交易过了 max_block 之后仍然可能上链, 这里只按 max_block 判断
+    pub async fn track_tx(&self, tx_hash: TxHash, max_block: U64) -> Result<OutcomeRecord> {
+        let provider = &self.bundler.provider;
+        let mut record = OutcomeRecord {
+            bundle_hash: None,
+            target_block: max_block,
+            tx_hashes: vec![tx_hash],
+            builders: Vec::new(),
+            outcome: BundleOutcome::NotIncluded,
+            gas_used: HashMap::new(),
+            gas_cost: U256::zero(),
+            realized_profit: None,
+            stats: Vec::new(),
+        };
+        loop {
+            if let Some(receipt) = provider.get_transaction_receipt(tx_hash).await? {
+                let block = receipt.block_number.unwrap_or_default();
+                record.target_block = block;
+                record.outcome = match receipt.status == Some(U64::zero()) {
+                    true => BundleOutcome::Reverted {
+                        tx_hash,
+                        reason: self.revert_reason(tx_hash, block).await,
+                    },
+                    false => BundleOutcome::Included { block },
+                };
+                if let Ok(Some(tx)) = provider.get_transaction(tx_hash).await {
+                    self.bundler.nonces.confirm(tx.nonce).await;
+                }
+                let gas_used = receipt.gas_used.unwrap_or_default();
+                record.gas_used.insert(tx_hash, gas_used);
+                record.gas_cost = gas_used * receipt.effective_gas_price.unwrap_or_default();
+                record.realized_profit = self.realized_profit(block).await;
+                break;
+            }
+            if provider.get_block_number().await? > max_block {
+                break;
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+        info!("Tx outcome: {:?}", record);
+        Ok(record)
+    }
+
+    // 在父区块的状态上重放, 拿到 revert 信息
+    // 排在前面的交易没有一起重放, 只是近似
+    async fn revert_reason(&self, tx_hash: TxHash, block: U64) -> String {
+        let provider = &self.bundler.provider;
+        let tx = match provider.get_transaction(tx_hash).await {
+            Ok(Some(tx)) => tx,
+            _ => return "unknown".to_string(),
+        };
+        let typed: TypedTransaction = (&tx).into();
+        let parent = BlockId::from(block - 1);
+        match provider.call(&typed, Some(parent)).await {
+            Ok(_) => "unknown".to_string(),
+            Err(e) => format!("{:?}", e),
+        }
+    }
+
+    async fn realized_profit(&self, block: U64) -> Option<I256> {
+        let token = ERC20::new(self.profit_token?, Arc::new(self.bundler.provider.clone()));
+        let bot = self.bundler.bot.address();
+        let before = token
+            .balance_of(bot)
+            .block(BlockId::from(block - 1))
+            .call()
+            .await
+            .ok()?;
+        let after = token
+            .balance_of(bot)
+            .block(BlockId::from(block))
+            .call()
+            .await
+            .ok()?;
+        Some(I256::from_raw(after) - I256::from_raw(before))
+    }
+
+    async fn bundle_stats(&self, bundle_hash: H256, block: U64) -> Vec<(String, Value)> {
+        let mut stats = Vec::new();
+        for builder in &self.bundler.builders {
+            if !builder.config.supports("flashbots_getBundleStatsV2") {
+                continue;
+            }
+            let params = json!([{ "bundleHash": bundle_hash, "blockNumber": block }]);
+            match builder.call("flashbots_getBundleStatsV2", params).await {
+                Ok(result) => stats.push((builder.config.name.clone(), result)),
+                Err(e) => info!("Error from flashbots_getBundleStatsV2: {:?}", e),
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tracker_tests {
+    use super::*;
+    use crate::mock::{mock_env, serve_fixtures, Fixtures, TEST_BOT_ADDRESS};
+    use ethers::types::Bytes;
+    use ethers::utils::keccak256;
+
+    fn receipt(tx_hash: TxHash, from: H160, status: u64) -> Value {
+        json!({
+            "transactionHash": tx_hash,
+            "transactionIndex": "0x0",
+            "blockHash": H256::from_low_u64_be(100),
+            "blockNumber": "0x64",
+            "from": from,
+            "to": TEST_BOT_ADDRESS,
+            "cumulativeGasUsed": "0x30d40",
+            "gasUsed": "0x30d40",
+            "effectiveGasPrice": "0x3b9aca00",
+            "logs": [],
+            "logsBloom": format!("0x{}", "0".repeat(512)),
+            "status": format!("0x{:x}", status),
+        })
+    }
+
+    fn transaction(tx_hash: TxHash, from: H160, nonce: u64) -> Value {
+        json!({
+            "hash": tx_hash,
+            "nonce": format!("0x{:x}", nonce),
+            "from": from,
+            "to": TEST_BOT_ADDRESS,
+            "value": "0x0",
+            "gas": "0x30d40",
+            "gasPrice": "0x3b9aca00",
+            "input": "0x",
+            "v": "0x1",
+            "r": "0x1",
+            "s": "0x1",
+        })
+    }
+
+    // 目标区块 0x64 里的交易哈希是 block_txs
+    async fn tracker(block_txs: Vec<TxHash>) -> (BundleTracker, Fixtures) {
+        let fixtures = Fixtures::default();
+        fixtures.push("eth_chainId", json!("0x1"));
+        fixtures.push("eth_blockNumber", json!("0x64"));
+        fixtures.push("eth_getTransactionCount", json!("0x7"));
+        fixtures.push(
+            "eth_getBlockByNumber",
+            json!({"number": "0x64", "transactions": block_txs}),
+        );
+        let url = serve_fixtures(fixtures.clone()).await.unwrap();
+        let bundler = Bundler::from_env(mock_env(&url).unwrap()).unwrap();
+        (BundleTracker::new(Arc::new(bundler), None), fixtures)
+    }
+
+    fn bundle(txs: &[Bytes]) -> (BundleRequest, Vec<TxHash>) {
+        let mut bundle = BundleRequest::new().set_block(U64::from(100));
+        for tx in txs {
+            bundle = bundle.push_transaction(tx.clone());
+        }
+        let hashes = txs.iter().map(|tx| TxHash::from(keccak256(tx))).collect();
+        (bundle, hashes)
+    }
+
+    #[tokio::test]
+    async fn not_included_when_a_tx_is_missing() {
+        let (bundle, hashes) = bundle(&[Bytes::from(vec![1u8]), Bytes::from(vec![2u8])]);
+        // 只有第一笔在目标区块里
+        let (tracker, fixtures) = tracker(vec![hashes[0]]).await;
+        let record = tracker.track(&bundle, Vec::new()).await.unwrap();
+        assert!(matches!(record.outcome, BundleOutcome::NotIncluded));
+        assert_eq!(record.tx_hashes, hashes);
+        assert!(fixtures.requests("eth_getTransactionReceipt").is_empty());
+
+        // 没有 confirm, 下一个区块从同步到的 nonce 重新分配
+        let nonce = tracker.bundler.reserve_bundle_nonces(1).await.unwrap();
+        assert_eq!(nonce, U256::from(7));
+        tracker.bundler.nonces.on_new_block(U64::from(101)).await;
+        let nonce = tracker.bundler.reserve_bundle_nonces(1).await.unwrap();
+        assert_eq!(nonce, U256::from(7));
+    }
+
+    #[tokio::test]
+    async fn reverted_tx_is_classified_and_confirmed() {
+        let (bundle, hashes) = bundle(&[Bytes::from(vec![1u8]), Bytes::from(vec![2u8])]);
+        let (tracker, fixtures) = tracker(hashes.clone()).await;
+        let sender = tracker.bundler.sender.address();
+        let victim = H160::from_low_u64_be(0xdead);
+        // 第一笔是别人的交易, 第二笔是我们的, revert 了
+        fixtures.push("eth_getTransactionReceipt", receipt(hashes[0], victim, 1));
+        fixtures.push("eth_getTransactionReceipt", receipt(hashes[1], sender, 0));
+        fixtures.push(
+            "eth_getTransactionByHash",
+            transaction(hashes[1], sender, 7),
+        );
+        fixtures.push(
+            "eth_call",
+            json!({"error": {"code": 3, "message": "execution reverted: no profit"}}),
+        );
+
+        // 提交前分配过 nonce 7
+        let nonce = tracker.bundler.reserve_bundle_nonces(1).await.unwrap();
+        assert_eq!(nonce, U256::from(7));
+        let record = tracker.track(&bundle, Vec::new()).await.unwrap();
+        match &record.outcome {
+            BundleOutcome::Reverted { tx_hash, reason } => {
+                assert_eq!(*tx_hash, hashes[1]);
+                assert!(reason.contains("no profit"));
+            }
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+        assert!(!record.is_included());
+        // 只算我们自己的交易
+        assert_eq!(record.gas_used.len(), 1);
+        assert_eq!(record.gas_cost, U256::from(200_000u64) * U256::exp10(9));
+
+        // 上链的 nonce 被 confirm, 新区块从 8 开始分配
+        tracker.bundler.nonces.on_new_block(U64::from(101)).await;
+        let nonce = tracker.bundler.reserve_bundle_nonces(1).await.unwrap();
+        assert_eq!(nonce, U256::from(8));
+        assert_eq!(fixtures.requests("eth_getTransactionCount").len(), 1);
+    }
+}
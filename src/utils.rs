@@ -1,17 +1,21 @@
-use std::{collections::HashMap, sync::Arc, time::Instant};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use ethers::{
     abi::{self, decode, ParamType, Token},
-    types::{Filter, H160, H256, U256, U64},
+    types::{Filter, Log, H160, U256, U64},
 };
-use ethers_contract::{self, Contract, Multicall};
-use ethers_providers::{Http, Middleware, Provider, Ws};
+use ethers_contract::{self, Multicall};
+use ethers_providers::{JsonRpcClient, Middleware, Provider};
 use fern::colors::{Color, ColoredLevelConfig};
 use log::{info, LevelFilter};
 use rand::Rng;
 
-use crate::{abi::ABI, pools::Pool};
+use crate::{abi::UniswapV2Pair, pools::Pool};
 #[derive(Default, Debug, Clone)]
 pub struct Reserve {
     pub reserve0: U256,
@@ -41,6 +45,13 @@ pub fn setup_logger() -> Result<()> {
         .apply()?;
     Ok(())
 }
+// builder / 远程签名的 HTTP 客户端, 超时一般给出块时间: 超过一个区块结果也用不上了
+pub fn http_client(timeout: Duration) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .expect("Error building HTTP client")
+}
 pub fn calculate_next_block_base_fee(
     gas_used: U256,
     gas_limit: U256,
@@ -70,55 +81,44 @@ pub fn calculate_next_block_base_fee(
     let seed = rand::thread_rng().gen_range(0..9);
     new_base_fee + seed
 }
-pub async fn get_uniswap_v2_reserves(
-    https_url: String,
+pub async fn get_uniswap_v2_reserves<P: JsonRpcClient + 'static>(
+    client: Arc<Provider<P>>,
     pools: Vec<Pool>,
 ) -> Result<HashMap<H160, Reserve>> {
-    let client = Provider::<Http>::try_from(https_url).unwrap();
-    let client = Arc::new(client);
-    let abi = ABI::new();
     // 创建多重调用实例
     let mut multicall = Multicall::new(client.clone(), None).await?;
     //
     for pool in &pools {
-        let contract = Contract::<Provider<Http>>::new(
-            pool.address,
-            abi.uniswap_v2_pair.clone(),
-            client.clone(),
-        );
-        let call = contract.method::<_, H256>("getReserves", ())?;
-        multicall.add_call(call, false);
+        let contract = UniswapV2Pair::new(pool.address, client.clone());
+        multicall.add_call(contract.get_reserves(), false);
     }
     //  执行批量调用 拿到结果
     let result = multicall.call_raw().await?;
     let mut reserves = HashMap::new();
     //  处理返回结果
-    for i in 0..result.len() {
-        let pool = &pools[i];
-        let reserve = result[i].clone();
+    for (pool, reserve) in pools.iter().zip(result) {
         // 解析返回数据
-        match reserve.unwrap() {
-            abi::Token::Tuple(response) => {
-                let reserve_data = Reserve {
-                    reserve0: response[0].clone().into_uint().unwrap(),
-                    reserve1: response[1].clone().into_uint().unwrap(),
-                };
-                reserves.insert(pool.address.clone(), reserve_data);
-            }
-            _ => {}
+        if let Ok(abi::Token::Tuple(response)) = reserve {
+            let reserve0 = response.first().and_then(|token| token.clone().into_uint());
+            let reserve1 = response.get(1).and_then(|token| token.clone().into_uint());
+            let (reserve0, reserve1) = match (reserve0, reserve1) {
+                (Some(reserve0), Some(reserve1)) => (reserve0, reserve1),
+                _ => return Err(anyhow!("Invalid getReserves result for {:?}", pool.address)),
+            };
+            reserves.insert(pool.address, Reserve { reserve0, reserve1 });
         }
     }
     Ok(reserves)
 }
-// 批量获取 Uniswap V2 池子储备量
-pub async fn batch_get_uniswap_v2_reserves(
-    https_url: String,
+// 批量获取 Uniswap V2 池子储备量, 任何一批失败都返回错误
+pub async fn batch_get_uniswap_v2_reserves<P: JsonRpcClient + 'static>(
+    provider: Arc<Provider<P>>,
     pools: Vec<Pool>,
-) -> HashMap<H160, Reserve> {
+) -> Result<HashMap<H160, Reserve>> {
     let start_time = Instant::now();
     let pools_cnt = pools.len();
     // 使用 ceil() 向上取整确保不遗漏
-    let batch = ((pools_cnt / 250) as f32).ceil(); // 计算需要多少批次（每250个一批）
+    let batch = (pools_cnt as f32 / 250.0).ceil(); // 计算需要多少批次（每250个一批）
 
     // usize无符号整数类型，大小取决于系统架构
     let pools_per_batch = ((pools_cnt as f32) / batch).ceil() as usize; // 每批的池子数量
@@ -127,24 +127,24 @@ pub async fn batch_get_uniswap_v2_reserves(
         let start_idx = i * pools_per_batch;
         let end_idx = std::cmp::min(start_idx + pools_per_batch, pools_cnt);
         let handle = tokio::spawn(get_uniswap_v2_reserves(
-            https_url.clone(),
+            provider.clone(),
             pools[start_idx..end_idx].to_vec(),
         ));
         handles.push(handle);
     }
     let mut reserves: HashMap<H160, Reserve> = HashMap::new();
     for handle in handles {
-        let result = handle.await.unwrap();
-        reserves.extend(result.unwrap());
+        reserves.extend(handle.await??);
     }
     info!(
         "Batch reserves call took: {} seconds",
         start_time.elapsed().as_secs()
     );
-    reserves
+    Ok(reserves)
 }
-pub async fn get_touched_pool_reserves(
-    provider: Arc<Provider<Ws>>,
+// provider 可以是 Provider<Ws>, 测试里用 mock::FixtureTransport
+pub async fn get_touched_pool_reserves<P: JsonRpcClient>(
+    provider: Arc<Provider<P>>,
     block_number: U64,
 ) -> Result<HashMap<H160, Reserve>> {
     //对应 reserve0 和 reserve1
@@ -156,11 +156,16 @@ pub async fn get_touched_pool_reserves(
         .event(sync_event);
     // 获取日志
     let logs = provider.get_logs(&event_filter).await?;
+    Ok(reserves_from_sync_logs(&logs))
+}
+
+// 从 Sync 日志里取出每个池子在区块里最后的储备量, 回测时也用这个函数
+pub fn reserves_from_sync_logs(logs: &[Log]) -> HashMap<H160, Reserve> {
     let mut tx_idx = HashMap::new(); // 存储每个池子最新的交易索引
     let mut reserves = HashMap::new(); // 存储每个池子的最新储备量
 
     //
-    for log in &logs {
+    for log in logs {
         // 解码日志数据
         let decoded = decode(&[ParamType::Uint(256), ParamType::Uint(256)], &log.data);
         match decoded {
@@ -190,5 +195,82 @@ pub async fn get_touched_pool_reserves(
             Err(_) => {}
         }
     }
-    Ok(reserves)
+    reserves
+}
+
+#[cfg(test)]
+mod utils_tests {
+    use super::*;
+    use crate::mock::{FixtureTransport, Fixtures};
+    use crate::pools::DexVariant;
+    use serde_json::json;
+
+    fn fixtures() -> Fixtures {
+        Fixtures::from_json(include_str!("../fixtures/uniswap_v2.json")).unwrap()
+    }
+
+    fn pool(address: u64) -> Pool {
+        Pool {
+            address: H160::from_low_u64_be(address),
+            version: DexVariant::UniswapV2,
+            token0: H160::from_low_u64_be(1),
+            token1: H160::from_low_u64_be(2),
+            decimals0: 6,
+            decimals1: 18,
+            fee: 300,
+            router: H160::zero(),
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_reserves_from_multicall() {
+        let fixtures = fixtures();
+        let provider = Arc::new(Provider::new(FixtureTransport::new(fixtures.clone())));
+        let reserves = batch_get_uniswap_v2_reserves(provider, vec![pool(0xa01), pool(0xa02)])
+            .await
+            .unwrap();
+
+        assert_eq!(reserves.len(), 2);
+        let reserve = &reserves[&H160::from_low_u64_be(0xa01)];
+        assert_eq!(reserve.reserve0, U256::from(2_000_000) * U256::exp10(6));
+        assert_eq!(reserve.reserve1, U256::from(1_000) * U256::exp10(18));
+        let reserve = &reserves[&H160::from_low_u64_be(0xa02)];
+        assert_eq!(reserve.reserve0, U256::from(500_000) * U256::exp10(18));
+        // 两个池子在一次 multicall 里
+        assert_eq!(fixtures.requests("eth_call").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn batch_reserves_return_rpc_errors() {
+        let fixtures = fixtures();
+        fixtures.set(
+            "eth_call",
+            json!({"error": {"code": -32000, "message": "header not found"}}),
+        );
+        let provider = Arc::new(Provider::new(FixtureTransport::new(fixtures)));
+        assert!(
+            batch_get_uniswap_v2_reserves(provider, vec![pool(0xa01), pool(0xa02)])
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn touched_reserves_use_last_sync() {
+        let fixtures = fixtures();
+        let provider = Arc::new(Provider::new(FixtureTransport::new(fixtures.clone())));
+        let reserves = get_touched_pool_reserves(provider, U64::from(18_000_000))
+            .await
+            .unwrap();
+
+        assert_eq!(reserves.len(), 2);
+        // 0xa01 在区块里 Sync 了两次, 取交易索引大的那次
+        let reserve = &reserves[&H160::from_low_u64_be(0xa01)];
+        assert_eq!(reserve.reserve0, U256::from(2_000_000) * U256::exp10(6));
+        assert_eq!(reserve.reserve1, U256::from(1_000) * U256::exp10(18));
+
+        let filter = &fixtures.requests("eth_getLogs")[0][0];
+        assert_eq!(filter["fromBlock"], "0x112a880");
+        assert_eq!(filter["toBlock"], "0x112a880");
+    }
 }